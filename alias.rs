@@ -0,0 +1,63 @@
+// Short names for tasks and their arguments, so a team can agree on them in the config
+// instead of in every make.py:
+//
+//     [alias]
+//     t = "test --fast"
+//     b = "build"
+//
+// 'mk t -k foo' then runs 'mk test --fast -k foo'. The first word of an alias may be another
+// alias, or the name of the alias itself, e.g. test = "test --fast" to change what 'mk test'
+// runs without touching make.py.
+
+use std::process;
+
+use config::Config;
+use facade;
+
+// Names of the aliases, sorted.
+pub fn names(config: &Config) -> Vec<String> {
+    config
+        .values()
+        .into_iter()
+        .filter_map(|(key, _)| key.strip_prefix("alias.").map(|n| n.to_string()))
+        .collect()
+}
+
+// The words alias 'name' stands for. None if it isn't an alias.
+pub fn words_of(config: &Config, name: &str) -> Option<Vec<String>> {
+    let key = format!("alias.{}", name);
+    let words = facade::words(&key, config.get_value(&key)?);
+    if words.is_empty() {
+        eprintln!("mk: '{}' is empty.", key);
+        process::exit(1);
+    }
+    Some(words)
+}
+
+// 'args' with the alias in front expanded, as often as it names another alias.
+pub fn expand(config: &Config, args: &[String]) -> Vec<String> {
+    let mut args = args.to_vec();
+    let mut seen: Vec<String> = Vec::new();
+    while let Some(name) = args.first().cloned() {
+        // An alias that names itself runs the task of that name.
+        if seen.last() == Some(&name) {
+            break;
+        }
+        if seen.contains(&name) {
+            eprintln!(
+                "mk: The aliases {} -> {} refer to each other.",
+                seen.join(" -> "),
+                name
+            );
+            process::exit(1);
+        }
+        let words = match words_of(config, &name) {
+            Some(words) => words,
+            None => break,
+        };
+        crate::explain(&format!("'{}' is an alias of '{}'", name, words.join(" ")));
+        seen.push(name);
+        args.splice(..1, words);
+    }
+    args
+}
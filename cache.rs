@@ -0,0 +1,345 @@
+// Storage for the venv cache, a TOML file with a binary index next to it, see
+// cache_index.rs. Callers only use the methods of CacheStore, not the format.
+//
+// Machines often have several mk versions, e.g. when teams upgrade at different times, and
+// they all share the cache. So formats only ever grow: new fields are added to entries,
+// older versions ignore what they don't know and never remove it. A change older versions
+// couldn't read has to go to a new file instead.
+
+use std::cell::RefCell;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use cache_index;
+use cache_index::Index;
+use config;
+use toml::{self, Value};
+
+pub fn open(cache_dir: &str) -> CacheStore {
+    CacheStore {
+        path: format!("{}/cache.toml", cache_dir),
+        legacy_path: format!("{}/cache", cache_dir),
+        index_path: format!("{}/cache.index", cache_dir),
+        index: RefCell::new(None),
+        loaded: RefCell::new(None),
+        sources: RefCell::new([[0; 3]; 2]),
+    }
+}
+
+// The cache file, 'cache.toml', with one inline table per venv:
+//
+//     version = 1
+//     venvs = [
+//         { key = "/home/me/app", venv = "/home/me/app/.venv", stamp = "...", updated_at = 1700000000 },
+//     ]
+//
+// It's rewritten atomically on every change, with one entry per key. 'version' is only
+// raised for changes older versions can't read; fields can be added without it, and the
+// fields of newer versions are kept when an older one rewrites an entry.
+const FORMAT_VERSION: i64 = 1;
+
+// The fields of an entry, in the order they are written.
+type Entry = Vec<(String, Value)>;
+
+pub struct CacheStore {
+    path: String,
+    // The '<key> <venv> <stamp>' lines older mk versions still write to 'cache'. Entries
+    // that aren't in 'cache.toml' are read from there.
+    legacy_path: String,
+    // The index of both, see cache_index.rs, and whether it's up to date, once looked at.
+    index_path: String,
+    index: RefCell<Option<Option<Index>>>,
+    // What was read, so a run reads the files only once, and the stamps of the files it was
+    // read from or written to, for the index.
+    loaded: RefCell<Option<Vec<Entry>>>,
+    sources: RefCell<[cache_index::Stamp; 2]>,
+}
+
+// The content of 'path' and the stamp of the file it was read from, which stays the same
+// while another mk renames a new file into place.
+fn read_stamped(path: &str) -> (String, cache_index::Stamp) {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return (String::new(), cache_index::stamp(None)),
+    };
+    let stamp = cache_index::stamp(file.metadata().ok().as_ref());
+    let mut content = String::new();
+    let _ = file.read_to_string(&mut content);
+    (content, stamp)
+}
+
+impl CacheStore {
+    // All entries, each a table with at least 'key' and 'venv'.
+    fn load(&self) -> Vec<Entry> {
+        if let Some(entries) = &*self.loaded.borrow() {
+            return entries.clone();
+        }
+
+        let (text, stamp) = read_stamped(&self.path);
+        self.sources.borrow_mut()[0] = stamp;
+        let mut entries = match parse_entries(&text) {
+            Ok(entries) => entries,
+            Err(e) => {
+                crate::warn(&format!("Ignoring invalid cache file {}: {}", self.path, e));
+                Vec::new()
+            }
+        };
+
+        for entry in self.load_legacy() {
+            if find(&entries, field(&entry, "key").unwrap_or("")).is_none() {
+                entries.push(entry);
+            }
+        }
+
+        *self.loaded.borrow_mut() = Some(entries.clone());
+        entries
+    }
+
+    // Later lines win.
+    fn load_legacy(&self) -> Vec<Entry> {
+        let (content, stamp) = read_stamped(&self.legacy_path);
+        self.sources.borrow_mut()[1] = stamp;
+        let mut entries: Vec<Entry> = Vec::new();
+
+        for line in content.lines() {
+            let (key, venv, stamp) = match parse_legacy_line(line) {
+                Some(fields) => fields,
+                None => continue,
+            };
+            let mut entry = vec![
+                ("key".to_string(), Value::String(key.to_string())),
+                ("venv".to_string(), Value::String(venv.to_string())),
+            ];
+            if let Some(stamp) = stamp {
+                entry.push(("stamp".to_string(), Value::String(stamp.to_string())));
+            }
+            entries.retain(|e| field(e, "key") != Some(key));
+            entries.push(entry);
+        }
+
+        entries
+    }
+
+    fn save(&self, entries: &[Entry]) {
+        let mut out = format!(
+            "# The venvs mk found, written by mk.\nversion = {}\nvenvs = [\n",
+            FORMAT_VERSION
+        );
+        for entry in entries {
+            out.push_str(&format!("    {},\n", Value::Table(entry.clone())));
+        }
+        out.push_str("]\n");
+
+        *self.loaded.borrow_mut() = Some(entries.to_vec());
+        match config::write_atomic_metadata(Path::new(&self.path), &out) {
+            Ok(written) => {
+                self.sources.borrow_mut()[0] = cache_index::stamp(Some(&written));
+                self.write_index(entries);
+            }
+            Err(e) => eprintln!("mk: Couldn't write to the cache: {}", e),
+        }
+    }
+
+    fn write_index(&self, entries: &[Entry]) {
+        let rows: Vec<(&str, &str, Option<&str>)> = entries
+            .iter()
+            .filter_map(|e| Some((field(e, "key")?, field(e, "venv")?, field(e, "stamp"))))
+            .collect();
+        let sources = *self.sources.borrow();
+        if let Err(e) = cache_index::write(&self.index_path, &sources, &rows) {
+            crate::explain(&format!("not writing the cache index: {}", e));
+        }
+        *self.index.borrow_mut() = None;
+    }
+
+    // The venv and stamp of 'key', from the index if it's up to date.
+    fn lookup(&self, key: &str) -> Option<(String, Option<String>)> {
+        if self.loaded.borrow().is_none() {
+            if self.index.borrow().is_none() {
+                let index = cache_index::open(&self.index_path, &self.path, &self.legacy_path);
+                *self.index.borrow_mut() = Some(index);
+            }
+            if let Some(Some(index)) = &*self.index.borrow() {
+                return index.get(key);
+            }
+
+            // Missing or out of date, e.g. after an older mk wrote the cache.
+            let entries = self.load();
+            if !crate::read_only() {
+                self.write_index(&entries);
+            }
+        }
+
+        let entries = self.load();
+        let entry = find(&entries, key)?;
+        Some((
+            field(entry, "venv")?.to_string(),
+            field(entry, "stamp").map(|s| s.to_string()),
+        ))
+    }
+}
+
+// Whether 'word' is a stamp, see stamp.rs: '-' or a hash, optionally followed by '+' and
+// another hash.
+fn is_stamp(word: &str) -> bool {
+    let is_hash = |part: &str| part.len() == 16 && part.chars().all(|c| c.is_ascii_hexdigit());
+    match word.split_once('+') {
+        Some((spec, files)) => (spec == "-" || is_hash(spec)) && is_hash(files),
+        None => word == "-" || is_hash(word),
+    }
+}
+
+// A '<key> <venv>[ <stamp>]' line of the legacy file. Keys and venvs may contain spaces, so
+// the line is split before the venv, which is an absolute path. If that's ambiguous, the
+// split that leaves an existing dir as the key wins.
+fn parse_legacy_line(line: &str) -> Option<(&str, &str, Option<&str>)> {
+    let (rest, stamp) = match line.rsplit_once(' ') {
+        Some((rest, last)) if is_stamp(last) => (rest, Some(last)),
+        _ => (line, None),
+    };
+    let splits: Vec<(&str, &str)> = rest
+        .match_indices(' ')
+        .map(|(i, _)| (&rest[..i], &rest[i + 1..]))
+        .filter(|(key, venv)| !key.is_empty() && Path::new(venv).is_absolute())
+        .collect();
+    let (key, venv) = splits
+        .iter()
+        .find(|(key, _)| Path::new(key).is_dir())
+        .or_else(|| splits.first())?;
+    Some((key, venv, stamp))
+}
+
+fn parse_entries(text: &str) -> Result<Vec<Entry>, String> {
+    let doc = toml::parse(text)?;
+    let value = |name: &str| doc.entries.iter().find(|e| e.key == name).map(|e| &e.value);
+
+    if let Some(Value::Integer(version)) = value("version") {
+        if *version > FORMAT_VERSION {
+            crate::explain(&format!(
+                "cache format version {} is from a newer mk, reading the fields of version {}",
+                version, FORMAT_VERSION
+            ));
+        }
+    }
+
+    let items = match value("venvs") {
+        Some(Value::Array(items)) => items,
+        Some(_) => return Err("'venvs' must be a list".to_string()),
+        None => return Ok(Vec::new()),
+    };
+
+    Ok(items
+        .iter()
+        .filter_map(|item| match item {
+            Value::Table(entry)
+                if field(entry, "key").is_some() && field(entry, "venv").is_some() =>
+            {
+                Some(entry.clone())
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+fn field<'a>(entry: &'a [(String, Value)], name: &str) -> Option<&'a str> {
+    entry
+        .iter()
+        .find(|(k, _)| k == name)
+        .and_then(|(_, v)| v.as_str())
+}
+
+fn find<'a>(entries: &'a [Entry], key: &str) -> Option<&'a Entry> {
+    entries
+        .iter()
+        .find(|entry| field(entry, "key") == Some(key))
+}
+
+// Set field 'name' of 'entry', in place to keep the order of the fields.
+fn set_field(entry: &mut Entry, name: &str, value: Value) {
+    match entry.iter_mut().find(|(k, _)| k == name) {
+        Some(field) => field.1 = value,
+        None => entry.push((name.to_string(), value)),
+    }
+}
+
+impl CacheStore {
+    // Where the cache lives, for messages.
+    pub fn location(&self) -> String {
+        self.path.clone()
+    }
+
+    // The venv path stored for 'key'.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.lookup(key).map(|(venv, _)| venv)
+    }
+
+    // The stamp stored with the entry for 'key', see stamp.rs.
+    pub fn get_stamp(&self, key: &str) -> Option<String> {
+        self.lookup(key).and_then(|(_, stamp)| stamp)
+    }
+
+    pub fn put(&self, key: &str, venv_path: &str, stamp: &str) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut entries = self.load();
+        let i = match entries.iter().position(|e| field(e, "key") == Some(key)) {
+            Some(i) => i,
+            None => {
+                entries.push(vec![("key".to_string(), Value::String(key.to_string()))]);
+                entries.len() - 1
+            }
+        };
+
+        set_field(
+            &mut entries[i],
+            "venv",
+            Value::String(venv_path.to_string()),
+        );
+        set_field(&mut entries[i], "stamp", Value::String(stamp.to_string()));
+        set_field(&mut entries[i], "updated_at", Value::Integer(now as i64));
+
+        self.save(&entries);
+    }
+
+    pub fn remove(&self, key: &str) {
+        let mut entries = self.load();
+        entries.retain(|entry| field(entry, "key") != Some(key));
+        self.save(&entries);
+
+        // Otherwise the entry would come back from there.
+        let content = match fs::read_to_string(&self.legacy_path) {
+            Ok(content) => content,
+            Err(_) => return,
+        };
+        let key_with_space = format!("{} ", key);
+
+        let kept: String = content
+            .lines()
+            .filter(|line| !line.starts_with(&key_with_space))
+            .map(|line| format!("{}\n", line))
+            .collect();
+
+        match config::write_atomic_metadata(Path::new(&self.legacy_path), &kept) {
+            Ok(written) => {
+                self.sources.borrow_mut()[1] = cache_index::stamp(Some(&written));
+                self.write_index(&entries);
+            }
+            Err(e) => eprintln!("mk: Couldn't write to file: {}", e),
+        }
+    }
+
+    // All keys in the cache, e.g. to find known projects.
+    pub fn keys(&self) -> Vec<String> {
+        self.load()
+            .iter()
+            .filter_map(|entry| field(entry, "key"))
+            .map(|key| key.to_string())
+            .collect()
+    }
+}
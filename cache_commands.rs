@@ -0,0 +1,343 @@
+// 'mk cache list|clear|prune|export|import': look into the venv cache, fix it without
+// editing the file by hand, and move it to other machines.
+//
+//     mk cache list               which project (or git key) maps to which venv
+//     mk cache clear [<dir>...]   remove the entries of the current project or the given dirs
+//     mk cache clear --all        remove all entries
+//     mk cache prune [--dry-run]  remove entries whose project or venv is gone
+//     mk cache export [<file>]    write all entries to a file, or stdout
+//     mk cache import <file> [--map <from>=<to>]...
+//                                 add the entries of an export, e.g. to prime a container
+//
+// Exports are TOML, like the cache file. '--map' replaces a path prefix in keys and venvs,
+// e.g. '--map /home/alice=/home/bob'; the first matching rule wins. Git keys ('cache_key =
+// git') need no mapping, they are the same on every machine.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use cache::CacheStore;
+use config::Config;
+use table;
+use toml::{self, Value};
+
+const USAGE: &str = "Usage: mk cache list | clear [--all | <dir>...] | prune [--dry-run] | \
+                     export [<file>] | import <file> [--map <from>=<to>]...";
+
+// Only raised for changes older versions can't import.
+const EXPORT_VERSION: i64 = 1;
+
+// What's wrong with an entry, None if it looks usable. Git keys have no dir to check.
+fn problem(key: &str, venv_path: &str) -> Option<String> {
+    if !key.starts_with("git:")
+        && !Path::new(key)
+            .join(crate::script_name(Path::new(key)))
+            .exists()
+    {
+        return Some("project gone".to_string());
+    }
+    if !Path::new(&crate::venv_python(venv_path)).exists() {
+        return Some("venv gone".to_string());
+    }
+    None
+}
+
+fn entries(store: &CacheStore) -> Vec<(String, String)> {
+    let mut keys = store.keys();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter_map(|key| store.get(&key).map(|venv| (key, venv)))
+        .collect()
+}
+
+fn list(config: &Config, store: &CacheStore) -> i32 {
+    let entries = entries(store);
+    if entries.is_empty() {
+        println!("The cache ({}) is empty.", store.location());
+        return 0;
+    }
+
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|(key, venv)| match problem(key, venv) {
+            Some(problem) => vec![key.clone(), format!("{} ({})", venv, problem)],
+            None => vec![key.clone(), venv.clone()],
+        })
+        .collect();
+    for line in table::format(&rows, 0, table::max_width(config)) {
+        println!("{}", line);
+    }
+    0
+}
+
+fn clear(
+    args: &[String],
+    store: &CacheStore,
+    project_keys: &[String],
+    keys_of: &dyn Fn(&str) -> Vec<String>,
+) -> i32 {
+    let keys: Vec<String> = match args {
+        [flag] if flag == "--all" => store.keys(),
+        [] if project_keys.is_empty() => {
+            eprintln!("mk: Not inside a project. Give the project dir or use '--all'.");
+            return 1;
+        }
+        [] => project_keys.to_vec(),
+        dirs if dirs.iter().all(|dir| !dir.starts_with('-')) => {
+            dirs.iter().flat_map(|dir| keys_of(dir)).collect()
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let mut removed = 0;
+    for key in keys {
+        if store.get(&key).is_some() {
+            store.remove(&key);
+            println!("removed '{}'", key);
+            removed += 1;
+        }
+    }
+
+    if removed == 0 {
+        println!("no matching cache entries");
+    }
+    0
+}
+
+fn prune(args: &[String], store: &CacheStore) -> i32 {
+    let dry_run = match args {
+        [] => false,
+        [flag] if flag == "--dry-run" || flag == "-n" => true,
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let verb = if dry_run { "would remove" } else { "removed" };
+    let mut removed = 0;
+
+    for (key, venv) in entries(store) {
+        if let Some(problem) = problem(&key, &venv) {
+            if !dry_run {
+                store.remove(&key);
+            }
+            println!("{} '{}' -> {} ({})", verb, key, venv, problem);
+            removed += 1;
+        }
+    }
+
+    if removed == 0 {
+        println!("nothing to prune");
+    }
+    0
+}
+
+fn export(args: &[String], store: &CacheStore) -> i32 {
+    let file = match args {
+        [] => None,
+        [file] if file == "-" => None,
+        [file] if !file.starts_with('-') => Some(file),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let mut out = format!(
+        "# mk cache export, add it with 'mk cache import <file>'.\nversion = {}\nvenvs = [\n",
+        EXPORT_VERSION
+    );
+    let entries = entries(store);
+    for (key, venv) in &entries {
+        let mut entry = vec![
+            ("key".to_string(), Value::String(key.clone())),
+            ("venv".to_string(), Value::String(venv.clone())),
+        ];
+        if let Some(stamp) = store.get_stamp(key) {
+            entry.push(("stamp".to_string(), Value::String(stamp)));
+        }
+        out.push_str(&format!("    {},\n", Value::Table(entry)));
+    }
+    out.push_str("]\n");
+
+    match file {
+        Some(file) => {
+            if let Err(e) = fs::write(file, out) {
+                eprintln!("mk: Cannot write {}: {}", file, e);
+                return 1;
+            }
+            eprintln!("mk: Exported {} entries to {}.", entries.len(), file);
+        }
+        None => print!("{}", out),
+    }
+    0
+}
+
+// Apply the first rule whose prefix matches 'path', at a path boundary.
+fn remap(path: &str, rules: &[(String, String)]) -> String {
+    for (from, to) in rules {
+        if let Some(rest) = path.strip_prefix(from.as_str()) {
+            if rest.is_empty() || rest.starts_with('/') || rest.starts_with('\\') {
+                return format!("{}{}", to, rest);
+            }
+        }
+    }
+    path.to_string()
+}
+
+fn import(args: &[String], store: &CacheStore) -> i32 {
+    let mut file = None;
+    let mut rules = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let rule = match arg.as_str() {
+            "--map" => args.next().map(|rule| rule.as_str()),
+            _ => arg.strip_prefix("--map="),
+        };
+        match rule {
+            Some(rule) => match rule.split_once('=') {
+                Some((from, to)) if !from.is_empty() => rules.push((
+                    from.trim_end_matches('/').to_string(),
+                    to.trim_end_matches('/').to_string(),
+                )),
+                _ => {
+                    eprintln!("mk: Invalid mapping '{}', expected '<from>=<to>'.", rule);
+                    return 2;
+                }
+            },
+            None if file.is_none() && (arg == "-" || !arg.starts_with('-')) => file = Some(arg),
+            None => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+
+    let file = match file {
+        Some(file) => file,
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let mut text = String::new();
+    let read = if file == "-" {
+        io::stdin().read_to_string(&mut text).map(|_| ())
+    } else {
+        fs::read_to_string(file).map(|t| text = t)
+    };
+    if let Err(e) = read {
+        eprintln!("mk: Cannot read {}: {}", file, e);
+        return 1;
+    }
+
+    let doc = match toml::parse(&text) {
+        Ok(doc) => doc,
+        Err(e) => {
+            eprintln!("mk: Invalid cache export {}: {}", file, e);
+            return 1;
+        }
+    };
+    let value = |name: &str| doc.entries.iter().find(|e| e.key == name).map(|e| &e.value);
+
+    match value("version") {
+        Some(Value::Integer(version)) if *version <= EXPORT_VERSION => {}
+        Some(Value::Integer(version)) => {
+            eprintln!(
+                "mk: {} was exported by a newer mk (format version {}), please upgrade mk.",
+                file, version
+            );
+            return 1;
+        }
+        _ => {
+            eprintln!("mk: {} is not an mk cache export.", file);
+            return 1;
+        }
+    }
+
+    let items = match value("venvs") {
+        Some(Value::Array(items)) => items.as_slice(),
+        _ => &[],
+    };
+
+    let mut imported = 0;
+    for item in items {
+        let entry = match item {
+            Value::Table(entry) => entry,
+            _ => continue,
+        };
+        let field = |name: &str| {
+            entry
+                .iter()
+                .find(|(k, _)| k == name)
+                .and_then(|(_, v)| v.as_str())
+        };
+        let (key, venv) = match (field("key"), field("venv")) {
+            (Some(key), Some(venv)) => (key, venv),
+            _ => continue,
+        };
+
+        let key = if key.starts_with("git:") {
+            key.to_string()
+        } else {
+            remap(key, &rules)
+        };
+        let venv = remap(venv, &rules);
+
+        store.put(&key, &venv, field("stamp").unwrap_or(""));
+        if Path::new(&crate::venv_python(&venv)).exists() {
+            println!("imported '{}' -> {}", key, venv);
+        } else {
+            println!("imported '{}' -> {} (venv not there yet)", key, venv);
+        }
+        imported += 1;
+    }
+
+    if imported == 0 {
+        println!("nothing to import");
+    }
+    0
+}
+
+// 'project_keys' are the cache keys of the current project, if mk runs in one. 'keys_of'
+// gives the keys of another project dir.
+pub fn run(
+    args: &[String],
+    config: &Config,
+    store: &CacheStore,
+    project_keys: &[String],
+    keys_of: &dyn Fn(&str) -> Vec<String>,
+) -> i32 {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    if command != "list" && command != "export" && crate::read_only() {
+        eprintln!("mk: Not changing the cache in read-only mode.");
+        return 1;
+    }
+
+    match command {
+        "list" if rest.is_empty() => list(config, store),
+        "clear" => clear(rest, store, project_keys, keys_of),
+        "prune" => prune(rest, store),
+        "export" => export(rest, store),
+        "import" => import(rest, store),
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
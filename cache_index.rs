@@ -0,0 +1,268 @@
+// A binary index of the file cache, 'cache.index', so a warm run finds its entry with a
+// binary search in the mmap'ed index instead of parsing all of 'cache.toml'. With hundreds
+// of projects in the cache, startup then stays as fast as with one.
+//
+// The index is rewritten, atomically, whenever mk writes the cache, and rebuilt when it's
+// missing. It records the size, mtime and inode of the 'cache.toml' and legacy 'cache' files
+// its entries came from, and is only used while they match, so readers need no locks. The
+// stamps are taken from exactly those files: from the temp file before it's renamed into
+// place when mk writes the cache, and from the open file when it reads it. Another mk
+// replacing a file in between leaves a file with another inode, so the index doesn't match
+// it and the cache is parsed as before. So is a cache written by an older mk.
+//
+// Layout, integers little-endian:
+//
+//     header   "MKIX", u32 version, u32 entry count, u32 0,
+//              u64 size, mtime (ns) and inode of 'cache.toml' and of 'cache'
+//     entries  6 x u32 each, sorted by key: offset and length of key, venv and stamp
+//              (offset u32::MAX for no stamp)
+//     strings  UTF-8
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use config;
+
+const MAGIC: &[u8] = b"MKIX";
+const FORMAT_VERSION: u32 = 2;
+const HEADER_LEN: usize = 64;
+const ENTRY_LEN: usize = 24;
+const NO_STAMP: u32 = u32::MAX;
+
+// Size, mtime and inode of a file the index was built from, zeros if it doesn't exist.
+pub type Stamp = [u64; 3];
+
+pub fn stamp(meta: Option<&fs::Metadata>) -> Stamp {
+    let meta = match meta {
+        Some(meta) => meta,
+        None => return [0; 3],
+    };
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    #[cfg(unix)]
+    let inode = std::os::unix::fs::MetadataExt::ino(meta);
+    #[cfg(not(unix))]
+    let inode = 0;
+    [meta.len(), mtime, inode]
+}
+
+fn stamp_of(path: &str) -> Stamp {
+    stamp(fs::metadata(path).ok().as_ref())
+}
+
+#[cfg(unix)]
+mod ffi {
+    use std::os::raw::{c_int, c_long, c_void};
+
+    pub const PROT_READ: c_int = 1;
+    pub const MAP_PRIVATE: c_int = 2;
+    pub const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+    extern "C" {
+        pub fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: c_long,
+        ) -> *mut c_void;
+        pub fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+}
+
+// The bytes of the index file. Mapped on Unix, where the index is replaced by renames, so
+// a mapped file never changes under the reader. Read into memory elsewhere.
+#[cfg(unix)]
+struct Bytes {
+    ptr: *mut std::os::raw::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl Bytes {
+    fn open(file: &File, len: usize) -> io::Result<Bytes> {
+        use std::os::unix::io::AsRawFd;
+
+        let ptr = unsafe {
+            ffi::mmap(
+                std::ptr::null_mut(),
+                len,
+                ffi::PROT_READ,
+                ffi::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == ffi::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Bytes { ptr, len })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Bytes {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct Bytes {
+    data: Vec<u8>,
+}
+
+#[cfg(not(unix))]
+impl Bytes {
+    fn open(file: &File, len: usize) -> io::Result<Bytes> {
+        use std::io::Read;
+
+        let mut data = Vec::with_capacity(len);
+        let mut file = file;
+        file.read_to_end(&mut data)?;
+        Ok(Bytes { data })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+pub struct Index {
+    bytes: Bytes,
+    count: usize,
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    let b = bytes.get(at..at + 4)?;
+    Some(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> Option<u64> {
+    let b = bytes.get(at..at + 8)?;
+    let mut buf = [0; 8];
+    buf.copy_from_slice(b);
+    Some(u64::from_le_bytes(buf))
+}
+
+// Open the index at 'path', None if it's missing, invalid or out of date with the cache
+// files 'cache_path' and 'legacy_path'.
+pub fn open(path: &str, cache_path: &str, legacy_path: &str) -> Option<Index> {
+    let file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len() as usize;
+    if len < HEADER_LEN {
+        return None;
+    }
+    let bytes = Bytes::open(&file, len).ok()?;
+
+    let count = {
+        let b = bytes.as_slice();
+        if &b[..4] != MAGIC || u32_at(b, 4)? != FORMAT_VERSION {
+            return None;
+        }
+        let stamps = [stamp_of(cache_path), stamp_of(legacy_path)];
+        for (i, n) in stamps.iter().flatten().enumerate() {
+            if u64_at(b, 16 + i * 8)? != *n {
+                return None;
+            }
+        }
+        let count = u32_at(b, 8)? as usize;
+        if HEADER_LEN + count * ENTRY_LEN > len {
+            return None;
+        }
+        count
+    };
+
+    Some(Index { bytes, count })
+}
+
+impl Index {
+    // String 'n' (0 key, 1 venv, 2 stamp) of entry 'i'.
+    fn string(&self, i: usize, n: usize) -> Option<&str> {
+        let b = self.bytes.as_slice();
+        let at = HEADER_LEN + i * ENTRY_LEN + n * 8;
+        let offset = u32_at(b, at)?;
+        if offset == NO_STAMP {
+            return None;
+        }
+        let len = u32_at(b, at + 4)? as usize;
+        let start = offset as usize;
+        std::str::from_utf8(b.get(start..start + len)?).ok()
+    }
+
+    // The venv and stamp of 'key'. None if there is no entry, as far as the index knows.
+    pub fn get(&self, key: &str) -> Option<(String, Option<String>)> {
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.string(mid, 0)?.cmp(key) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => {
+                    let venv = self.string(mid, 1)?.to_string();
+                    let stamp = self.string(mid, 2).map(|s| s.to_string());
+                    return Some((venv, stamp));
+                }
+            }
+        }
+        None
+    }
+}
+
+// Write the index of 'entries' (key, venv, stamp), read from or written to the cache files
+// with 'sources', the stamps of 'cache.toml' and 'cache'.
+pub fn write(
+    path: &str,
+    sources: &[Stamp; 2],
+    entries: &[(&str, &str, Option<&str>)],
+) -> Result<(), String> {
+    let mut entries = entries.to_vec();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries.dedup_by(|a, b| a.0 == b.0);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    for n in &[FORMAT_VERSION, entries.len() as u32, 0] {
+        out.extend_from_slice(&n.to_le_bytes());
+    }
+    for n in sources.iter().flatten() {
+        out.extend_from_slice(&n.to_le_bytes());
+    }
+
+    let mut strings: Vec<u8> = Vec::new();
+    let strings_start = HEADER_LEN + entries.len() * ENTRY_LEN;
+    let mut add = |out: &mut Vec<u8>, s: Option<&str>| {
+        let (offset, len) = match s {
+            Some(s) => {
+                let offset = strings_start + strings.len();
+                strings.extend_from_slice(s.as_bytes());
+                (offset as u32, s.len() as u32)
+            }
+            None => (NO_STAMP, 0),
+        };
+        out.extend_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(&len.to_le_bytes());
+    };
+    for (key, venv, stamp) in &entries {
+        add(&mut out, Some(key));
+        add(&mut out, Some(venv));
+        add(&mut out, *stamp);
+    }
+    out.extend_from_slice(&strings);
+
+    config::write_atomic(Path::new(path), &out)
+}
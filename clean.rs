@@ -0,0 +1,87 @@
+// 'mk clean [--dry-run] [--cache]': remove what mk left in the project, i.e. the managed
+// dirs in '.mk' and, with '--cache', the project's cache entries.
+
+use std::fs;
+use std::path::Path;
+
+use cache::CacheStore;
+use workspace;
+
+// Dirs in '.mk' that only mk writes to.
+const MANAGED: &[(&str, &str)] = &[
+    ("tmp", "task temp dirs"),
+    ("logs", "run logs"),
+    ("pids", "detached task records"),
+    ("completion", "cached task names"),
+];
+
+pub fn run(args: &[String], project_root: &str, store: &CacheStore, cache_keys: &[String]) -> i32 {
+    let mut dry_run = false;
+    let mut cache = false;
+
+    for arg in args {
+        match arg.as_str() {
+            "--dry-run" | "-n" => dry_run = true,
+            "--cache" => cache = true,
+            _ => {
+                eprintln!("Usage: mk clean [--dry-run] [--cache]");
+                return 2;
+            }
+        }
+    }
+
+    let verb = if dry_run { "would remove" } else { "removed" };
+    let mut code = 0;
+    let mut removed = 0;
+
+    for (name, what) in MANAGED {
+        let dir = workspace::dir(project_root).join(name);
+        if !dir.exists() {
+            continue;
+        }
+
+        if !dry_run {
+            if let Err(e) = fs::remove_dir_all(&dir) {
+                eprintln!("mk: Cannot remove {}: {}", dir.display(), e);
+                code = 1;
+                continue;
+            }
+        }
+        println!("{} {} ({})", verb, dir.display(), what);
+        removed += 1;
+    }
+
+    if cache {
+        for key in cache_keys {
+            if store.get(key).is_none() {
+                continue;
+            }
+            if !dry_run {
+                store.remove(key);
+            }
+            println!("{} cache entry '{}' from {}", verb, key, store.location());
+            removed += 1;
+        }
+    }
+
+    // Don't leave an empty '.mk' behind.
+    let dir = workspace::dir(project_root);
+    if !dry_run && is_empty_but_gitignore(&dir) {
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    if removed == 0 {
+        println!("nothing to clean");
+    }
+
+    code
+}
+
+fn is_empty_but_gitignore(dir: &Path) -> bool {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .all(|e| e.file_name() == ".gitignore"),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,394 @@
+// Command line handling. Everything mk doesn't recognize is passed on to make.py
+// untouched, so mk's own options and commands must come first. A task of the project
+// with the name of an mk command runs instead of the command, see 'command_word'; '--'
+// runs a task in any case, e.g. 'mk -- explain'.
+
+use std::process;
+
+pub enum Command {
+    // Run make.py with the remaining args.
+    Run,
+    // Print how the venv would be resolved, without running make.py or writing the cache.
+    Explain,
+    // Run tasks in sequence, feeding the stdout of each into the next.
+    Pipe,
+    // Print the project root, e.g. for 'cd $(mk root)'.
+    Root,
+    // Create a minimal 'make.py'.
+    Init,
+    // Create a 'make.py' from a Makefile or justfile.
+    Migrate,
+    // Change settings in the project or user config.
+    Config,
+    // List, clear or prune entries of the venv cache.
+    Cache,
+    // Add a dependency for tasks to the project's dev group.
+    AddTaskDep,
+    // Remove mk's files from the project.
+    Clean,
+    // Check for permission problems and other common failure modes.
+    Doctor,
+    // Run a server task and rerun tasks and restart it when files change.
+    Dev,
+    // Run a task and run it again whenever files change.
+    Watch,
+    // List the detached tasks, e.g. dev servers.
+    Ps,
+    // Show how mk changes the environment of tasks.
+    Env,
+    // Install git hooks that run mk tasks, or run them.
+    Hooks,
+    // Register the project's venv as a Jupyter kernel.
+    Kernel,
+    // Run tasks on request from a local HTTP server, e.g. for editor plugins.
+    Listen,
+    // Print the tools and tasks of the project, e.g. as markdown for READMEs.
+    Status,
+    // Print a short project status for shell prompts.
+    Prompt,
+    // Print the tasks of make.py and the config. Also what a bare 'mk' does.
+    List,
+    // Print a shell completion script.
+    Completions,
+    // Print mk's messages, to change their wording.
+    Messages,
+    // Print completion candidates for the given words, used by shell completion scripts.
+    Complete,
+    // Print version and build information.
+    PrintInstallInfo,
+    // Show a flight recording.
+    Report,
+    // Print the interpreter or venv a run would use, e.g. to point an editor at it.
+    Which,
+    WhichVenv,
+}
+
+pub struct Cli {
+    pub command: Command,
+    pub args: Vec<String>,
+    // The word the command was given as, e.g. 'clean'. mk runs the project's task of that
+    // name instead, if it has one; see 'run_as_task'.
+    pub command_word: Option<String>,
+    // No command or task was given: the 'default_task' runs, or else the tasks are listed.
+    pub bare: bool,
+    // Run as if mk was started in this dir.
+    pub directory: Option<String>,
+    // The workspace member to run in, '@<name>' or a dir, see monorepo.rs.
+    pub project: Option<String>,
+    // The task script, instead of 'make.py'.
+    pub file: Option<String>,
+    // Write Prometheus metrics of the run to this file.
+    pub metrics_file: Option<String>,
+    // Copy the output of make.py to this file.
+    pub log_file: Option<String>,
+    // Read this file instead of the project's '.env'.
+    pub env_file: Option<String>,
+    // Variables of '-e KEY=VALUE', set for the task of this run only.
+    pub env: Vec<(String, String)>,
+    // Record the run to this file, or re-run the one recorded in it.
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    // Record the phases of the run and the resources of the task to this file.
+    pub flight_recorder: Option<String>,
+    // Sync the venv before running, with these dependency groups and extras.
+    pub sync: bool,
+    pub groups: Vec<String>,
+    pub extras: Vec<String>,
+    // Passed on to uv or poetry when mk runs them, so nothing is resolved or synced
+    // behind the caller's back.
+    pub frozen: bool,
+    pub locked: bool,
+    pub no_sync: bool,
+    // Print a shell snippet that runs what mk would run, instead of running it.
+    pub print_equivalent: bool,
+    // Print the interpreter, args and environment mk would run the task with, instead of
+    // running it.
+    pub dry_run: bool,
+    // Run the task under a pseudo-terminal.
+    pub pty: bool,
+    // Don't write the cache or other state.
+    pub read_only: bool,
+    // Treat warnings as errors.
+    pub strict: bool,
+    // Narrate how the project, venv and command were resolved.
+    pub verbose: bool,
+    // Don't wrap mk's tables to the terminal width.
+    pub wide: bool,
+    // Summarize the slowest imports of make.py after running it.
+    pub why_slow: bool,
+    // Run each arg as a task, all at the same time.
+    pub parallel: bool,
+    // Extra interpreter args, inserted before 'make.py'.
+    pub python_args: Vec<String>,
+}
+
+// Take the value of option 'name' if it's the next arg, given as '--name value' or
+// '--name=value'.
+fn take_value(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let arg = args.first()?.clone();
+
+    if arg == name {
+        if args.len() < 2 {
+            eprintln!("mk: Option '{}' needs a value.", name);
+            process::exit(2);
+        }
+        args.remove(0);
+        return Some(args.remove(0));
+    }
+
+    let value = arg.strip_prefix(&format!("{}=", name))?.to_string();
+    args.remove(0);
+    Some(value)
+}
+
+pub fn parse(mut args: Vec<String>) -> Cli {
+    let mut directory = None;
+    let mut project = None;
+    let mut file = None;
+    let mut metrics_file = None;
+    let mut log_file = None;
+    let mut env_file = None;
+    let mut env = Vec::new();
+    let mut record = None;
+    let mut replay = None;
+    let mut flight_recorder = None;
+    let mut python_args = Vec::new();
+    let mut sync = false;
+    let mut print_equivalent = false;
+    let mut dry_run = false;
+    let mut why_slow = false;
+    let mut parallel = false;
+    let mut pty = false;
+    let mut read_only = false;
+    let mut strict = false;
+    let mut verbose = false;
+    let mut wide = false;
+    let mut frozen = false;
+    let mut locked = false;
+    let mut no_sync = false;
+    let mut groups = Vec::new();
+    let mut extras = Vec::new();
+
+    loop {
+        if let Some(value) = take_value(&mut args, "-C") {
+            directory = Some(value);
+        } else if let Some(value) = take_value(&mut args, "--project") {
+            project = Some(value);
+        } else if args
+            .first()
+            .is_some_and(|a| a.len() > 1 && a.starts_with('@'))
+        {
+            project = Some(args.remove(0));
+        } else if let Some(value) =
+            take_value(&mut args, "-f").or_else(|| take_value(&mut args, "--file"))
+        {
+            file = Some(value);
+        } else if let Some(value) = take_value(&mut args, "--metrics-file") {
+            metrics_file = Some(value);
+        } else if let Some(value) = take_value(&mut args, "--log-file") {
+            log_file = Some(value);
+        } else if let Some(value) = take_value(&mut args, "--env-file") {
+            env_file = Some(value);
+        } else if let Some(value) =
+            take_value(&mut args, "-e").or_else(|| take_value(&mut args, "--env"))
+        {
+            match value.split_once('=') {
+                Some((name, value)) if !name.is_empty() && !name.contains('\0') => {
+                    env.push((name.to_string(), value.to_string()))
+                }
+                _ => {
+                    eprintln!(
+                        "mk: Option '-e' needs KEY=VALUE, e.g. 'mk -e DEBUG=1 test', not '{}'.",
+                        value
+                    );
+                    process::exit(2);
+                }
+            }
+        } else if let Some(value) = take_value(&mut args, "--record") {
+            record = Some(value);
+        } else if let Some(value) = take_value(&mut args, "--replay") {
+            replay = Some(value);
+        } else if let Some(value) = take_value(&mut args, "--flight-recorder") {
+            flight_recorder = Some(value);
+        } else if let Some(value) = take_value(&mut args, "--python-arg") {
+            python_args.push(value);
+        } else if let Some(value) = take_value(&mut args, "--group") {
+            groups.push(value);
+        } else if let Some(value) = take_value(&mut args, "--extra") {
+            extras.push(value);
+        } else if args.first().map(|a| a.as_str()) == Some("--sync") {
+            args.remove(0);
+            sync = true;
+        } else if args.first().map(|a| a.as_str()) == Some("--frozen") {
+            args.remove(0);
+            frozen = true;
+        } else if args.first().map(|a| a.as_str()) == Some("--locked") {
+            args.remove(0);
+            locked = true;
+        } else if args.first().map(|a| a.as_str()) == Some("--no-sync") {
+            args.remove(0);
+            no_sync = true;
+        } else if args.first().map(|a| a.as_str()) == Some("--print-equivalent") {
+            args.remove(0);
+            print_equivalent = true;
+        } else if args.first().map(|a| a.as_str()) == Some("--dry-run") {
+            args.remove(0);
+            dry_run = true;
+        } else if args.first().map(|a| a.as_str()) == Some("--why-slow") {
+            args.remove(0);
+            why_slow = true;
+        } else if args.first().map(|a| a.as_str()) == Some("--parallel") {
+            args.remove(0);
+            parallel = true;
+        } else if args.first().map(|a| a.as_str()) == Some("--pty") {
+            args.remove(0);
+            pty = true;
+        } else if args.first().map(|a| a.as_str()) == Some("--read-only") {
+            args.remove(0);
+            read_only = true;
+        } else if args.first().map(|a| a.as_str()) == Some("--strict") {
+            args.remove(0);
+            strict = true;
+        } else if matches!(
+            args.first().map(|a| a.as_str()),
+            Some("-v") | Some("--verbose")
+        ) {
+            args.remove(0);
+            verbose = true;
+        } else if matches!(
+            args.first().map(|a| a.as_str()),
+            Some("--wide") | Some("--no-truncate")
+        ) {
+            args.remove(0);
+            wide = true;
+        } else {
+            break;
+        }
+    }
+
+    let sync = sync || !groups.is_empty() || !extras.is_empty();
+
+    if frozen && locked {
+        eprintln!("mk: Options '--frozen' and '--locked' can't be combined.");
+        process::exit(2);
+    }
+    if no_sync && sync {
+        eprintln!(
+            "mk: Option '--no-sync' can't be combined with '--sync', '--group' or '--extra'."
+        );
+        process::exit(2);
+    }
+
+    if parallel && args.is_empty() {
+        eprintln!("mk: Option '--parallel' needs tasks, e.g. 'mk --parallel lint \"test -x\"'.");
+        process::exit(2);
+    }
+
+    let bare = args.is_empty();
+    let command = match args.first().map(|a| a.as_str()) {
+        Some("--") => Some(Command::Run),
+        Some("explain") => Some(Command::Explain),
+        Some("pipe") => Some(Command::Pipe),
+        Some("root") => Some(Command::Root),
+        Some("init") => Some(Command::Init),
+        Some("cache") => Some(Command::Cache),
+        Some("add-task-dep") => Some(Command::AddTaskDep),
+        Some("clean") => Some(Command::Clean),
+        Some("config") => Some(Command::Config),
+        Some("migrate") => Some(Command::Migrate),
+        Some("doctor") => Some(Command::Doctor),
+        Some("env") => Some(Command::Env),
+        Some("hooks") => Some(Command::Hooks),
+        Some("kernel") => Some(Command::Kernel),
+        Some("dev") => Some(Command::Dev),
+        Some("listen") => Some(Command::Listen),
+        Some("prompt") => Some(Command::Prompt),
+        Some("ps") => Some(Command::Ps),
+        Some("status") => Some(Command::Status),
+        Some("completions") => Some(Command::Completions),
+        Some("messages") => Some(Command::Messages),
+        Some("report") => Some(Command::Report),
+        Some("__complete") => Some(Command::Complete),
+        Some("--print-install-info") => Some(Command::PrintInstallInfo),
+        Some("--which") => Some(Command::Which),
+        Some("--which-venv") => Some(Command::WhichVenv),
+        Some("--watch") => Some(Command::Watch),
+        Some("--list") => Some(Command::List),
+        None => Some(Command::List),
+        _ => None,
+    };
+
+    let (command, command_word) = match command {
+        Some(command) => {
+            let word = match args.first() {
+                Some(word) if !word.starts_with('-') && word != "__complete" => Some(word.clone()),
+                _ => None,
+            };
+            if !args.is_empty() {
+                args.remove(0);
+            }
+            (command, word)
+        }
+        None => (Command::Run, None),
+    };
+
+    let cli = Cli {
+        command,
+        args,
+        command_word,
+        bare,
+        directory,
+        project,
+        file,
+        metrics_file,
+        log_file,
+        env_file,
+        env,
+        record,
+        replay,
+        flight_recorder,
+        sync,
+        groups,
+        extras,
+        frozen,
+        locked,
+        no_sync,
+        print_equivalent,
+        dry_run,
+        why_slow,
+        parallel,
+        pty,
+        read_only,
+        strict,
+        verbose,
+        wide,
+        python_args,
+    };
+    // Commands given as a word may still turn out to be tasks.
+    if cli.command_word.is_none() {
+        check_task_options(&cli);
+    }
+    cli
+}
+
+// Run the task 'command_word' instead of the command.
+pub fn run_as_task(cli: &mut Cli) {
+    if let Some(word) = cli.command_word.take() {
+        cli.command = Command::Run;
+        cli.args.insert(0, word);
+    }
+}
+
+// Exit if options for tasks are given with a command.
+pub fn check_task_options(cli: &Cli) {
+    // A bare 'mk' can still become a task, the 'default_task'.
+    let task = cli.bare || matches!(cli.command, Command::Run | Command::Pipe);
+    if cli.dry_run && !task {
+        eprintln!("mk: Option '--dry-run' works with tasks only.");
+        process::exit(2);
+    }
+    if cli.flight_recorder.is_some() && !task {
+        eprintln!("mk: Option '--flight-recorder' works with tasks only.");
+        process::exit(2);
+    }
+}
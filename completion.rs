@@ -0,0 +1,391 @@
+// Shell completion, driven by the hidden 'mk __complete <words...>' command, where the last
+// word is the one being completed. Scripts that speak the 'completion' protocol complete
+// their own args: mk runs them with 'MK_COMPLETE=<version>' and the words, and they print
+// one candidate per line. For all other scripts mk falls back to heuristics, which can be
+// tuned per task in the config:
+//
+//     [task.lint]
+//     complete = "files"   # or "dirs", "none", or a list of words
+//
+// 'mk completions bash|zsh|fish' prints the scripts that hook this into the shell. Task
+// names are asked from make.py like for 'mk --list' and cached in '.mk/completion' until
+// make.py changes, so completing them stays fast.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::UNIX_EPOCH;
+
+use alias;
+use config::Config;
+use facade;
+use listing;
+use monorepo;
+use protocol;
+use tasks;
+use workspace;
+
+const BASH: &str = r#"_mk() {
+    mapfile -t COMPREPLY < <(mk __complete "${COMP_WORDS[@]:1:COMP_CWORD}")
+}
+complete -o filenames -F _mk mk
+"#;
+
+const ZSH: &str = r#"#compdef mk
+_mk() {
+    local -a candidates
+    candidates=(${(f)"$(mk __complete "${(@)words[2,CURRENT]}")"})
+    compadd -f -- "${candidates[@]}"
+}
+compdef _mk mk
+"#;
+
+const FISH: &str = r#"function __mk_complete
+    set -l words (commandline -opc)
+    set -e words[1]
+    mk __complete $words (commandline -ct)
+end
+complete -c mk -f -a '(__mk_complete)'
+"#;
+
+// 'mk completions <shell>'.
+pub fn print_script(args: &[String]) -> i32 {
+    let script = match args.first().map(|a| a.as_str()) {
+        Some("bash") if args.len() == 1 => BASH,
+        Some("zsh") if args.len() == 1 => ZSH,
+        Some("fish") if args.len() == 1 => FISH,
+        _ => {
+            eprintln!("Usage: mk completions bash|zsh|fish");
+            return 2;
+        }
+    };
+
+    print!("{}", script);
+    0
+}
+
+const COMMANDS: &[&str] = &[
+    "add-task-dep",
+    "cache",
+    "clean",
+    "completions",
+    "config",
+    "dev",
+    "doctor",
+    "env",
+    "explain",
+    "hooks",
+    "init",
+    "kernel",
+    "listen",
+    "messages",
+    "migrate",
+    "prompt",
+    "pipe",
+    "ps",
+    "report",
+    "root",
+    "status",
+];
+
+// mk options that take a value.
+const VALUE_OPTIONS: &[&str] = &[
+    "-C",
+    "-e",
+    "-f",
+    "--env",
+    "--env-file",
+    "--extra",
+    "--file",
+    "--flight-recorder",
+    "--group",
+    "--log-file",
+    "--metrics-file",
+    "--project",
+    "--python-arg",
+    "--record",
+    "--replay",
+];
+
+const OPTIONS: &[&str] = &[
+    "-C",
+    "-e",
+    "-f",
+    "-v",
+    "--dry-run",
+    "--env",
+    "--env-file",
+    "--extra",
+    "--file",
+    "--flight-recorder",
+    "--frozen",
+    "--group",
+    "--list",
+    "--locked",
+    "--log-file",
+    "--metrics-file",
+    "--no-sync",
+    "--parallel",
+    "--print-equivalent",
+    "--print-install-info",
+    "--project",
+    "--pty",
+    "--python-arg",
+    "--read-only",
+    "--record",
+    "--replay",
+    "--strict",
+    "--sync",
+    "--verbose",
+    "--watch",
+    "--which",
+    "--which-venv",
+    "--why-slow",
+    "--wide",
+];
+
+pub struct Project<'a> {
+    pub project_root: &'a str,
+    pub make_py_file: &'a str,
+    // The venv's interpreter, if known without discovery.
+    pub python_bin: Option<String>,
+}
+
+pub fn run(config: &Config, project: Option<&Project>, words: &[String]) {
+    for candidate in complete(config, project, words) {
+        println!("{}", candidate);
+    }
+}
+
+fn complete(config: &Config, project: Option<&Project>, words: &[String]) -> Vec<String> {
+    let (current, before) = match words.split_last() {
+        Some((current, before)) => (current.as_str(), before),
+        None => ("", &[][..]),
+    };
+
+    // Skip mk's own options, and '@<member>'.
+    let mut i = 0;
+    while i < before.len()
+        && (before[i].starts_with('-') || before[i].starts_with('@'))
+        && before[i] != "--"
+    {
+        i += if VALUE_OPTIONS.contains(&before[i].as_str()) {
+            2
+        } else {
+            1
+        };
+    }
+
+    if i > before.len() {
+        // Completing the value of an mk option.
+        return match before.last().map(|w| w.as_str()) {
+            Some("-C") | Some("--project") => paths(current, true),
+            Some("--python-arg") | Some("--group") | Some("--extra") | Some("-e")
+            | Some("--env") => Vec::new(),
+            _ => paths(current, false),
+        };
+    }
+
+    let before = &before[i..];
+    let before = match before.first().map(|w| w.as_str()) {
+        Some("--") => &before[1..],
+        _ => before,
+    };
+
+    if before.is_empty() {
+        let mut candidates: Vec<String> = if current.starts_with('-') {
+            OPTIONS.iter().map(|o| o.to_string()).collect()
+        } else if current.starts_with('@') {
+            let cur_dir = env::current_dir().unwrap_or_default();
+            monorepo::member_names(&cur_dir)
+                .into_iter()
+                .map(|name| format!("@{}", name))
+                .collect()
+        } else {
+            let mut names = COMMANDS.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+            if let Some(project) = project {
+                names.extend(task_names(project));
+                names.extend(tasks::names(config));
+                names.extend(facade::names(config));
+                names.extend(alias::names(config));
+            }
+            names
+        };
+        candidates.retain(|c| c.starts_with(current));
+        candidates.sort();
+        candidates.dedup();
+        return candidates;
+    }
+
+    let project = match project {
+        Some(project) => project,
+        None => return paths(current, false),
+    };
+
+    if let Some(candidates) = bridge(project, before, current) {
+        return candidates;
+    }
+
+    heuristic(config, before, current)
+}
+
+// Ask make.py, if it speaks the completion protocol.
+fn bridge(project: &Project, before: &[String], current: &str) -> Option<Vec<String>> {
+    let script = protocol::read_script(project.make_py_file);
+    let version = protocol::negotiate(&script, "completion")?;
+    let python_bin = project.python_bin.as_ref()?;
+
+    let output = Command::new(python_bin)
+        .arg(project.make_py_file)
+        .args(before)
+        .arg(current)
+        .env("MK_COMPLETE", version.to_string())
+        .env("MK_FEATURES", protocol::env_value())
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| line.to_string())
+            .collect(),
+    )
+}
+
+fn heuristic(config: &Config, before: &[String], current: &str) -> Vec<String> {
+    let task = &before[0];
+
+    if let Some(words) = config.get_list(&format!("task.{}.complete", task)) {
+        return match words.as_slice() {
+            [kind] if kind == "files" => paths(current, false),
+            [kind] if kind == "dirs" => paths(current, true),
+            [kind] if kind == "none" => Vec::new(),
+            _ => words
+                .into_iter()
+                .filter(|w| w.starts_with(current))
+                .collect(),
+        };
+    }
+
+    let previous = before.last().map(|w| w.as_str()).unwrap_or("");
+    let passthrough = before.iter().any(|w| w == "--");
+
+    // Paths are the most common value of flags and of args passed through to other tools.
+    let looks_like_path =
+        current.contains('/') || current.starts_with('.') || current.starts_with('~');
+    if passthrough || (previous.starts_with('-') && before.len() > 1) || looks_like_path {
+        return paths(current, false);
+    }
+
+    Vec::new()
+}
+
+// The tasks of make.py, from the cache if make.py didn't change since they were last asked
+// for. Without a known venv, its top-level functions are used.
+fn task_names(project: &Project) -> Vec<String> {
+    let python_bin = match &project.python_bin {
+        Some(python_bin) => python_bin,
+        None => return function_names(project.make_py_file),
+    };
+
+    let stamp = match fs::metadata(project.make_py_file)
+        .and_then(|m| m.modified())
+        .map(|t| t.duration_since(UNIX_EPOCH).unwrap_or_default())
+    {
+        Ok(mtime) => format!("{}.{:09}", mtime.as_secs(), mtime.subsec_nanos()),
+        Err(_) => return Vec::new(),
+    };
+
+    // The first line is the stamp, the task names follow.
+    let cache_file = workspace::dir(project.project_root)
+        .join("completion")
+        .join("tasks");
+    if let Ok(text) = fs::read_to_string(&cache_file) {
+        let mut lines = text.lines();
+        if lines.next() == Some(stamp.as_str()) {
+            return lines.map(|line| line.to_string()).collect();
+        }
+    }
+
+    let script = protocol::read_script(project.make_py_file);
+    let names: Vec<String> = match listing::script_tasks(
+        &script,
+        project.make_py_file,
+        python_bin,
+        &Command::new(python_bin),
+        Stdio::null(),
+    ) {
+        Ok(entries) => entries.into_iter().map(|(name, _)| name).collect(),
+        Err(_) => return function_names(project.make_py_file),
+    };
+
+    if !crate::read_only() {
+        if let Ok(dir) = workspace::ensure_dir(project.project_root) {
+            let dir = dir.join("completion");
+            if fs::create_dir_all(&dir).is_ok() {
+                let mut content = stamp;
+                for name in &names {
+                    content.push('\n');
+                    content.push_str(name);
+                }
+                let _ = fs::write(dir.join("tasks"), content);
+            }
+        }
+    }
+
+    names
+}
+
+// Top-level functions of make.py, which are the tasks in most scripts.
+pub fn function_names(make_py_file: &str) -> Vec<String> {
+    let content = fs::read_to_string(make_py_file).unwrap_or_default();
+
+    content
+        .lines()
+        .filter_map(|line| line.strip_prefix("def "))
+        .filter_map(|rest| rest.split('(').next())
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty() && !name.starts_with('_') && *name != "main")
+        .map(|name| name.to_string())
+        .collect()
+}
+
+fn paths(current: &str, dirs_only: bool) -> Vec<String> {
+    let (dir, prefix) = match current.rfind('/') {
+        Some(i) => (&current[..=i], &current[i + 1..]),
+        None => ("", current),
+    };
+
+    let read_dir = if dir.is_empty() { "." } else { dir };
+    let entries = match fs::read_dir(Path::new(read_dir)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_dir = entry.path().is_dir();
+
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            if dirs_only && !is_dir {
+                return None;
+            }
+
+            Some(format!("{}{}", dir, name))
+        })
+        .collect();
+
+    candidates.sort();
+    candidates
+}
@@ -0,0 +1,633 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use presets;
+use toml::{self, Value};
+
+// The values a setting takes, e.g. for 'mk config schema'.
+pub enum Kind {
+    String,
+    Bool,
+    Integer,
+    // A list of strings, or a comma-separated string.
+    List,
+    Choice(&'static [&'static str]),
+    // A number, or this word.
+    IntegerOr(&'static str),
+}
+
+// Settings mk understands, with the values they take. They are read from the user config
+// ('~/.config/mk/config.toml'), the project's '[tool.mk]' in 'pyproject.toml' and 'mk.toml'
+// and 'MK_<NAME>' environment variables, e.g. 'io_encoding' is read from 'MK_IO_ENCODING'.
+pub const SETTINGS: &[(&str, Kind)] = &[
+    // Value of PYTHONIOENCODING for tasks ('utf-8' on Windows by default, 'off' to never set it).
+    ("io_encoding", Kind::String),
+    // Value of LC_ALL for tasks.
+    ("lc_all", Kind::String),
+    // How projects are keyed in the cache: 'path' (default) or 'git', which additionally
+    // keys entries by git remote URL and path inside the checkout.
+    ("cache_key", Kind::Choice(&["path", "git"])),
+    // System-wide cache dir shared between users, e.g. '/var/cache/mk'.
+    ("shared_cache", Kind::String),
+    // What to do when running as root in a project owned by someone else: 'warn'
+    // (default), 'abort' or 'off'.
+    ("root_guard", Kind::Choice(&["warn", "abort", "off"])),
+    // Shell commands run after a task succeeded or failed.
+    ("on_success", Kind::String),
+    ("on_failure", Kind::String),
+    // OTLP/HTTP collector to send a trace of every run to, e.g. 'http://localhost:4318'.
+    ("otlp_endpoint", Kind::String),
+    // Command the interpreter is run with, e.g. '["stdbuf", "-oL"]'.
+    ("exec_wrapper", Kind::List),
+    // Executables of the venv to put on the task's PATH, instead of its whole bin dir.
+    ("path_shims", Kind::List),
+    // Prometheus textfile to write metrics of every run to, like '--metrics-file'.
+    ("metrics_file", Kind::String),
+    // File to copy the output of every task to, like '--log-file'.
+    ("log_file", Kind::String),
+    // Whether ANSI escape sequences (colors) are removed from the log file copy (default: true).
+    ("log_strip_ansi", Kind::Bool),
+    // Dependency groups and extras installed by '--sync', in addition to '--group'/'--extra'.
+    ("sync_groups", Kind::List),
+    ("sync_extras", Kind::List),
+    // Milliseconds after which a venv existence check counts as passed (default: 0, no limit).
+    ("stat_timeout", Kind::Integer),
+    // Whether venvs are on a slow network filesystem, so checks are only repeated every
+    // 'slow_fs_revalidate' seconds (default: false, 3600).
+    ("slow_fs", Kind::Bool),
+    ("slow_fs_revalidate", Kind::Integer),
+    // Seconds of silence after which a spinner shows that the task is still running (default:
+    // 0, off).
+    ("spinner_after", Kind::Integer),
+    // Whether mk tells crashes, failures and infrastructure problems apart in its output and
+    // exit code (default: false).
+    ("classify_failures", Kind::Bool),
+    // Tasks run by the git hooks 'mk hooks install' writes, e.g. '["lint"]'.
+    ("pre_commit", Kind::List),
+    ("pre_push", Kind::List),
+    // Whether mk runs without writing its cache and other state, like '--read-only'.
+    ("read_only", Kind::Bool),
+    // When tasks are asked to keep their colors with FORCE_COLOR, PY_COLORS and
+    // CLICOLOR_FORCE: 'auto' (default), 'always' or 'never'.
+    ("force_color", Kind::Choice(&["auto", "always", "never"])),
+    // Seconds after which a backend discovery command (uv, poetry) is killed (default: 20).
+    ("discovery_timeout", Kind::Integer),
+    // How often a timed out or temporarily failing discovery command is retried (default: 0),
+    // and the seconds to wait before the first retry, doubled for each next one (default: 1).
+    ("discovery_retries", Kind::Integer),
+    ("discovery_backoff", Kind::Integer),
+    // The tools asked for the venv, in order, e.g. '["pdm", "uv"]', see resolvers.rs, and the
+    // environment the 'conda' resolver uses.
+    ("resolvers", Kind::List),
+    ("conda_env", Kind::String),
+    // A file with changed wording of mk's messages, see messages.rs.
+    ("messages_file", Kind::String),
+    // Links printed after errors of these classes, e.g. to internal runbooks, see
+    // print_runbook in messages.rs.
+    ("on_missing_script_url", Kind::String),
+    ("on_missing_venv_url", Kind::String),
+    ("on_unusable_venv_url", Kind::String),
+    ("on_sync_failure_url", Kind::String),
+    ("on_crash_url", Kind::String),
+    ("on_infrastructure_url", Kind::String),
+    // Modules 'mk doctor' imports in the task environment, e.g. '["pytest"]'.
+    ("doctor_imports", Kind::List),
+    // Whether mk replaces itself with the task on Unix when there is nothing left to do
+    // after it (default: true).
+    ("exec", Kind::Bool),
+    // Variables removed from the environment of tasks (default: '["PYTHONHOME"]').
+    ("unset_env", Kind::List),
+    // The task script relative to the project root, e.g. 'tasks.py' (default: 'make.py').
+    ("script", Kind::String),
+    // The task a bare 'mk' runs instead of listing the tasks, e.g. 'test' or 'serve --reload'.
+    ("default_task", Kind::String),
+    // Whether the project's venv is cached (default: true). Without it, it's resolved on
+    // every run.
+    ("cache", Kind::Bool),
+    // The task 'mk dev' keeps running, e.g. 'serve --port 8000', the tasks it runs when files
+    // change, and the files and dirs it watches (default: the project root).
+    ("dev_server", Kind::String),
+    ("dev_on_change", Kind::List),
+    ("dev_watch", Kind::List),
+    // The dotenv file read into the environment of tasks, relative to the project root
+    // (default: '.env', '""' for none), see dotenv.rs.
+    ("env_file", Kind::String),
+    // Whether 'mk --watch' clears the terminal before each run (default: false).
+    ("watch_clear", Kind::Bool),
+    // Whether warnings are errors, like '--strict'.
+    ("strict", Kind::Bool),
+    // Width mk's tables are wrapped to (default: 0, the terminal's width).
+    ("output_width", Kind::Integer),
+    // How the output of tasks running in parallel is shown: 'dashboard' (default), 'grouped'
+    // or 'prefixed', see dashboard.rs.
+    (
+        "parallel_output",
+        Kind::Choice(&["dashboard", "grouped", "prefixed"]),
+    ),
+    // The soft limit of open files of tasks, or 'max' for the hard limit, and whether they
+    // may dump core. Also per task, e.g. 'task.serve.open_files', see limits.rs. Unix only.
+    ("open_files", Kind::IntegerOr("max")),
+    ("core_dumps", Kind::Bool),
+    // Whether mk creates the venv with the sync command when none is found: 'never'
+    // (default), 'ask' or 'always'.
+    ("create_venv", Kind::Choice(&["never", "ask", "always"])),
+    // The member projects of a workspace, in the config at its top, as dirs relative to it,
+    // e.g. 'services/*', see monorepo.rs.
+    ("workspace_members", Kind::List),
+    // What mk does when the project's '.mk-version' pins another version and the toolchain
+    // dir has none that matches: 'error' (default), 'warn' or 'off', see mk_version.rs.
+    ("mk_version_check", Kind::Choice(&["error", "warn", "off"])),
+    // Where versions of mk are installed as '<version>/mk' (default:
+    // '~/.local/share/mk/toolchains').
+    ("toolchain_dir", Kind::String),
+];
+
+pub struct Config {
+    values: HashMap<String, Value>,
+    // Keys of the config files that aren't settings or tasks, with their file.
+    unknown: Vec<String>,
+}
+
+impl Config {
+    // Settings are layered: the user config is overridden by the project's '[tool.mk]' in
+    // 'pyproject.toml', then by its 'mk.toml', and those by 'MK_*' environment variables.
+    pub fn load(project_root: Option<&str>) -> Config {
+        let mut values = HashMap::new();
+        let mut unknown = Vec::new();
+
+        let mut files = Vec::new();
+        if let Some(path) = user_config_path() {
+            files.push(path);
+        }
+        if let Some(project_root) = project_root {
+            files.push(Path::new(project_root).join("pyproject.toml"));
+            files.push(project_config_path(project_root));
+        }
+
+        for path in files {
+            load_file(&path, &mut values, &mut unknown, &mut Vec::new());
+        }
+
+        for (name, _) in SETTINGS {
+            if let Ok(value) = env::var(env_var_name(name)) {
+                values.insert(name.to_string(), Value::String(value));
+            }
+        }
+
+        Config { values, unknown }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).and_then(|v| v.as_str())
+    }
+
+    pub fn get_value(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    // A list of strings, given as an array or as a comma separated string (e.g. from the
+    // environment).
+    pub fn get_list(&self, name: &str) -> Option<Vec<String>> {
+        as_list(self.values.get(name)?)
+    }
+
+    pub fn get_u64(&self, name: &str, default: u64) -> u64 {
+        let invalid = |value: &dyn fmt::Display| {
+            crate::warn(&format!(
+                "Invalid value '{}' for '{}', using {}.",
+                value, name, default
+            ));
+            default
+        };
+
+        match self.values.get(name) {
+            None => default,
+            Some(Value::Integer(n)) if *n >= 0 => *n as u64,
+            Some(Value::String(value)) => match value.trim().parse() {
+                Ok(n) => n,
+                Err(_) => invalid(value),
+            },
+            Some(value) => invalid(value),
+        }
+    }
+
+    pub fn get_bool(&self, name: &str, default: bool) -> bool {
+        let invalid = |value: &dyn fmt::Display| {
+            crate::warn(&format!(
+                "Invalid value '{}' for '{}', using {}.",
+                value, name, default
+            ));
+            default
+        };
+
+        match self.values.get(name) {
+            None => default,
+            Some(Value::Boolean(b)) => *b,
+            Some(Value::String(value)) => match value.trim().to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => true,
+                "false" | "no" | "off" | "0" => false,
+                _ => invalid(value),
+            },
+            Some(value) => invalid(value),
+        }
+    }
+
+    // Keys of the config files mk doesn't know, probably typos, e.g. "'log_fiel' in
+    // /home/me/app/mk.toml".
+    pub fn unknown_keys(&self) -> &[String] {
+        &self.unknown
+    }
+
+    // All settings that are set, sorted by name.
+    pub fn values(&self) -> Vec<(String, String)> {
+        let mut values: Vec<(String, String)> = self
+            .values
+            .iter()
+            .map(|(k, v)| match v {
+                Value::String(s) => (k.clone(), s.clone()),
+                v => (k.clone(), v.to_string()),
+            })
+            .collect();
+        values.sort();
+        values
+    }
+
+    // Environment variables for tasks from the '[env]' table, e.g. 'env.DJANGO_DEBUG = "1"'.
+    pub fn env(&self) -> Vec<(String, String)> {
+        self.values()
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix("env.")?.to_string(), value)))
+            .collect()
+    }
+}
+
+// Add the settings of the config file at 'path' to 'values', after those of the files it
+// extends, see presets.rs. 'chain' are the files currently being loaded, to catch cycles.
+fn load_file(
+    path: &Path,
+    values: &mut HashMap<String, Value>,
+    unknown: &mut Vec<String>,
+    chain: &mut Vec<PathBuf>,
+) {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(_) => return,
+    };
+
+    let pyproject = path.file_name() == Some("pyproject.toml".as_ref());
+    let text = if pyproject {
+        tool_mk_sections(&text)
+    } else {
+        text
+    };
+
+    let doc = match toml::parse(&text) {
+        Ok(doc) => doc,
+        Err(e) if pyproject => {
+            eprintln!("mk: Invalid '[tool.mk]' in {}: {}", path.display(), e);
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("mk: Invalid config file {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    };
+
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if chain.contains(&canonical) {
+        eprintln!(
+            "mk: Config file {} is part of an 'extends' cycle.",
+            path.display()
+        );
+        process::exit(1);
+    }
+    chain.push(canonical);
+
+    let mut flat = doc.flatten();
+    if pyproject {
+        flat = flat
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix("tool.mk.")?.to_string(), value)))
+            .collect();
+    }
+
+    let extends = match flat.iter().position(|(k, _)| k == "extends") {
+        Some(i) => match flat.remove(i).1 {
+            Value::String(spec) => vec![spec],
+            Value::Array(items) => items
+                .iter()
+                .filter_map(|item| item.as_str())
+                .map(|item| item.to_string())
+                .collect(),
+            other => {
+                eprintln!(
+                    "mk: Invalid value '{}' for 'extends' in {}, expected a string or a list.",
+                    other,
+                    path.display()
+                );
+                process::exit(1);
+            }
+        },
+        None => Vec::new(),
+    };
+
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    for spec in extends {
+        match presets::resolve(&spec, dir) {
+            Ok(base) => load_file(&base, values, unknown, chain),
+            Err(e) => {
+                eprintln!(
+                    "mk: Cannot load '{}' extended by {}: {}",
+                    spec,
+                    path.display(),
+                    e
+                );
+                process::exit(1);
+            }
+        }
+    }
+
+    for (key, _) in &flat {
+        if !is_setting(key)
+            && !key.starts_with("task.")
+            && !key.starts_with("env.")
+            && !key.starts_with("alias.")
+            && !key.starts_with("facade.task.")
+            && key != "facade.tool"
+        {
+            unknown.push(format!("'{}' in {}", key, path.display()));
+        }
+    }
+
+    values.extend(flat);
+    chain.pop();
+}
+
+// A list setting: a list of strings, or a comma-separated string.
+fn as_list(value: &Value) -> Option<Vec<String>> {
+    match value {
+        Value::Array(items) => Some(
+            items
+                .iter()
+                .filter_map(|item| item.as_str())
+                .map(|item| item.to_string())
+                .collect(),
+        ),
+        Value::String(value) => Some(
+            value
+                .split(',')
+                .map(|item| item.trim())
+                .filter(|item| !item.is_empty())
+                .map(|item| item.to_string())
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+// How often project_value() parsed a config, for the startup test in mk.rs.
+#[cfg(test)]
+pub static PROJECT_PARSES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+// Setting 'name' of the project config in 'dir', if it has it, for settings needed before
+// the config can be loaded. Only 'mk.toml' and '[tool.mk]' of 'pyproject.toml' are read and
+// errors are left for the full load to report.
+fn project_value(dir: &Path, name: &str) -> Option<Value> {
+    for file in &["mk.toml", "pyproject.toml"] {
+        let text = match fs::read_to_string(dir.join(file)) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let (text, key) = match *file {
+            "pyproject.toml" => (tool_mk_sections(&text), format!("tool.mk.{}", name)),
+            _ => (text, name.to_string()),
+        };
+        // Most configs don't set it, and searching is much cheaper than parsing.
+        if !text.contains(name) {
+            continue;
+        }
+        #[cfg(test)]
+        PROJECT_PARSES.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let value = toml::parse(&text).ok().and_then(|doc| {
+            doc.flatten()
+                .into_iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v)
+        });
+        if value.is_some() {
+            return value;
+        }
+    }
+    None
+}
+
+// The 'script' setting of the project config in 'dir', if it has one. It's needed to find
+// the project root.
+pub fn project_script(dir: &Path) -> Option<String> {
+    project_value(dir, "script").and_then(|v| v.as_str().map(|s| s.to_string()))
+}
+
+// The 'workspace_members' setting of the config in 'dir', if it has one. It's needed to
+// find the workspace, see monorepo.rs.
+pub fn workspace_members(dir: &Path) -> Option<Vec<String>> {
+    as_list(&project_value(dir, "workspace_members")?)
+}
+
+// The '[tool.mk]' tables of a pyproject.toml, e.g. '[tool.mk]' and '[tool.mk.task.lint]'.
+// Only these are parsed, the rest of the file is none of mk's business.
+fn tool_mk_sections(text: &str) -> String {
+    let mut out = String::new();
+    let mut inside = false;
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('[') {
+            let name = trimmed.trim_start_matches('[').trim_start();
+            inside = !trimmed.starts_with("[[")
+                && (name.starts_with("tool.mk]") || name.starts_with("tool.mk."));
+        }
+        if inside {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+pub fn is_setting(name: &str) -> bool {
+    SETTINGS.iter().any(|(setting, _)| *setting == name)
+}
+
+pub fn env_var_name(name: &str) -> String {
+    format!("MK_{}", name.to_uppercase())
+}
+
+pub fn user_config_path() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        if !dir.is_empty() {
+            return Some(PathBuf::from(dir).join("mk").join("config.toml"));
+        }
+    }
+
+    if cfg!(windows) {
+        if let Some(dir) = env::var_os("APPDATA") {
+            return Some(PathBuf::from(dir).join("mk").join("config.toml"));
+        }
+    }
+
+    env::home_dir().map(|home| home.join(".config").join("mk").join("config.toml"))
+}
+
+pub fn project_config_path(project_root: &str) -> PathBuf {
+    Path::new(project_root).join("mk.toml")
+}
+
+// End of the line containing 'pos', including the newline.
+fn line_end(text: &str, pos: usize) -> usize {
+    match text[pos..].find('\n') {
+        Some(offset) => pos + offset + 1,
+        None => text.len(),
+    }
+}
+
+fn assignment(key: &str, value: &Value) -> String {
+    let key: Vec<String> = key.split('.').map(toml::quote_key).collect();
+    format!("{} = {}\n", key.join("."), value)
+}
+
+// Set 'key' in the TOML file at 'path'. Only the value is replaced, so comments and
+// formatting elsewhere survive. 'raw_value' is parsed as a TOML value if possible and
+// taken as a string otherwise, so 'mk config set lc_all C.UTF-8' works without quotes.
+pub fn set_in_file(path: &Path, key: &str, raw_value: &str) -> Result<(), String> {
+    let value =
+        toml::parse_value(raw_value).unwrap_or_else(|_| Value::String(raw_value.to_string()));
+
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("cannot read {}: {}", path.display(), e)),
+    };
+
+    let doc = toml::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut new_text = text.clone();
+
+    if let Some(entry) = doc.entries.iter().find(|e| e.key == key) {
+        new_text.replace_range(entry.value_start..entry.value_end, &value.to_string());
+    } else if let Some(header) = doc
+        .headers
+        .iter()
+        .filter(|h| key.starts_with(&format!("{}.", h.name)))
+        .max_by_key(|h| h.name.len())
+    {
+        // Append to the end of the most specific existing table.
+        let section_start = header.line_end;
+        let section_end = doc
+            .headers
+            .iter()
+            .map(|h| h.line_start)
+            .filter(|start| *start >= section_start)
+            .min()
+            .unwrap_or(text.len());
+        let insert_at = doc
+            .entries
+            .iter()
+            .filter(|e| e.line_start >= section_start && e.line_start < section_end)
+            .map(|e| line_end(&text, e.value_end))
+            .max()
+            .unwrap_or(section_start);
+
+        let mut line = assignment(&key[header.name.len() + 1..], &value);
+        if insert_at == text.len() && !text.is_empty() && !text.ends_with('\n') {
+            line.insert(0, '\n');
+        }
+        new_text.insert_str(insert_at, &line);
+    } else if !key.contains('.') && !doc.headers.is_empty() {
+        // Top-level keys must come before the first table.
+        let first_header = doc.headers.iter().map(|h| h.line_start).min().unwrap_or(0);
+        match doc
+            .entries
+            .iter()
+            .filter(|e| e.line_start < first_header)
+            .map(|e| line_end(&text, e.value_end))
+            .max()
+        {
+            Some(insert_at) => new_text.insert_str(insert_at, &assignment(key, &value)),
+            None => new_text.insert_str(first_header, &format!("{}\n", assignment(key, &value))),
+        }
+    } else {
+        if !new_text.is_empty() && !new_text.ends_with('\n') {
+            new_text.push('\n');
+        }
+        match key.rfind('.') {
+            Some(dot) => {
+                if !new_text.is_empty() {
+                    new_text.push('\n');
+                }
+                let table: Vec<String> = key[..dot].split('.').map(toml::quote_key).collect();
+                new_text.push_str(&format!("[{}]\n", table.join(".")));
+                new_text.push_str(&assignment(&key[dot + 1..], &value));
+            }
+            None => new_text.push_str(&assignment(key, &value)),
+        }
+    }
+
+    // Never write a file we can't read back.
+    toml::parse(&new_text).map_err(|e| format!("editing {} failed: {}", path.display(), e))?;
+
+    write_atomic(path, &new_text)
+}
+
+// Write to a temp file next to 'path' and rename it over, so readers never see a
+// half-written file.
+//
+// A symlinked file is written where the link points to, so the link stays and the rename
+// doesn't cross filesystems.
+pub fn write_atomic<C: AsRef<[u8]>>(path: &Path, content: C) -> Result<(), String> {
+    write_atomic_metadata(path, content).map(|_| ())
+}
+
+// Like write_atomic, returning the metadata of the file as it was written, taken before it
+// replaced 'path', so another writer can't have changed it yet.
+pub fn write_atomic_metadata<C: AsRef<[u8]>>(
+    path: &Path,
+    content: C,
+) -> Result<fs::Metadata, String> {
+    let resolved = fs::canonicalize(path).ok();
+    let path = resolved.as_deref().unwrap_or(path);
+
+    if let Some(dir) = path.parent() {
+        if !dir.as_os_str().is_empty() {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+        }
+    }
+
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, process::id()));
+
+    fs::write(&tmp_path, content)
+        .map_err(|e| format!("cannot write {}: {}", tmp_path.display(), e))?;
+
+    // Keep the permissions of the file we replace.
+    if let Ok(meta) = fs::metadata(path) {
+        let _ = fs::set_permissions(&tmp_path, meta.permissions());
+    }
+    let written = fs::metadata(&tmp_path)
+        .map_err(|e| format!("cannot write {}: {}", tmp_path.display(), e))?;
+
+    fs::rename(&tmp_path, path).map_err(|e| {
+        let _ = fs::remove_file(&tmp_path);
+        format!("cannot write {}: {}", path.display(), e)
+    })?;
+    Ok(written)
+}
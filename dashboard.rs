@@ -0,0 +1,389 @@
+// Output of tasks that run in parallel, e.g. 'mk --parallel lint test' or the combinations
+// of a 'parallel' matrix. How it's shown is the 'parallel_output' setting:
+//
+// - 'dashboard' (default): On a terminal mk draws a live dashboard with one line per task: a
+//   spinner, the time it's been running and its last line of output. When all are done,
+//   the output of the tasks that failed is printed. Elsewhere, e.g. in CI logs, it's
+//   'grouped'.
+// - 'grouped': Each task's output is collected and printed in one piece as soon as it
+//   finishes, in GitHub Actions as a collapsible group.
+// - 'prefixed': Lines are printed as they come, interleaved, each prefixed with its task.
+
+use std::env;
+use std::io;
+use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use config::Config;
+use output;
+use output::AnsiStripper;
+use pty;
+
+pub struct Job {
+    pub label: String,
+    // Run one after another until one fails, e.g. the steps of a config task, with what
+    // they run for messages.
+    pub commands: Vec<(String, Command)>,
+}
+
+pub struct Finished {
+    // What failed and how, or the error it couldn't be run with.
+    pub failed: Option<(String, Result<ExitStatus, String>)>,
+    pub duration: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Live,
+    Grouped,
+    Prefixed,
+}
+
+fn mode(config: &Config) -> Mode {
+    match config.get("parallel_output") {
+        None | Some("dashboard") if io::stderr().is_terminal() => Mode::Live,
+        None | Some("dashboard") | Some("grouped") => Mode::Grouped,
+        Some("prefixed") => Mode::Prefixed,
+        Some(other) => {
+            crate::warn(&format!(
+                "Invalid value '{}' for 'parallel_output', using dashboard.",
+                other
+            ));
+            if io::stderr().is_terminal() {
+                Mode::Live
+            } else {
+                Mode::Grouped
+            }
+        }
+    }
+}
+
+struct State {
+    label: String,
+    // In 'prefixed' mode, what lines are prefixed with, and the unfinished lines of stdout
+    // and stderr.
+    prefix: Option<String>,
+    pending: [Vec<u8>; 2],
+    started: Instant,
+    output: Vec<u8>,
+    stripper: AnsiStripper,
+    // The line being written and the last complete one, without colors, for the dashboard.
+    line: Vec<u8>,
+    last_line: String,
+    finished: Option<Finished>,
+}
+
+impl State {
+    // Add output of 'stream', 0 for stdout and 1 for stderr.
+    fn append(&mut self, chunk: &[u8], stream: usize) {
+        if let Some(prefix) = &self.prefix {
+            let pending = &mut self.pending[stream];
+            pending.extend_from_slice(chunk);
+            while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                write_prefixed(prefix, &line, stream);
+            }
+            return;
+        }
+
+        self.output.extend_from_slice(chunk);
+        for byte in self.stripper.strip(chunk) {
+            match byte {
+                b'\n' | b'\r' => {
+                    let line = String::from_utf8_lossy(&self.line).trim().to_string();
+                    if !line.is_empty() {
+                        self.last_line = line;
+                    }
+                    self.line.clear();
+                }
+                _ => self.line.push(byte),
+            }
+        }
+    }
+
+    // The end of 'stream' was reached.
+    fn flush(&mut self, stream: usize) {
+        if let Some(prefix) = &self.prefix {
+            let mut line = std::mem::take(&mut self.pending[stream]);
+            if !line.is_empty() {
+                line.push(b'\n');
+                write_prefixed(prefix, &line, stream);
+            }
+        }
+    }
+
+    fn current_line(&self) -> String {
+        let line = String::from_utf8_lossy(&self.line).trim().to_string();
+        if line.is_empty() {
+            self.last_line.clone()
+        } else {
+            line
+        }
+    }
+}
+
+fn write_prefixed(prefix: &str, line: &[u8], stream: usize) {
+    let mut text = prefix.as_bytes().to_vec();
+    text.extend_from_slice(line);
+    let _ = match stream {
+        0 => io::stdout().write_all(&text),
+        _ => io::stderr().write_all(&text),
+    };
+}
+
+type Shared = Arc<Mutex<Vec<State>>>;
+
+fn lock(shared: &Shared) -> std::sync::MutexGuard<'_, Vec<State>> {
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn read_into<R: Read + Send + 'static>(
+    mut from: R,
+    shared: Shared,
+    index: usize,
+    stream: usize,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            let n = match from.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            lock(&shared)[index].append(&buf[..n], stream);
+        }
+        lock(&shared)[index].flush(stream);
+    })
+}
+
+// Run 'cmd' with its output going to the state of job 'index'.
+fn run_one(cmd: &mut Command, shared: &Shared, index: usize) -> Result<ExitStatus, String> {
+    // Python buffers output to pipes in blocks, which would freeze the dashboard.
+    if env::var_os("PYTHONUNBUFFERED").is_none() {
+        cmd.env("PYTHONUNBUFFERED", "1");
+    }
+
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    let stdout = child.stdout.take().expect("mk: Child stdout is piped.");
+    let stderr = child.stderr.take().expect("mk: Child stderr is piped.");
+    let readers = vec![
+        read_into(stdout, shared.clone(), index, 0),
+        read_into(stderr, shared.clone(), index, 1),
+    ];
+
+    let status = child.wait().map_err(|e| e.to_string());
+    for reader in readers {
+        let _ = reader.join();
+    }
+    status
+}
+
+fn run_job(commands: Vec<(String, Command)>, shared: &Shared, index: usize) -> Finished {
+    let started = Instant::now();
+    let mut failed = None;
+
+    for (what, mut cmd) in commands {
+        match run_one(&mut cmd, shared, index) {
+            Ok(status) if status.success() => {}
+            result => {
+                failed = Some((what, result));
+                break;
+            }
+        }
+    }
+
+    Finished {
+        failed,
+        duration: started.elapsed(),
+    }
+}
+
+fn describe(finished: &Finished) -> String {
+    match &finished.failed {
+        None => "ok".to_string(),
+        Some((what, Ok(status))) => format!("FAILED, '{}' ({})", what, status),
+        Some((what, Err(e))) => format!("FAILED, cannot run '{}': {}", what, e),
+    }
+}
+
+fn header(state: &State, finished: &Finished) -> String {
+    format!(
+        "[{}] {} ({:.1}s)",
+        state.label,
+        describe(finished),
+        finished.duration.as_secs_f64()
+    )
+}
+
+// Print a job's output in one piece.
+fn print_log(state: &State) {
+    let finished = match &state.finished {
+        Some(finished) => finished,
+        None => return,
+    };
+    let header = header(state, finished);
+    let github = env::var_os("GITHUB_ACTIONS").is_some();
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if github {
+        let _ = writeln!(out, "::group::{}", header);
+    } else {
+        let _ = out.flush();
+        eprintln!("mk: {}", header);
+    }
+    let _ = out.write_all(&state.output);
+    if !state.output.is_empty() && !state.output.ends_with(b"\n") {
+        let _ = writeln!(out);
+    }
+    if github {
+        let _ = writeln!(out, "::endgroup::");
+    }
+    let _ = out.flush();
+}
+
+fn terminal_width() -> usize {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .filter(|columns| *columns > 0)
+        .or_else(pty::stdout_columns)
+        .unwrap_or(80)
+}
+
+// Draw the dashboard, over the previous one if there is one.
+fn draw(states: &[State], frame: usize, redraw: bool) {
+    let width = terminal_width();
+    let label_width = states
+        .iter()
+        .map(|s| s.label.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut text = String::new();
+    if redraw {
+        text.push_str(&format!("\x1b[{}A", states.len()));
+    }
+    for state in states {
+        let (status, elapsed) = match &state.finished {
+            Some(finished) if finished.failed.is_none() => ("ok".to_string(), finished.duration),
+            Some(finished) => ("FAILED".to_string(), finished.duration),
+            None => (
+                output::SPINNER_FRAMES[frame % output::SPINNER_FRAMES.len()].to_string(),
+                state.started.elapsed(),
+            ),
+        };
+        let line = format!(
+            "{:<6} {:<width$} {:>6.1}s  {}",
+            status,
+            state.label,
+            elapsed.as_secs_f64(),
+            state.current_line(),
+            width = label_width
+        );
+        text.push_str(output::CLEAR_LINE);
+        text.extend(line.chars().take(width.saturating_sub(1)));
+        text.push('\n');
+    }
+
+    eprint!("{}", text);
+    let _ = io::stderr().flush();
+}
+
+// Run the jobs in parallel. Returns how they finished, in the order of 'jobs'.
+pub fn run(config: &Config, jobs: Vec<Job>) -> Vec<Finished> {
+    let mode = mode(config);
+    let live = mode == Mode::Live;
+    let label_width = jobs
+        .iter()
+        .map(|job| job.label.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let shared: Shared = Arc::new(Mutex::new(
+        jobs.iter()
+            .map(|job| State {
+                label: job.label.clone(),
+                prefix: match mode {
+                    Mode::Prefixed => {
+                        Some(format!("{:<width$} | ", job.label, width = label_width))
+                    }
+                    _ => None,
+                },
+                pending: [Vec::new(), Vec::new()],
+                started: Instant::now(),
+                output: Vec::new(),
+                stripper: AnsiStripper::new(),
+                line: Vec::new(),
+                last_line: String::new(),
+                finished: None,
+            })
+            .collect(),
+    ));
+
+    let workers: Vec<thread::JoinHandle<()>> = jobs
+        .into_iter()
+        .enumerate()
+        .map(|(index, job)| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let finished = run_job(job.commands, &shared, index);
+                let mut states = lock(&shared);
+                let state = &mut states[index];
+                match mode {
+                    Mode::Live => {}
+                    Mode::Grouped => {
+                        state.finished = Some(finished);
+                        print_log(state);
+                        return;
+                    }
+                    Mode::Prefixed => eprintln!("mk: {}", header(state, &finished)),
+                }
+                state.finished = Some(finished);
+            })
+        })
+        .collect();
+
+    if live {
+        let mut frame = 0;
+        loop {
+            let done = {
+                let states = lock(&shared);
+                draw(&states, frame, frame > 0);
+                states.iter().all(|s| s.finished.is_some())
+            };
+            if done {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+            frame += 1;
+        }
+    }
+
+    for worker in workers {
+        let _ = worker.join();
+    }
+
+    let states = std::mem::take(&mut *lock(&shared));
+    if live {
+        for state in states.iter().filter(|s| {
+            s.finished
+                .as_ref()
+                .is_some_and(|finished| finished.failed.is_some())
+        }) {
+            print_log(state);
+        }
+    }
+
+    states.into_iter().filter_map(|s| s.finished).collect()
+}
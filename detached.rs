@@ -0,0 +1,206 @@
+// Records of the long-running tasks mk starts in a process group of their own, e.g. the
+// server of 'mk dev', in '.mk/pids/<pid>'. Such a task outlives mk if mk doesn't get to
+// stop it, e.g. when mk is killed or its terminal closed, and then holds on to its port.
+//
+// Every mk run in the project looks at the records: those of tasks that exited are removed,
+// and tasks whose mk is gone are reported as orphaned. 'mk ps' lists the tasks, '--all'
+// those of all projects in the cache.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use config::Config;
+use sys;
+use table;
+use toml;
+use toml::Value;
+use workspace;
+
+struct Record {
+    file: PathBuf,
+    // The mk that started the task.
+    owner: u32,
+    pid: u32,
+    task: String,
+    started_at: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Record task 'pid', started by this mk.
+pub fn register(project_root: &str, pid: u32, task: &str) {
+    if crate::read_only() {
+        return;
+    }
+    let dir = match workspace::ensure_dir(project_root) {
+        Ok(dir) => dir.join("pids"),
+        Err(_) => return,
+    };
+    let content = format!(
+        "owner = {}\npid = {}\ntask = {}\nstarted_at = {}\n",
+        process::id(),
+        pid,
+        toml::quote(task),
+        now()
+    );
+    if fs::create_dir_all(&dir).is_ok() {
+        let _ = fs::write(dir.join(pid.to_string()), content);
+    }
+}
+
+fn read(file: &Path) -> Option<Record> {
+    let doc = toml::parse(&fs::read_to_string(file).ok()?).ok()?;
+    let value = |name: &str| {
+        doc.entries
+            .iter()
+            .find(|e| e.key == name)
+            .map(|e| e.value.clone())
+    };
+    let number = |name: &str| match value(name) {
+        Some(Value::Integer(n)) if n >= 0 => Some(n as u64),
+        _ => None,
+    };
+
+    Some(Record {
+        file: file.to_path_buf(),
+        owner: number("owner")? as u32,
+        pid: number("pid")? as u32,
+        task: value("task")?.as_str()?.to_string(),
+        started_at: number("started_at").unwrap_or(0),
+    })
+}
+
+fn records(project_root: &str) -> Vec<Record> {
+    let entries = match fs::read_dir(workspace::dir(project_root).join("pids")) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut records: Vec<Record> = entries
+        .flatten()
+        .filter_map(|entry| read(&entry.path()))
+        .collect();
+    records.sort_by_key(|r| r.started_at);
+    records
+}
+
+// Remove the records of tasks that exited and report orphaned tasks. Returns the records
+// of the tasks still running.
+fn reap_records(project_root: &str) -> Vec<Record> {
+    let mut running = Vec::new();
+    for record in records(project_root) {
+        match sys::running_since(record.pid, record.started_at) {
+            Some(false) => {
+                if !crate::read_only() {
+                    let _ = fs::remove_file(&record.file);
+                }
+            }
+            _ => running.push(record),
+        }
+    }
+    running
+}
+
+fn orphaned(record: &Record) -> bool {
+    sys::running_since(record.owner, record.started_at) == Some(false)
+}
+
+// Run on every mk start in a project.
+pub fn reap(project_root: &str) {
+    for record in reap_records(project_root).iter().filter(|r| orphaned(r)) {
+        eprintln!(
+            "mk: '{}' (pid {}) was left running by an mk that is gone. Stop it with 'kill -- -{}'.",
+            record.task, record.pid, record.pid
+        );
+    }
+}
+
+fn uptime(started_at: u64) -> String {
+    let secs = now().saturating_sub(started_at);
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h{}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d{}h", secs / 86400, secs % 86400 / 3600),
+    }
+}
+
+// 'mk ps [--all]'. 'projects' are the project dirs known from the cache.
+pub fn ps(
+    args: &[String],
+    config: &Config,
+    project_root: Option<&str>,
+    projects: &[String],
+) -> i32 {
+    let all = match args {
+        [] => false,
+        [flag] if flag == "--all" || flag == "-a" => true,
+        _ => {
+            eprintln!("Usage: mk ps [--all]");
+            return 2;
+        }
+    };
+
+    let roots: Vec<String> = match (all, project_root) {
+        (true, _) => {
+            let mut roots: Vec<String> = projects
+                .iter()
+                .chain(project_root.map(|r| r.to_string()).iter())
+                .filter(|dir| Path::new(dir).is_dir())
+                .cloned()
+                .collect();
+            roots.sort();
+            roots.dedup();
+            roots
+        }
+        (false, Some(project_root)) => vec![project_root.to_string()],
+        (false, None) => {
+            eprintln!("mk: Not inside a project. Use 'mk ps --all' for all projects.");
+            return 2;
+        }
+    };
+
+    let mut rows = vec![vec![
+        "pid".to_string(),
+        "state".to_string(),
+        "uptime".to_string(),
+        "task".to_string(),
+    ]];
+    if all {
+        rows[0].push("project".to_string());
+    }
+    for root in &roots {
+        for record in reap_records(root) {
+            let state = if orphaned(&record) {
+                "orphaned"
+            } else {
+                "running"
+            };
+            let mut row = vec![
+                record.pid.to_string(),
+                state.to_string(),
+                uptime(record.started_at),
+                record.task.clone(),
+            ];
+            if all {
+                row.push(root.clone());
+            }
+            rows.push(row);
+        }
+    }
+
+    if rows.len() == 1 {
+        println!("No tasks running.");
+        return 0;
+    }
+    for line in table::format(&rows, 0, table::max_width(config)) {
+        println!("{}", line);
+    }
+    0
+}
@@ -0,0 +1,346 @@
+// 'mk dev': run a long-lived task like a dev server together with a watcher. When files
+// change, mk stops the server, runs the 'dev_on_change' tasks (e.g. tests) and starts the
+// server again. Ctrl+C stops everything that's running, so no server is left behind
+// holding its port.
+//
+//     dev_server = "serve --port 8000"
+//     dev_on_change = ["test --fast"]
+//     dev_watch = ["src", "make.py"]
+//
+// 'mk --watch [<paths>...] <task>' is the same loop for tasks that finish, like linters and
+// tests: the task runs, and runs again whenever files change.
+//
+// Watching is done by polling mtimes, which works on every platform and filesystem and is
+// cheap enough for source trees.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use config::Config;
+use detached;
+use sys;
+use tasks;
+
+const USAGE: &str = "Usage: mk dev [<server task> [<args>...]]";
+const WATCH_USAGE: &str = "Usage: mk --watch [<paths>...] [--] <task> [<args>...]";
+
+// Clears the terminal and its scrollback, for 'watch_clear'.
+const CLEAR_SCREEN: &str = "\x1b[H\x1b[2J\x1b[3J";
+
+// Dirs whose changes never matter for the server. Hidden dirs are always skipped.
+const SKIP_DIRS: &[&str] = &["__pycache__", "node_modules", "venv", "build", "dist"];
+
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+// How long the server gets to shut down after SIGTERM before it's killed.
+const STOP_GRACE: Duration = Duration::from_secs(5);
+
+type Snapshot = HashMap<PathBuf, SystemTime>;
+
+fn scan(path: &Path, snapshot: &mut Snapshot) {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return,
+    };
+
+    if !metadata.is_dir() {
+        if let Ok(modified) = metadata.modified() {
+            snapshot.insert(path.to_path_buf(), modified);
+        }
+        return;
+    }
+
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') || SKIP_DIRS.contains(&name.as_str()) {
+            continue;
+        }
+        scan(&entry.path(), snapshot);
+    }
+}
+
+fn snapshot(watched: &[PathBuf]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    for path in watched {
+        scan(path, &mut snapshot);
+    }
+    snapshot
+}
+
+// Paths added, modified or removed between two snapshots.
+fn changes(old: &Snapshot, new: &Snapshot) -> Vec<PathBuf> {
+    let mut changed: Vec<PathBuf> = new
+        .iter()
+        .filter(|(path, modified)| old.get(*path) != Some(modified))
+        .map(|(path, _)| path.clone())
+        .chain(old.keys().filter(|path| !new.contains_key(*path)).cloned())
+        .collect();
+    changed.sort();
+    changed
+}
+
+// Editors and formatters often write several files in a row, wait until that's done.
+fn settle(watched: &[PathBuf], mut current: Snapshot) -> Snapshot {
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let settled = snapshot(watched);
+        if changes(&current, &settled).is_empty() {
+            return settled;
+        }
+        current = settled;
+    }
+}
+
+fn print_changes(changed: &[PathBuf], project_root: &str) {
+    let first = changed[0]
+        .strip_prefix(project_root)
+        .unwrap_or(&changed[0])
+        .display()
+        .to_string();
+    match changed.len() {
+        1 => eprintln!("mk: {} changed.", first),
+        n => eprintln!("mk: {} and {} more changed.", first, n - 1),
+    }
+}
+
+// Start a task, recorded as detached as it's in a group of its own, see detached.rs.
+fn start(
+    spawn: &dyn Fn(&[String]) -> Command,
+    args: &[String],
+    project_root: &str,
+) -> Option<Child> {
+    let mut command = spawn(args);
+    // Children run in a group of their own, so they don't get the terminal's Ctrl+C and
+    // mk can stop them in order. That also means they can't read the terminal.
+    sys::new_group(&mut command);
+    command.stdin(Stdio::null());
+
+    match command.spawn() {
+        Ok(child) => {
+            detached::register(project_root, child.id(), &args.join(" "));
+            Some(child)
+        }
+        Err(e) => {
+            eprintln!("mk: Failed to run '{}': {}", args.join(" "), e);
+            None
+        }
+    }
+}
+
+// Wait for a child, None if mk was interrupted meanwhile.
+fn wait(child: &mut Child) -> Option<ExitStatus> {
+    loop {
+        if sys::interrupted() {
+            sys::terminate(child, STOP_GRACE);
+            return None;
+        }
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) => thread::sleep(Duration::from_millis(50)),
+            Err(_) => return child.wait().ok(),
+        }
+    }
+}
+
+// Run the 'dev_on_change' tasks one after another until one fails. Returns false if mk was
+// interrupted.
+fn run_on_change(
+    spawn: &dyn Fn(&[String]) -> Command,
+    on_change: &[String],
+    project_root: &str,
+) -> bool {
+    for task in on_change {
+        eprintln!("mk: > mk {}", task);
+        let mut child = match start(spawn, &tasks::split_words(task), project_root) {
+            Some(child) => child,
+            None => return true,
+        };
+        match wait(&mut child) {
+            Some(status) if status.success() => (),
+            Some(status) => {
+                eprintln!("mk: '{}' failed ({}).", task, status);
+                return true;
+            }
+            None => return false,
+        }
+    }
+    true
+}
+
+pub fn run(
+    args: &[String],
+    config: &Config,
+    project_root: &str,
+    spawn: &dyn Fn(&[String]) -> Command,
+) -> i32 {
+    let server: Vec<String> = match (args.is_empty(), config.get("dev_server")) {
+        (false, _) => args.to_vec(),
+        (true, Some(server)) => tasks::split_words(server),
+        (true, None) => {
+            eprintln!("mk: No server task, set e.g. 'dev_server = \"serve\"'.");
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    if server.is_empty() {
+        eprintln!("{}", USAGE);
+        return 2;
+    }
+
+    let on_change = config.get_list("dev_on_change").unwrap_or_default();
+    let watched: Vec<PathBuf> = match config.get_list("dev_watch") {
+        Some(paths) if !paths.is_empty() => paths
+            .iter()
+            .map(|p| Path::new(project_root).join(p))
+            .collect(),
+        _ => vec![PathBuf::from(project_root)],
+    };
+
+    sys::catch_interrupts();
+
+    let mut last = snapshot(&watched);
+    eprintln!("mk: > mk {}", server.join(" "));
+    let mut running = start(spawn, &server, project_root);
+
+    loop {
+        thread::sleep(POLL_INTERVAL);
+
+        if sys::interrupted() {
+            eprintln!("mk: Stopping.");
+            if let Some(mut child) = running.take() {
+                sys::terminate(&mut child, STOP_GRACE);
+            }
+            return 130;
+        }
+
+        let exited = running
+            .as_mut()
+            .and_then(|child| child.try_wait().ok())
+            .and_then(|status| status);
+        if let Some(status) = exited {
+            eprintln!(
+                "mk: '{}' exited ({}), it's restarted on the next change.",
+                server.join(" "),
+                status
+            );
+            running = None;
+        }
+
+        let current = snapshot(&watched);
+        if changes(&last, &current).is_empty() {
+            continue;
+        }
+        let current = settle(&watched, current);
+
+        let changed = changes(&last, &current);
+        last = current;
+        if changed.is_empty() {
+            continue;
+        }
+        print_changes(&changed, project_root);
+
+        if let Some(mut child) = running.take() {
+            sys::terminate(&mut child, STOP_GRACE);
+        }
+
+        if !run_on_change(spawn, &on_change, project_root) {
+            eprintln!("mk: Stopping.");
+            return 130;
+        }
+
+        eprintln!("mk: > mk {}", server.join(" "));
+        running = start(spawn, &server, project_root);
+    }
+}
+
+// 'mk --watch'. Paths are relative to 'invocation_dir'. Without '--', the leading args that
+// exist as files or dirs are the paths.
+pub fn watch(
+    args: &[String],
+    config: &Config,
+    project_root: &str,
+    invocation_dir: &str,
+    spawn: &dyn Fn(&[String]) -> Command,
+) -> i32 {
+    let (paths, task) = match args.iter().position(|a| a == "--") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => {
+            let n = args
+                .iter()
+                .take(args.len().saturating_sub(1))
+                .take_while(|a| Path::new(invocation_dir).join(a).exists())
+                .count();
+            args.split_at(n)
+        }
+    };
+    if task.is_empty() {
+        eprintln!("{}", WATCH_USAGE);
+        return 2;
+    }
+
+    let watched: Vec<PathBuf> = if paths.is_empty() {
+        vec![PathBuf::from(project_root)]
+    } else {
+        paths
+            .iter()
+            .map(|p| Path::new(invocation_dir).join(p))
+            .collect()
+    };
+    for path in &watched {
+        if !path.exists() {
+            eprintln!("mk: Cannot watch {}, it doesn't exist.", path.display());
+            return 2;
+        }
+    }
+
+    let clear = config.get_bool("watch_clear", false);
+    let label = task.join(" ");
+
+    sys::catch_interrupts();
+
+    loop {
+        eprintln!("mk: > mk {}", label);
+        if let Some(mut child) = start(spawn, task, project_root) {
+            match wait(&mut child) {
+                Some(status) if status.success() => eprintln!("mk: '{}' succeeded.", label),
+                Some(status) => eprintln!("mk: '{}' failed ({}).", label, status),
+                None => {
+                    eprintln!("mk: Stopping.");
+                    return 130;
+                }
+            }
+        }
+        eprintln!("mk: Waiting for changes, Ctrl+C to stop.");
+
+        // Taken after the run, so files the task writes itself don't trigger the next one.
+        let last = snapshot(&watched);
+        let changed = loop {
+            thread::sleep(POLL_INTERVAL);
+            if sys::interrupted() {
+                eprintln!("mk: Stopping.");
+                return 130;
+            }
+            let current = snapshot(&watched);
+            if changes(&last, &current).is_empty() {
+                continue;
+            }
+            let changed = changes(&last, &settle(&watched, current));
+            if !changed.is_empty() {
+                break changed;
+            }
+        };
+
+        if clear {
+            eprint!("{}", CLEAR_SCREEN);
+        }
+        print_changes(&changed, project_root);
+    }
+}
@@ -0,0 +1,259 @@
+// 'mk doctor': checks for common, baffling failure modes and prints exact fixes.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use cache::CacheStore;
+use config::Config;
+use sys;
+use table;
+
+struct Problem {
+    path: String,
+    what: String,
+    fix: String,
+}
+
+// Files owned by another user (typically root after a 'sudo mk ...') or without write
+// permission make mk and uv/poetry fail in confusing ways.
+fn check_ownership(path: &str, alternative: Option<&str>, problems: &mut Vec<Problem>) {
+    let meta = match fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => return,
+    };
+
+    let euid = match sys::euid() {
+        Some(euid) => euid,
+        None => return,
+    };
+
+    let recursive = if meta.is_dir() { "-R " } else { "" };
+    let alternative = alternative
+        .map(|a| format!("\n      or: {}", a))
+        .unwrap_or_default();
+
+    match sys::owner(path) {
+        Some(owner) if owner != euid && euid != 0 => problems.push(Problem {
+            path: path.to_string(),
+            what: format!(
+                "{} is owned by uid {}, not by you (uid {})",
+                path, owner, euid
+            ),
+            fix: format!(
+                "sudo chown {}$(id -un) '{}'{}",
+                recursive, path, alternative
+            ),
+        }),
+        _ if meta.permissions().readonly() && euid != 0 => problems.push(Problem {
+            path: path.to_string(),
+            what: format!("{} is not writable", path),
+            fix: format!("chmod {}u+w '{}'{}", recursive, path, alternative),
+        }),
+        _ => {}
+    }
+}
+
+pub fn run(
+    cache_dir: &str,
+    store: &CacheStore,
+    cache_keys: &[String],
+    project_root: Option<&str>,
+) -> i32 {
+    let mut problems = Vec::new();
+    let mut checked = Vec::new();
+
+    let cache_location = store.location();
+    checked.push(cache_dir.to_string());
+    check_ownership(cache_dir, None, &mut problems);
+    checked.push(cache_location.clone());
+    check_ownership(
+        &cache_location,
+        Some(&format!("rm '{}' (mk recreates it)", cache_location)),
+        &mut problems,
+    );
+
+    // Venvs the project resolves to, and the conventional in-project venv.
+    let mut venvs: Vec<String> = cache_keys.iter().filter_map(|k| store.get(k)).collect();
+    if let Some(project_root) = project_root {
+        let local_venv = format!("{}/.venv", project_root);
+        if Path::new(&local_venv).is_dir() {
+            venvs.push(local_venv);
+        }
+    }
+    venvs.sort();
+    venvs.dedup();
+
+    for venv in &venvs {
+        let count = problems.len();
+        checked.push(venv.clone());
+        check_ownership(venv, None, &mut problems);
+
+        // The fix for the venv covers its bin dir as well.
+        if problems.len() == count {
+            let bin_dir = crate::venv_bin_dir(venv);
+            checked.push(bin_dir.clone());
+            check_ownership(&bin_dir, None, &mut problems);
+        }
+    }
+
+    for path in &checked {
+        if Path::new(path).exists() && !problems.iter().any(|p| &p.path == path) {
+            println!("ok       {}", path);
+        }
+    }
+
+    if problems.is_empty() {
+        println!("\nNo problems found.");
+        return 0;
+    }
+
+    for problem in &problems {
+        println!("problem  {}\n  fix: {}", problem.what, problem.fix);
+    }
+
+    1
+}
+
+// Run inside the venv with the task's environment, prints what the interpreter sees as
+// 'key=value' lines. The args are modules to import.
+const PROBE: &str = r#"
+import importlib, os, shutil, sys, sysconfig
+print("executable=" + sys.executable)
+print("prefix=" + sys.prefix)
+print("virtual_env=" + os.environ.get("VIRTUAL_ENV", ""))
+print("path_head=" + os.environ.get("PATH", "").split(os.pathsep)[0])
+print("which_python=" + (shutil.which("python") or ""))
+purelib = sysconfig.get_paths()["purelib"]
+print("purelib=" + purelib)
+print("purelib_on_path=" + str(purelib in sys.path))
+for name in sys.argv[1:]:
+    try:
+        importlib.import_module(name)
+        print("import " + name + "=ok")
+    except Exception as e:
+        print("import " + name + "=" + type(e).__name__ + ": " + str(e))
+"#;
+
+fn same_path(a: &str, b: &str) -> bool {
+    !a.is_empty()
+        && !b.is_empty()
+        && fs::canonicalize(a)
+            .ok()
+            .is_some_and(|a| Some(a) == fs::canonicalize(b).ok())
+}
+
+// Check from inside the venv that tasks get the environment mk means to give them: the
+// interpreter, VIRTUAL_ENV, the head of PATH, the venv's site-packages and the modules in
+// 'doctor_imports'. 'task' is the command make.py would run with.
+pub fn verify_env(
+    config: &Config,
+    task: &Command,
+    python_bin: &str,
+    venv_path: &str,
+    path_head: &str,
+) -> i32 {
+    let imports = config.get_list("doctor_imports").unwrap_or_default();
+
+    let mut probe = Command::new(python_bin);
+    probe.arg("-c").arg(PROBE).args(&imports);
+    for (name, value) in task.get_envs() {
+        match value {
+            Some(value) => probe.env(name, value),
+            None => probe.env_remove(name),
+        };
+    }
+    probe.stdin(Stdio::null()).stderr(Stdio::inherit());
+
+    println!("\nTask environment:");
+    let output = match probe.output() {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            println!("problem  {} returned {}", python_bin, output.status);
+            return 1;
+        }
+        Err(e) => {
+            println!("problem  cannot run {}: {}", python_bin, e);
+            return 1;
+        }
+    };
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let seen = |key: &str| {
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+            .unwrap_or("")
+            .to_string()
+    };
+
+    let virtual_env = task
+        .get_envs()
+        .find(|(name, _)| *name == "VIRTUAL_ENV")
+        .and_then(|(_, value)| value)
+        .map(|value| value.to_string_lossy().to_string());
+
+    // Check, whether it passed, and what was seen.
+    let mut results: Vec<(String, bool, String)> = vec![
+        (
+            "sys.executable".to_string(),
+            same_path(&seen("executable"), python_bin),
+            seen("executable"),
+        ),
+        (
+            "sys.prefix".to_string(),
+            same_path(&seen("prefix"), venv_path),
+            seen("prefix"),
+        ),
+        (
+            "VIRTUAL_ENV".to_string(),
+            match &virtual_env {
+                Some(expected) => seen("virtual_env") == *expected,
+                None => true,
+            },
+            seen("virtual_env"),
+        ),
+        (
+            "PATH head".to_string(),
+            seen("path_head") == path_head,
+            seen("path_head"),
+        ),
+        (
+            "python on PATH".to_string(),
+            same_path(&seen("which_python"), python_bin)
+                || Path::new(&seen("which_python")).starts_with(path_head),
+            seen("which_python"),
+        ),
+        (
+            "site-packages".to_string(),
+            seen("purelib_on_path") == "True",
+            seen("purelib"),
+        ),
+    ];
+    for name in &imports {
+        let result = seen(&format!("import {}", name));
+        results.push((format!("import {}", name), result == "ok", result));
+    }
+
+    let mut rows = vec![vec![
+        "check".to_string(),
+        "result".to_string(),
+        "seen".to_string(),
+    ]];
+    for (check, ok, seen) in &results {
+        let result = if *ok { "ok" } else { "problem" };
+        rows.push(vec![check.clone(), result.to_string(), seen.clone()]);
+    }
+    for line in table::format(&rows, 0, table::max_width(config)) {
+        println!("{}", line);
+    }
+
+    if results.iter().all(|(_, ok, _)| *ok) {
+        0
+    } else {
+        println!(
+            "\nTasks don't get the environment mk means to give them, see the problems above."
+        );
+        1
+    }
+}
@@ -0,0 +1,175 @@
+// '.env' files, read into the environment of tasks so make.py doesn't need python-dotenv:
+//
+//     # comment
+//     DATABASE_URL=postgres://localhost/app
+//     export SECRET_KEY='not$expanded'
+//     GREETING="Hello\nWorld"
+//     CACHE_DIR=${HOME}/.cache/app
+//
+// The syntax is python-dotenv's: values can be unquoted (up to a ' #' comment), in single
+// quotes (literal) or in double quotes (with escapes, may span lines). '${NAME}' and
+// '${NAME:-default}' are expanded in unquoted and double quoted values, from the file
+// itself and the environment.
+
+use std::env;
+use std::fs;
+
+fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+// Expand '${NAME}' and '${NAME:-default}' in 'value'.
+fn expand(value: &str, vars: &[(String, String)]) -> String {
+    let lookup = |name: &str| {
+        vars.iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.clone())
+            .or_else(|| env::var(name).ok())
+    };
+
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        out.push_str(&rest[..start]);
+        let inner = &rest[start + 2..end];
+        let (name, default) = match inner.find(":-") {
+            Some(i) => (&inner[..i], &inner[i + 2..]),
+            None => (inner, ""),
+        };
+        match lookup(name) {
+            Some(value) if !value.is_empty() => out.push_str(&value),
+            _ => out.push_str(default),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+// The value of a double quoted string starting after its opening quote in 'text', and the
+// rest of 'text' after its closing quote.
+fn double_quoted(text: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &text[i + 1..])),
+            '\\' => match chars.next().map(|(_, c)| c) {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('r') => value.push('\r'),
+                Some(c @ '"') | Some(c @ '\\') | Some(c @ '\'') => value.push(c),
+                Some(c) => {
+                    value.push('\\');
+                    value.push(c);
+                }
+                None => value.push('\\'),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+// The variables of a '.env' file, in order. Lines that can't be parsed are skipped with a
+// warning.
+fn parse(text: &str, path: &str) -> Vec<(String, String)> {
+    let mut vars: Vec<(String, String)> = Vec::new();
+    let mut rest = text;
+    let mut line_no = 0;
+
+    while !rest.is_empty() {
+        line_no += 1;
+        let (line, after) = match rest.find('\n') {
+            Some(i) => (&rest[..i], &rest[i + 1..]),
+            None => (rest, ""),
+        };
+        rest = after;
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+
+        let (name, value) = match line.find('=') {
+            Some(i) => (line[..i].trim(), line[i + 1..].trim_start()),
+            None => {
+                crate::warn(&format!("Ignoring line {} of {}: no '='.", line_no, path));
+                continue;
+            }
+        };
+        if !valid_name(name) {
+            crate::warn(&format!(
+                "Ignoring line {} of {}: invalid name '{}'.",
+                line_no, path, name
+            ));
+            continue;
+        }
+
+        let value = if let Some(quoted) = value.strip_prefix('\'') {
+            match quoted.find('\'') {
+                Some(end) => quoted[..end].to_string(),
+                None => {
+                    crate::warn(&format!(
+                        "Ignoring line {} of {}: no closing '.",
+                        line_no, path
+                    ));
+                    continue;
+                }
+            }
+        } else if value.starts_with('"') {
+            // The value may go on over the next lines.
+            let start = value.as_ptr() as usize - text.as_ptr() as usize + 1;
+            match double_quoted(&text[start..]) {
+                Some((value, after)) => {
+                    let consumed = &text[start..text.len() - after.len()];
+                    line_no += consumed.matches('\n').count();
+                    rest = match after.find('\n') {
+                        Some(i) => &after[i + 1..],
+                        None => "",
+                    };
+                    expand(&value, &vars)
+                }
+                None => {
+                    crate::warn(&format!(
+                        "Ignoring line {} of {}: no closing \".",
+                        line_no, path
+                    ));
+                    continue;
+                }
+            }
+        } else {
+            let value = match value.find(" #") {
+                Some(i) => &value[..i],
+                None => value,
+            };
+            expand(value.trim_end(), &vars)
+        };
+
+        vars.retain(|(n, _)| n != name);
+        vars.push((name.to_string(), value));
+    }
+
+    vars
+}
+
+// The variables of the file at 'path' that aren't set in mk's environment, which takes
+// precedence.
+pub fn load(path: &str) -> Result<Vec<(String, String)>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    Ok(parse(&text, path)
+        .into_iter()
+        .filter(|(name, _)| env::var_os(name).is_none())
+        .collect())
+}
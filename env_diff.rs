@@ -0,0 +1,76 @@
+// 'mk env diff': what mk changes in the environment of tasks, compared to the current
+// shell. PATH-like variables are compared entry by entry, as a long PATH that got one dir
+// prepended is hard to read as a whole.
+
+use std::env;
+use std::ffi::OsStr;
+use std::process::Command;
+
+// Variables holding lists of paths.
+const PATH_VARS: &[&str] = &["PATH", "PYTHONPATH", "LD_LIBRARY_PATH", "DYLD_LIBRARY_PATH"];
+
+fn print_path_diff(name: &str, old: &OsStr, new: &OsStr) {
+    let old: Vec<String> = env::split_paths(old)
+        .map(|p| p.display().to_string())
+        .collect();
+    let new: Vec<String> = env::split_paths(new)
+        .map(|p| p.display().to_string())
+        .collect();
+
+    println!("~ {}", name);
+    for (i, entry) in new.iter().enumerate() {
+        if !old.contains(entry) {
+            println!("    + {} (entry {} of {})", entry, i + 1, new.len());
+        }
+    }
+    for entry in &old {
+        if !new.contains(entry) {
+            println!("    - {}", entry);
+        }
+    }
+    if old.iter().all(|e| new.contains(e)) && new.iter().all(|e| old.contains(e)) {
+        println!("    (same entries, different order)");
+    }
+}
+
+pub fn run(args: &[String], task: &Command) -> i32 {
+    if args.first().map(|a| a.as_str()) != Some("diff") || args.len() > 1 {
+        eprintln!("Usage: mk env diff");
+        return 2;
+    }
+
+    let mut changes: Vec<(&OsStr, Option<&OsStr>)> = task.get_envs().collect();
+    changes.sort();
+
+    let mut changed = 0;
+
+    for (name, value) in changes {
+        let name_str = name.to_string_lossy();
+        let old = env::var_os(name);
+
+        match (old, value) {
+            (None, Some(new)) => println!("+ {}={}", name_str, new.to_string_lossy()),
+            (Some(_), None) => println!("- {}", name_str),
+            (Some(old), Some(new)) if old.as_os_str() != new => {
+                if PATH_VARS.contains(&name_str.as_ref()) {
+                    print_path_diff(&name_str, &old, new);
+                } else {
+                    println!(
+                        "~ {}: {} -> {}",
+                        name_str,
+                        old.to_string_lossy(),
+                        new.to_string_lossy()
+                    );
+                }
+            }
+            _ => continue,
+        }
+        changed += 1;
+    }
+
+    if changed == 0 {
+        println!("mk doesn't change the environment.");
+    }
+
+    0
+}
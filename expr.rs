@@ -0,0 +1,220 @@
+// A small expression language for conditions in the config, e.g.
+// 'when = "env.CI == \'1\' and platform != \'windows\'"'.
+//
+//     expr    := or
+//     or      := and (('or' | '||') and)*
+//     and     := not (('and' | '&&') not)*
+//     not     := ('not' | '!') not | compare
+//     compare := primary (('==' | '!=') primary)?
+//     primary := 'string' | "string" | true | false | name | '(' expr ')'
+//
+// Names are 'platform' ('linux', 'macos', 'windows', ...), 'arch' ('x86_64', 'aarch64',
+// ...) and 'env.NAME' (empty if unset). Strings are true if not empty.
+
+use std::env;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Str(String),
+    Bool(bool),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Str(s) => !s.is_empty(),
+            Value::Bool(b) => *b,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Str(String),
+    Name(String),
+    Eq,
+    Ne,
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).cloned();
+
+        match c {
+            ' ' | '\t' => i += 1,
+            '(' => {
+                tokens.push(Token::Open);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            '=' if next == Some('=') => {
+                tokens.push(Token::Eq);
+                i += 2;
+            }
+            '!' if next == Some('=') => {
+                tokens.push(Token::Ne);
+                i += 2;
+            }
+            '!' => {
+                tokens.push(Token::Not);
+                i += 1;
+            }
+            '&' if next == Some('&') => {
+                tokens.push(Token::And);
+                i += 2;
+            }
+            '|' if next == Some('|') => {
+                tokens.push(Token::Or);
+                i += 2;
+            }
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&q| q == c)
+                    .ok_or_else(|| format!("unterminated string at column {}", i + 1))?;
+                tokens.push(Token::Str(chars[i + 1..i + 1 + end].iter().collect()));
+                i += end + 2;
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_alphanumeric() || **c == '_' || **c == '.')
+                    .count();
+                let word: String = chars[i..i + len].iter().collect();
+                tokens.push(match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Name(word),
+                });
+                i += len;
+            }
+            c => return Err(format!("unexpected '{}' at column {}", c, i + 1)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn platform() -> &'static str {
+    match env::consts::OS {
+        "macos" => "macos",
+        os => os,
+    }
+}
+
+fn lookup(name: &str) -> Result<Value, String> {
+    if let Some(var) = name.strip_prefix("env.") {
+        return Ok(Value::Str(env::var(var).unwrap_or_default()));
+    }
+
+    match name {
+        "platform" => Ok(Value::Str(platform().to_string())),
+        "arch" => Ok(Value::Str(env::consts::ARCH.to_string())),
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ => Err(format!("unknown name '{}'", name)),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Value, String> {
+        let mut value = self.and()?;
+        while self.eat(&Token::Or) {
+            let rhs = self.and()?;
+            value = Value::Bool(value.truthy() || rhs.truthy());
+        }
+        Ok(value)
+    }
+
+    fn and(&mut self) -> Result<Value, String> {
+        let mut value = self.not()?;
+        while self.eat(&Token::And) {
+            let rhs = self.not()?;
+            value = Value::Bool(value.truthy() && rhs.truthy());
+        }
+        Ok(value)
+    }
+
+    fn not(&mut self) -> Result<Value, String> {
+        if self.eat(&Token::Not) {
+            return Ok(Value::Bool(!self.not()?.truthy()));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Value, String> {
+        let lhs = self.primary()?;
+        if self.eat(&Token::Eq) {
+            return Ok(Value::Bool(lhs == self.primary()?));
+        }
+        if self.eat(&Token::Ne) {
+            return Ok(Value::Bool(lhs != self.primary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn primary(&mut self) -> Result<Value, String> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+
+        match token {
+            Some(Token::Str(s)) => Ok(Value::Str(s)),
+            Some(Token::Name(name)) => lookup(&name),
+            Some(Token::Open) => {
+                let value = self.or()?;
+                if !self.eat(&Token::Close) {
+                    return Err("missing ')'".to_string());
+                }
+                Ok(value)
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+}
+
+pub fn eval(text: &str) -> Result<bool, String> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        pos: 0,
+    };
+
+    let value = parser.or()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(format!("unexpected {:?}", parser.tokens[parser.pos]));
+    }
+
+    Ok(value.truthy())
+}
@@ -0,0 +1,81 @@
+// Tasks that are run by another build system, for repos moving to Bazel or Pants, so
+// 'mk test' keeps working while the tasks move over one by one:
+//
+//     [facade]
+//     tool = "bazel"
+//
+//     [facade.task]
+//     test = "test //..."
+//     build = ["build", "//src/..."]
+//
+// 'mk test -k foo' then runs 'bazel test //... -k foo' in the project root, with the
+// environment of the config. No venv is resolved for it. Config tasks take precedence over
+// mapped tasks, and mapped tasks over make.py tasks of the same name.
+
+use std::process;
+use std::process::Command;
+
+use config::Config;
+use limits;
+use tasks;
+use toml::Value;
+
+// A string is split like a command line, a list taken as it is.
+pub fn words(key: &str, value: &Value) -> Vec<String> {
+    match value {
+        Value::String(line) => tasks::split_words(line),
+        Value::Array(items) if items.iter().all(|item| item.as_str().is_some()) => items
+            .iter()
+            .filter_map(|item| item.as_str().map(|s| s.to_string()))
+            .collect(),
+        _ => {
+            eprintln!("mk: '{}' must be a command or a list of words.", key);
+            process::exit(1);
+        }
+    }
+}
+
+// The tool of the facade, e.g. ["bazel"] or ["./pants"].
+fn tool(config: &Config, name: &str) -> Vec<String> {
+    let tool = config
+        .get_value("facade.tool")
+        .map(|value| words("facade.tool", value))
+        .unwrap_or_default();
+    if tool.is_empty() {
+        eprintln!(
+            "mk: 'facade.task.{}' needs 'facade.tool', e.g. tool = \"bazel\".",
+            name
+        );
+        process::exit(1);
+    }
+    tool
+}
+
+// Names of the mapped tasks, sorted.
+pub fn names(config: &Config) -> Vec<String> {
+    config
+        .values()
+        .into_iter()
+        .filter_map(|(key, _)| key.strip_prefix("facade.task.").map(|n| n.to_string()))
+        .collect()
+}
+
+// The command line task 'name' is mapped to, without the args of the run. None if it isn't
+// mapped.
+pub fn words_of(config: &Config, name: &str) -> Option<Vec<String>> {
+    let key = format!("facade.task.{}", name);
+    let mapped = words(&key, config.get_value(&key)?);
+    Some(tool(config, name).into_iter().chain(mapped).collect())
+}
+
+// The command of task 'name' with 'args', run in 'dir'.
+pub fn command(config: &Config, name: &str, args: &[String], dir: &str) -> Option<Command> {
+    let words = words_of(config, name)?;
+    let mut cmd = Command::new(&words[0]);
+    cmd.args(&words[1..])
+        .args(args)
+        .envs(config.env())
+        .current_dir(dir);
+    limits::apply(&mut cmd, limits::for_task(config, Some(name)));
+    Some(cmd)
+}
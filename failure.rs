@@ -0,0 +1,87 @@
+// Failure classification ('classify_failures'). Tells apart a task that crashed with an
+// uncaught exception, one that failed on purpose (nonzero exit), one that was killed by
+// a signal, and mk failing to start it, so CI can react to each differently, e.g. only
+// retry infrastructure failures.
+
+use std::process::ExitStatus;
+
+// Exit codes mk uses for the classes that don't have the task's own.
+pub const EXIT_CRASHED: i32 = 70;
+pub const EXIT_INFRASTRUCTURE: i32 = 125;
+
+pub enum Kind {
+    Success,
+    // Nonzero exit code, chosen by the task.
+    Failed(i32),
+    // Uncaught exception, with its last traceback line if found.
+    Crashed(Option<String>),
+    Killed(i32),
+}
+
+#[cfg(unix)]
+fn signal(status: &ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn signal(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+// The exit code a shell would report for 'status': the task's own, or 128 + the signal it
+// was killed by, e.g. 130 after Ctrl+C.
+pub fn status_code(status: &ExitStatus) -> i32 {
+    match (status.code(), signal(status)) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => 1,
+    }
+}
+
+pub fn classify(status: &ExitStatus, stderr_tail: &[u8]) -> Kind {
+    if status.success() {
+        return Kind::Success;
+    }
+    if let Some(signal) = signal(status) {
+        return Kind::Killed(signal);
+    }
+
+    let code = status.code().unwrap_or(1);
+    let tail = String::from_utf8_lossy(stderr_tail);
+
+    // Python exits with 1 after printing the traceback.
+    let traceback = match tail.rfind("Traceback (most recent call last):") {
+        Some(start) if code == 1 => &tail[start..],
+        _ => return Kind::Failed(code),
+    };
+
+    // The traceback ends with an unindented 'ValueError: message' line.
+    let exception = traceback
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty() && !line.starts_with(' '))
+        .filter(|line| !line.starts_with("Traceback"))
+        .map(|line| line.trim().to_string());
+
+    Kind::Crashed(exception)
+}
+
+pub fn exit_code(kind: &Kind) -> i32 {
+    match kind {
+        Kind::Success => 0,
+        Kind::Failed(code) => *code,
+        Kind::Crashed(_) => EXIT_CRASHED,
+        Kind::Killed(signal) => 128 + signal,
+    }
+}
+
+pub fn describe(kind: &Kind, task: &str) -> Option<String> {
+    match kind {
+        Kind::Success => None,
+        Kind::Failed(code) => Some(format!("'{}' failed with exit code {}", task, code)),
+        Kind::Crashed(Some(exception)) => Some(format!("'{}' crashed: {}", task, exception)),
+        Kind::Crashed(None) => Some(format!("'{}' crashed with an uncaught exception", task)),
+        Kind::Killed(signal) => Some(format!("'{}' was killed by signal {}", task, signal)),
+    }
+}
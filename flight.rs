@@ -0,0 +1,455 @@
+// '--flight-recorder <file>' and 'mk report <file>', for tasks that are slow now and then,
+// e.g. on a laptop with a busy disk or a flaky network. While mk runs, it records when each
+// of its phases started and, every SAMPLE_INTERVAL, the resources the task and the
+// processes it started use: CPU time, memory and disk I/O. The report shows where the time
+// went, and the stretches in which the task was idle, i.e. waiting for something.
+//
+// Samples are read from /proc, so they're Linux only. Elsewhere only the phases are
+// recorded.
+
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use json;
+use json::Json;
+use table;
+
+const FORMAT_VERSION: i64 = 1;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+
+// A task is idle while it uses less CPU than this, in percent of a core, and does no I/O.
+const IDLE_CPU: u64 = 5;
+
+// Idle stretches shorter than this are normal and not reported.
+const IDLE_REPORTED: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Default)]
+struct Sample {
+    // Since mk started.
+    at_ms: u64,
+    // CPU time of the task and the processes it started, so far.
+    cpu_ms: u64,
+    rss_kb: u64,
+    // Bytes read from and written to disk by the processes running now.
+    read_bytes: u64,
+    write_bytes: u64,
+    processes: u64,
+}
+
+pub struct Recorder {
+    started: SystemTime,
+    // Phases, by when they started.
+    phases: Mutex<Vec<(String, SystemTime)>>,
+    samples: Arc<Mutex<Vec<Sample>>>,
+}
+
+fn since(start: SystemTime, time: SystemTime) -> u64 {
+    time.duration_since(start).unwrap_or_default().as_millis() as u64
+}
+
+#[cfg(target_os = "linux")]
+mod proc_fs {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::process;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::SystemTime;
+
+    use super::{since, Sample, SAMPLE_INTERVAL};
+
+    extern "C" {
+        fn sysconf(name: i32) -> i64;
+    }
+
+    const SC_CLK_TCK: i32 = 2;
+
+    // Parent, and CPU ticks of the process and its waited-for children, from
+    // '/proc/<pid>/stat'.
+    fn stat(pid: u32) -> Option<(u32, u64, u64)> {
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // The command name in parens may contain spaces.
+        let fields: Vec<&str> = stat.rsplit_once(") ")?.1.split(' ').collect();
+        let number = |i: usize| fields.get(i).and_then(|f| f.parse::<u64>().ok());
+        let ppid = number(1)? as u32;
+        let own = number(11)? + number(12)?;
+        let children = number(13)? + number(14)?;
+        Some((ppid, own, children))
+    }
+
+    fn status_kb(pid: u32, name: &str) -> u64 {
+        fs::read_to_string(format!("/proc/{}/status", pid))
+            .ok()
+            .and_then(|status| {
+                status
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .and_then(|rest| rest.trim().trim_end_matches("kB").trim().parse().ok())
+            })
+            .unwrap_or(0)
+    }
+
+    fn io(pid: u32) -> (u64, u64) {
+        let io = fs::read_to_string(format!("/proc/{}/io", pid)).unwrap_or_default();
+        let field = |name: &str| {
+            io.lines()
+                .find_map(|line| line.strip_prefix(name))
+                .and_then(|rest| rest.trim().parse().ok())
+                .unwrap_or(0)
+        };
+        (field("read_bytes:"), field("write_bytes:"))
+    }
+
+    // The processes mk started, directly or not, with mk's own ticks for those that
+    // exited.
+    fn sample(at_ms: u64) -> Sample {
+        let me = process::id();
+        let mut children: HashMap<u32, Vec<(u32, u64)>> = HashMap::new();
+        for entry in fs::read_dir("/proc").into_iter().flatten().flatten() {
+            let pid = match entry.file_name().to_string_lossy().parse::<u32>() {
+                Ok(pid) => pid,
+                Err(_) => continue,
+            };
+            if let Some((ppid, own, waited)) = stat(pid) {
+                children.entry(ppid).or_default().push((pid, own + waited));
+            }
+        }
+
+        let mut ticks = stat(me).map_or(0, |(_, _, waited)| waited);
+        let mut sample = Sample {
+            at_ms,
+            ..Sample::default()
+        };
+        let mut queue = vec![me];
+        while let Some(parent) = queue.pop() {
+            for (pid, pid_ticks) in children.get(&parent).into_iter().flatten() {
+                ticks += pid_ticks;
+                let (read, write) = io(*pid);
+                sample.read_bytes += read;
+                sample.write_bytes += write;
+                sample.rss_kb += status_kb(*pid, "VmRSS:");
+                sample.processes += 1;
+                queue.push(*pid);
+            }
+        }
+
+        let per_second = match unsafe { sysconf(SC_CLK_TCK) } {
+            n if n > 0 => n as u64,
+            _ => 100,
+        };
+        sample.cpu_ms = ticks * 1000 / per_second;
+        sample
+    }
+
+    // Add a sample to 'samples' every SAMPLE_INTERVAL, until mk exits.
+    pub fn sample_in_background(started: SystemTime, samples: Arc<Mutex<Vec<Sample>>>) {
+        thread::spawn(move || loop {
+            thread::sleep(SAMPLE_INTERVAL);
+            let sample = sample(since(started, SystemTime::now()));
+            if let Ok(mut samples) = samples.lock() {
+                samples.push(sample);
+            }
+        });
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod proc_fs {
+    use std::sync::{Arc, Mutex};
+    use std::time::SystemTime;
+
+    use super::Sample;
+
+    pub fn sample_in_background(_started: SystemTime, _samples: Arc<Mutex<Vec<Sample>>>) {}
+}
+
+impl Recorder {
+    // Start recording, with the 'startup' phase at 'started', when mk started.
+    pub fn start(started: SystemTime) -> Recorder {
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        proc_fs::sample_in_background(started, samples.clone());
+
+        Recorder {
+            started,
+            phases: Mutex::new(vec![("startup".to_string(), started)]),
+            samples,
+        }
+    }
+
+    // Phase 'name' started at 'time'.
+    pub fn phase_at(&self, name: &str, time: SystemTime) {
+        if let Ok(mut phases) = self.phases.lock() {
+            phases.push((name.to_string(), time));
+        }
+    }
+
+    pub fn phase(&self, name: &str) {
+        self.phase_at(name, SystemTime::now());
+    }
+
+    // Write the recording of the run of 'args' so far to 'path'.
+    pub fn write(&self, path: &str, args: &[String], exit_code: Option<i32>) -> Result<(), String> {
+        let now = SystemTime::now();
+        let phases: Vec<String> = self
+            .phases
+            .lock()
+            .map(|phases| phases.clone())
+            .unwrap_or_default()
+            .iter()
+            .map(|(name, start)| {
+                format!(
+                    "\n    {{ \"name\": {}, \"start_ms\": {} }}",
+                    json::quote(name),
+                    since(self.started, *start)
+                )
+            })
+            .collect();
+        let samples: Vec<String> = self
+            .samples
+            .lock()
+            .map(|samples| samples.clone())
+            .unwrap_or_default()
+            .iter()
+            .map(|s| {
+                format!(
+                    "\n    [{}, {}, {}, {}, {}, {}]",
+                    s.at_ms, s.cpu_ms, s.rss_kb, s.read_bytes, s.write_bytes, s.processes
+                )
+            })
+            .collect();
+        let args: Vec<String> = args.iter().map(|arg| json::quote(arg)).collect();
+
+        let content = format!(
+            "{{\n  \"version\": {},\n  \"mk\": {},\n  \"recorded_at\": {},\n  \"args\": [{}],\n  \"exit_code\": {},\n  \"duration_ms\": {},\n  \"phases\": [{}\n  ],\n  \"sample_columns\": [\"at_ms\", \"cpu_ms\", \"rss_kb\", \"read_bytes\", \"write_bytes\", \"processes\"],\n  \"samples\": [{}\n  ]\n}}\n",
+            FORMAT_VERSION,
+            json::quote(crate::VERSION),
+            self.started
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            args.join(", "),
+            exit_code.map_or("null".to_string(), |code| code.to_string()),
+            since(self.started, now),
+            phases.join(","),
+            samples.join(",")
+        );
+
+        fs::write(path, content).map_err(|e| format!("cannot write {}: {}", path, e))
+    }
+}
+
+fn seconds(ms: u64) -> String {
+    match ms {
+        0..=999 => format!("{}ms", ms),
+        _ => format!("{:.1}s", ms as f64 / 1000.0),
+    }
+}
+
+fn bytes(n: u64) -> String {
+    match n {
+        0..=1023 => format!("{}B", n),
+        1024..=1048575 => format!("{:.1}KB", n as f64 / 1024.0),
+        1048576..=1073741823 => format!("{:.1}MB", n as f64 / 1048576.0),
+        _ => format!("{:.1}GB", n as f64 / 1073741824.0),
+    }
+}
+
+struct Recording {
+    doc: Json,
+    // Phases, by when they started.
+    phases: Vec<(String, u64)>,
+    samples: Vec<Sample>,
+}
+
+fn read(path: &str) -> Result<Recording, String> {
+    let doc = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| json::parse(&text))?;
+    match doc.get("version").and_then(|v| v.as_i64()) {
+        Some(FORMAT_VERSION) => {}
+        Some(version) => {
+            return Err(format!(
+                "it has version {}, this mk reads version {}",
+                version, FORMAT_VERSION
+            ))
+        }
+        None => return Err("it's not an mk flight recording".to_string()),
+    }
+
+    let number = |value: &Json| value.as_i64().map_or(0, |n| n.max(0) as u64);
+    let phases = doc
+        .get("phases")
+        .and_then(|p| p.as_array())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|phase| {
+            let name = phase.get("name")?.as_str()?.to_string();
+            Some((name, number(phase.get("start_ms")?)))
+        })
+        .collect();
+    let samples = doc
+        .get("samples")
+        .and_then(|s| s.as_array())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|sample| {
+            let values: Vec<u64> = sample.as_array()?.iter().map(number).collect();
+            match values.as_slice() {
+                [at_ms, cpu_ms, rss_kb, read_bytes, write_bytes, processes] => Some(Sample {
+                    at_ms: *at_ms,
+                    cpu_ms: *cpu_ms,
+                    rss_kb: *rss_kb,
+                    read_bytes: *read_bytes,
+                    write_bytes: *write_bytes,
+                    processes: *processes,
+                }),
+                _ => None,
+            }
+        })
+        .collect();
+    Ok(Recording {
+        doc,
+        phases,
+        samples,
+    })
+}
+
+// 'mk report <file>'. Returns the exit code.
+pub fn report(args: &[String], max_width: Option<usize>) -> i32 {
+    let path = match args {
+        [path] => path,
+        _ => {
+            eprintln!("Usage: mk report <file>");
+            return 2;
+        }
+    };
+    let Recording {
+        doc,
+        phases,
+        samples,
+    } = match read(path) {
+        Ok(recording) => recording,
+        Err(e) => {
+            eprintln!("mk: Cannot read flight recording {}: {}.", path, e);
+            return 1;
+        }
+    };
+
+    let duration = doc.get("duration_ms").and_then(|d| d.as_i64()).unwrap_or(0) as u64;
+    let args: Vec<&str> = doc
+        .get("args")
+        .and_then(|a| a.as_array())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|arg| arg.as_str())
+        .collect();
+    let outcome = match doc.get("exit_code").and_then(|c| c.as_i64()) {
+        Some(code) => format!("exit code {}", code),
+        None => "no exit code".to_string(),
+    };
+    println!(
+        "mk {} (mk {}): {}, {}",
+        args.join(" "),
+        doc.get("mk").and_then(|v| v.as_str()).unwrap_or("?"),
+        seconds(duration),
+        outcome
+    );
+
+    println!();
+    println!("Phases:");
+    let mut rows = Vec::new();
+    for (i, (name, start)) in phases.iter().enumerate() {
+        let end = phases.get(i + 1).map_or(duration, |(_, next)| *next);
+        let took = end.saturating_sub(*start);
+        let bar = "#".repeat((took * 40 / duration.max(1)) as usize);
+        rows.push(vec![name.clone(), seconds(*start), seconds(took), bar]);
+    }
+    for line in table::format(&rows, 2, max_width) {
+        println!("{}", line);
+    }
+
+    if samples.is_empty() {
+        println!();
+        println!("No resource samples, they're recorded on Linux only.");
+        return 0;
+    }
+
+    // At most about 30 rows: samples are merged into slices of equal length.
+    let slice_ms = (duration / 30).max(SAMPLE_INTERVAL.as_millis() as u64);
+    println!();
+    println!(
+        "Resources of the task and its processes, per {}:",
+        seconds(slice_ms)
+    );
+    let mut rows = vec![vec![
+        "time".to_string(),
+        "cpu".to_string(),
+        "memory".to_string(),
+        "read".to_string(),
+        "written".to_string(),
+        "processes".to_string(),
+    ]];
+    let mut previous = Sample::default();
+    let mut slice_end = slice_ms;
+    let mut peak_rss = 0;
+    let mut idle_since: Option<u64> = None;
+    let mut idle = Vec::new();
+    let mut slice_start = previous;
+    for (i, sample) in samples.iter().enumerate() {
+        peak_rss = peak_rss.max(sample.rss_kb);
+
+        let elapsed = sample.at_ms.saturating_sub(previous.at_ms).max(1);
+        let cpu = sample.cpu_ms.saturating_sub(previous.cpu_ms) * 100 / elapsed;
+        let io =
+            sample.read_bytes > previous.read_bytes || sample.write_bytes > previous.write_bytes;
+        match (cpu < IDLE_CPU && !io && sample.processes > 0, idle_since) {
+            (true, None) => idle_since = Some(previous.at_ms),
+            (false, Some(since)) => {
+                idle.push((since, previous.at_ms));
+                idle_since = None;
+            }
+            _ => {}
+        }
+        previous = *sample;
+
+        if sample.at_ms < slice_end && i + 1 < samples.len() {
+            continue;
+        }
+        let elapsed = sample.at_ms.saturating_sub(slice_start.at_ms).max(1);
+        rows.push(vec![
+            seconds(slice_start.at_ms),
+            format!(
+                "{}%",
+                sample.cpu_ms.saturating_sub(slice_start.cpu_ms) * 100 / elapsed
+            ),
+            bytes(sample.rss_kb * 1024),
+            bytes(sample.read_bytes.saturating_sub(slice_start.read_bytes)),
+            bytes(sample.write_bytes.saturating_sub(slice_start.write_bytes)),
+            sample.processes.to_string(),
+        ]);
+        slice_start = *sample;
+        slice_end = sample.at_ms + slice_ms;
+    }
+    if let Some(since) = idle_since {
+        idle.push((since, previous.at_ms));
+    }
+    for line in table::format(&rows, 2, max_width) {
+        println!("{}", line);
+    }
+
+    println!();
+    println!("Peak memory: {}", bytes(peak_rss * 1024));
+    for (start, end) in idle
+        .iter()
+        .filter(|(start, end)| end - start >= IDLE_REPORTED.as_millis() as u64)
+    {
+        println!(
+            "Idle from {} to {}: the task used no CPU and did no disk I/O, it was waiting, e.g. for the network, a lock or user input.",
+            seconds(*start),
+            seconds(*end)
+        );
+    }
+    0
+}
@@ -0,0 +1,222 @@
+// 'mk hooks install|uninstall': git hooks that run mk tasks, configured with e.g.
+// 'pre_commit = ["lint"]' and 'pre_push = ["test"]'. The installed hook only calls
+// 'mk hooks run <hook>', which reads the config and resolves the venv like any other mk run,
+// so hooks work on every machine without hardcoded paths and pick up config changes
+// without being reinstalled.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use config::Config;
+use failure;
+use tasks;
+
+const USAGE: &str = "Usage: mk hooks install [--force] | uninstall | run <hook>";
+
+// Git hooks mk can install, and the settings listing their tasks.
+const HOOKS: &[(&str, &str)] = &[("pre-commit", "pre_commit"), ("pre-push", "pre_push")];
+
+// Marks hooks written by mk, so they can be updated and removed safely.
+const MARKER: &str = "# Installed by 'mk hooks install'.";
+
+// Git runs hooks in the top-level dir of the checkout. 'prefix' is the path of the project
+// inside it, for projects in a subdir.
+fn hook_script(hook: &str, prefix: &str) -> String {
+    let mk = if prefix.is_empty() {
+        "mk".to_string()
+    } else {
+        format!("mk -C '{}'", prefix.replace('\'', "'\\''"))
+    };
+
+    format!(
+        "#!/bin/sh\n{}\n\
+         if ! command -v mk >/dev/null 2>&1; then\n\
+         \techo \"{}: mk not found on PATH, skip with 'git {} --no-verify'.\" >&2\n\
+         \texit 1\n\
+         fi\n\
+         exec {} hooks run {}\n",
+        MARKER,
+        hook,
+        if hook == "pre-push" { "push" } else { "commit" },
+        mk,
+        hook
+    )
+}
+
+fn git_rev_parse(project_root: &str, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .arg("rev-parse")
+        .args(args)
+        .current_dir(project_root)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("failed to execute 'git': {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!("{} is not in a git repository", project_root));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// The hooks dir of the repository, respecting 'core.hooksPath' and worktrees.
+fn hooks_dir(project_root: &str) -> Result<PathBuf, String> {
+    let dir = git_rev_parse(project_root, &["--git-path", "hooks"])?;
+    Ok(Path::new(project_root).join(dir))
+}
+
+fn installed_by_mk(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .map(|text| text.contains(MARKER))
+        .unwrap_or(false)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+fn install(config: &Config, project_root: &str, force: bool) -> i32 {
+    let configured: Vec<&(&str, &str)> = HOOKS
+        .iter()
+        .filter(|(_, setting)| !config.get_list(setting).unwrap_or_default().is_empty())
+        .collect();
+
+    if configured.is_empty() {
+        eprintln!(
+            "mk: No hooks configured, set e.g. 'pre_commit = [\"lint\"]' or 'pre_push = [\"test\"]'."
+        );
+        return 1;
+    }
+
+    let (dir, prefix) = match hooks_dir(project_root)
+        .and_then(|dir| Ok((dir, git_rev_parse(project_root, &["--show-prefix"])?)))
+    {
+        Ok(found) => found,
+        Err(e) => {
+            eprintln!("mk: Cannot install hooks: {}", e);
+            return 1;
+        }
+    };
+    let prefix = prefix.trim_end_matches('/');
+
+    let mut code = 0;
+
+    for (hook, setting) in configured {
+        let path = dir.join(hook);
+
+        if path.exists() && !installed_by_mk(&path) && !force {
+            eprintln!(
+                "mk: {} exists and wasn't installed by mk, use '--force' to replace it.",
+                path.display()
+            );
+            code = 1;
+            continue;
+        }
+
+        let result = fs::create_dir_all(&dir)
+            .and_then(|_| fs::write(&path, hook_script(hook, prefix)))
+            .and_then(|_| make_executable(&path));
+
+        match result {
+            Ok(()) => eprintln!(
+                "mk: Installed {} hook, running: {}",
+                hook,
+                config.get_list(setting).unwrap_or_default().join(", ")
+            ),
+            Err(e) => {
+                eprintln!("mk: Cannot write {}: {}", path.display(), e);
+                code = 1;
+            }
+        }
+    }
+
+    code
+}
+
+fn uninstall(project_root: &str) -> i32 {
+    let dir = match hooks_dir(project_root) {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("mk: Cannot uninstall hooks: {}", e);
+            return 1;
+        }
+    };
+
+    let mut code = 0;
+
+    for (hook, _) in HOOKS {
+        let path = dir.join(hook);
+        if !installed_by_mk(&path) {
+            continue;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => eprintln!("mk: Removed {} hook.", hook),
+            Err(e) => {
+                eprintln!("mk: Cannot remove {}: {}", path.display(), e);
+                code = 1;
+            }
+        }
+    }
+
+    code
+}
+
+// Run the tasks configured for 'hook' one after another, stopping at the first failure.
+fn run_hook(config: &Config, project_root: &str, hook: &str) -> i32 {
+    let setting = match HOOKS.iter().find(|(name, _)| *name == hook) {
+        Some((_, setting)) => setting,
+        None => {
+            eprintln!("mk: Unknown hook '{}'.", hook);
+            return 2;
+        }
+    };
+
+    let mk = env::current_exe().unwrap_or_else(|_| "mk".into());
+
+    for task in config.get_list(setting).unwrap_or_default() {
+        eprintln!("mk: > mk {}", task);
+
+        let status = Command::new(&mk)
+            .args(tasks::split_words(&task))
+            .current_dir(project_root)
+            .status();
+
+        match status {
+            Ok(status) if status.success() => (),
+            Ok(status) => {
+                eprintln!("mk: '{}' failed ({}), {} aborted.", task, status, hook);
+                return failure::status_code(&status);
+            }
+            Err(e) => {
+                eprintln!("mk: Failed to run '{}': {}", task, e);
+                return 1;
+            }
+        }
+    }
+
+    0
+}
+
+pub fn run(args: &[String], config: &Config, project_root: &str) -> i32 {
+    let args: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+
+    match args.as_slice() {
+        ["install"] => install(config, project_root, false),
+        ["install", "--force"] => install(config, project_root, true),
+        ["uninstall"] => uninstall(project_root),
+        ["run", hook] => run_hook(config, project_root, hook),
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
@@ -0,0 +1,62 @@
+// 'on_success' / 'on_failure' hooks: shell commands run after every task, e.g. for
+// notifications or to push metrics. They get the outcome through 'MK_*' variables.
+
+use std::time::Duration;
+
+use config::Config;
+use sys;
+
+pub struct Outcome<'a> {
+    pub args: &'a [String],
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    pub duration: Duration,
+    pub project_root: &'a str,
+    pub venv_path: &'a str,
+}
+
+pub fn run(config: &Config, outcome: &Outcome) {
+    let name = if outcome.success {
+        "on_success"
+    } else {
+        "on_failure"
+    };
+
+    let script = match config.get(name) {
+        Some(script) if !script.trim().is_empty() => script,
+        _ => return,
+    };
+
+    let exit_code = match outcome.exit_code {
+        Some(code) => code.to_string(),
+        None => "".to_string(),
+    };
+
+    let status = sys::shell(script)
+        .env(
+            "MK_TASK",
+            outcome.args.first().map(|a| a.as_str()).unwrap_or(""),
+        )
+        .env("MK_ARGS", outcome.args.join(" "))
+        .env(
+            "MK_RESULT",
+            if outcome.success {
+                "success"
+            } else {
+                "failure"
+            },
+        )
+        .env("MK_EXIT_CODE", exit_code)
+        .env("MK_DURATION_MS", outcome.duration.as_millis().to_string())
+        .env("MK_PROJECT_ROOT", outcome.project_root)
+        .env("MK_VENV", outcome.venv_path)
+        .status();
+
+    match status {
+        Ok(status) if !status.success() => {
+            eprintln!("mk: The '{}' hook returned {}", name, status)
+        }
+        Err(e) => eprintln!("mk: Failed to run the '{}' hook: {}", name, e),
+        _ => {}
+    }
+}
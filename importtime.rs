@@ -0,0 +1,99 @@
+// '--why-slow': run make.py with '-X importtime' and summarize the slowest imports, to
+// find out why the task runner itself takes seconds to start. Python writes one line
+// per import to stderr:
+//
+//     import time: self [us] | cumulative | imported package
+//     import time:       539 |       2181 | site
+//
+// Those lines are collected, everything else on stderr is passed through.
+
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::Duration;
+
+use sys;
+
+const TOP: usize = 10;
+
+struct Import {
+    name: String,
+    // Nesting depth, 0 for imports done by make.py itself.
+    depth: usize,
+    self_us: u64,
+    cumulative_us: u64,
+}
+
+fn parse_line(line: &str) -> Option<Import> {
+    let rest = line.strip_prefix("import time:")?;
+    let fields: Vec<&str> = rest.split('|').collect();
+    if fields.len() != 3 {
+        return None;
+    }
+
+    let name = fields[2].trim_end();
+    let stripped = name.trim_start();
+    Some(Import {
+        name: stripped.to_string(),
+        depth: (name.len() - stripped.len()).saturating_sub(1) / 2,
+        self_us: fields[0].trim().parse().ok()?,
+        cumulative_us: fields[1].trim().parse().ok()?,
+    })
+}
+
+pub fn run(cmd: &mut Command) -> io::Result<ExitStatus> {
+    sys::prepare_forwarding(cmd);
+    let mut child = cmd.stderr(Stdio::piped()).spawn()?;
+    let _forwarding = sys::forward_signals(&child);
+    let stderr = child.stderr.take().expect("mk: Child stderr is piped.");
+
+    let mut imports = Vec::new();
+    for line in BufReader::new(stderr).lines() {
+        let line = line?;
+        match parse_line(&line) {
+            Some(import) => imports.push(import),
+            None if line.starts_with("import time:") => {}
+            None => eprintln!("{}", line),
+        }
+    }
+
+    let status = child.wait()?;
+    summarize(&imports);
+    Ok(status)
+}
+
+fn ms(us: u64) -> String {
+    format!("{:.1}ms", Duration::from_micros(us).as_secs_f64() * 1000.0)
+}
+
+fn summarize(imports: &[Import]) {
+    // Top-level imports add up to the total, nested ones are part of their parent.
+    let total: u64 = imports
+        .iter()
+        .filter(|i| i.depth == 0)
+        .map(|i| i.cumulative_us)
+        .sum();
+
+    let mut by_cumulative: Vec<&Import> = imports.iter().filter(|i| i.depth == 0).collect();
+    by_cumulative.sort_by_key(|i| std::cmp::Reverse(i.cumulative_us));
+
+    let mut by_self: Vec<&Import> = imports.iter().collect();
+    by_self.sort_by_key(|i| std::cmp::Reverse(i.self_us));
+
+    eprintln!(
+        "\nmk: {} imports took {} in total.",
+        imports.len(),
+        ms(total)
+    );
+
+    eprintln!("\nSlowest top-level imports (including what they import):");
+    for import in by_cumulative.iter().take(TOP) {
+        eprintln!("  {:>10}  {}", ms(import.cumulative_us), import.name);
+    }
+
+    eprintln!("\nSlowest modules by their own import time:");
+    for import in by_self.iter().take(TOP) {
+        eprintln!("  {:>10}  {}", ms(import.self_us), import.name);
+    }
+}
@@ -0,0 +1,214 @@
+// Minimal JSON helpers, mk has no dependencies.
+
+pub fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // Members in the order of the document.
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(n) if n.fract() == 0.0 => Some(*n as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Json)]> {
+        match self {
+            Json::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(format!("unexpected data at offset {}", parser.pos));
+    }
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let c = *self.chars.get(self.pos).ok_or("unexpected end of input")?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), String> {
+        for expected in word.chars() {
+            if self.next()? != expected {
+                return Err(format!("expected '{}' at offset {}", word, self.pos));
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos) {
+            Some('n') => self.expect("null").map(|_| Json::Null),
+            Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.chars.get(self.pos) == Some(&']') && items.is_empty() {
+                        self.pos += 1;
+                        return Ok(Json::Array(items));
+                    }
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => {}
+                        ']' => return Ok(Json::Array(items)),
+                        c => return Err(format!("unexpected '{}' in array", c)),
+                    }
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                loop {
+                    self.skip_whitespace();
+                    if self.chars.get(self.pos) == Some(&'}') && members.is_empty() {
+                        self.pos += 1;
+                        return Ok(Json::Object(members));
+                    }
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    if self.next()? != ':' {
+                        return Err(format!("expected ':' after \"{}\"", key));
+                    }
+                    members.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => {}
+                        '}' => return Ok(Json::Object(members)),
+                        c => return Err(format!("unexpected '{}' in object", c)),
+                    }
+                }
+            }
+            Some(_) => {
+                let start = self.pos;
+                while self.pos < self.chars.len()
+                    && (self.chars[self.pos].is_ascii_digit()
+                        || "+-.eE".contains(self.chars[self.pos]))
+                {
+                    self.pos += 1;
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                number
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| format!("unexpected data at offset {}", start))
+            }
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        if self.next()? != '"' {
+            return Err(format!("expected a string at offset {}", self.pos - 1));
+        }
+        let mut out = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(out),
+                '\\' => match self.next()? {
+                    'n' => out.push('\n'),
+                    't' => out.push('\t'),
+                    'r' => out.push('\r'),
+                    'b' => out.push('\u{8}'),
+                    'f' => out.push('\u{c}'),
+                    'u' => {
+                        let hex: String = (0..4).map(|_| self.next()).collect::<Result<_, _>>()?;
+                        let code = u32::from_str_radix(&hex, 16)
+                            .map_err(|_| format!("invalid escape '\\u{}'", hex))?;
+                        out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    c => out.push(c),
+                },
+                c => out.push(c),
+            }
+        }
+    }
+}
+
+// The strings of the array at top-level 'key' in 'text', e.g. '"envs": ["/a", "/b"]', as in
+// the output of 'conda info --json'. None if there is no such array.
+pub fn string_array(text: &str, key: &str) -> Option<Vec<String>> {
+    let doc = parse(text).ok()?;
+    let items = doc.get(key)?.as_array()?;
+    Some(
+        items
+            .iter()
+            .filter_map(|item| item.as_str())
+            .map(|s| s.to_string())
+            .collect(),
+    )
+}
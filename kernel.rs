@@ -0,0 +1,158 @@
+// 'mk kernel install|uninstall': register the project's venv as a Jupyter kernel, so
+// notebooks run on the same interpreter as the project's tasks. The kernel spec is written
+// to the user's Jupyter data dir, where Jupyter and VS Code look for kernels.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use json;
+
+const USAGE: &str = "Usage: mk kernel install|uninstall [--name <name>] [--display-name <name>]";
+
+// Where Jupyter looks for user kernels, see 'jupyter --paths'.
+fn kernels_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("JUPYTER_DATA_DIR") {
+        if !dir.is_empty() {
+            return Some(PathBuf::from(dir).join("kernels"));
+        }
+    }
+
+    if cfg!(windows) {
+        return env::var_os("APPDATA")
+            .map(|dir| PathBuf::from(dir).join("jupyter").join("kernels"));
+    }
+
+    let home = env::home_dir()?;
+    if cfg!(target_os = "macos") {
+        return Some(home.join("Library").join("Jupyter").join("kernels"));
+    }
+
+    match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("jupyter").join("kernels")),
+        _ => Some(
+            home.join(".local")
+                .join("share")
+                .join("jupyter")
+                .join("kernels"),
+        ),
+    }
+}
+
+// Kernel names may only contain ASCII letters, digits, '-', '.' and '_'.
+fn kernel_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '_' {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        "mk".to_string()
+    } else {
+        name
+    }
+}
+
+fn kernel_json(python_bin: &str, display_name: &str) -> String {
+    format!(
+        "{{\n  \"argv\": [{}, \"-m\", \"ipykernel_launcher\", \"-f\", \"{{connection_file}}\"],\n  \"display_name\": {},\n  \"language\": \"python\",\n  \"metadata\": {{ \"debugger\": true }}\n}}\n",
+        json::quote(python_bin),
+        json::quote(display_name)
+    )
+}
+
+fn has_ipykernel(python_bin: &str) -> bool {
+    Command::new(python_bin)
+        .args(["-c", "import ipykernel"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+pub fn run(args: &[String], project_root: &str, python_bin: &str) -> i32 {
+    let project_name = Path::new(project_root)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut name = None;
+    let mut display_name = None;
+
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--name" => name = rest.next().cloned(),
+            "--display-name" => display_name = rest.next().cloned(),
+            _ => {
+                eprintln!("{}", USAGE);
+                return 2;
+            }
+        }
+    }
+
+    let name = kernel_name(name.as_ref().unwrap_or(&project_name));
+
+    let dir = match kernels_dir() {
+        Some(dir) => dir.join(&name),
+        None => {
+            eprintln!("mk: Cannot find the Jupyter data dir, set JUPYTER_DATA_DIR.");
+            return 1;
+        }
+    };
+
+    match args.first().map(|a| a.as_str()) {
+        Some("install") => {
+            let display_name = display_name.unwrap_or_else(|| format!("Python ({})", project_name));
+
+            if let Err(e) = fs::create_dir_all(&dir).and_then(|_| {
+                fs::write(
+                    dir.join("kernel.json"),
+                    kernel_json(python_bin, &display_name),
+                )
+            }) {
+                eprintln!("mk: Cannot write {}: {}", dir.display(), e);
+                return 1;
+            }
+
+            eprintln!(
+                "mk: Installed kernel '{}' ({}) for {} in {}.",
+                name,
+                display_name,
+                python_bin,
+                dir.display()
+            );
+            if !has_ipykernel(python_bin) {
+                eprintln!(
+                    "mk: The venv has no 'ipykernel', add it to start the kernel, e.g. \
+                     'uv add --dev ipykernel'."
+                );
+            }
+            0
+        }
+        Some("uninstall") => {
+            if !dir.join("kernel.json").is_file() {
+                eprintln!("mk: There is no kernel '{}' in {}.", name, dir.display());
+                return 1;
+            }
+            if let Err(e) = fs::remove_dir_all(&dir) {
+                eprintln!("mk: Cannot remove {}: {}", dir.display(), e);
+                return 1;
+            }
+            eprintln!("mk: Removed kernel '{}'.", name);
+            0
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            2
+        }
+    }
+}
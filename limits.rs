@@ -0,0 +1,196 @@
+// Resource limits of tasks, for all tasks or per task:
+//
+//     open_files = 4096
+//     core_dumps = false
+//
+//     [task.serve]
+//     open_files = "max"
+//
+// 'open_files' sets the soft limit of open files (RLIMIT_NOFILE), which is often 1024
+// while dev servers and test suites with many sockets need more. "max" raises it to the
+// hard limit. 'core_dumps' raises the core file size limit to the hard limit, or sets it to
+// 0. They're set in the task's process before it starts, so mk and other tasks keep their
+// limits. Unix only.
+
+use std::process;
+use std::process::Command;
+
+use config::Config;
+use toml::Value;
+
+#[cfg(unix)]
+use self::ffi::Rlim;
+#[cfg(not(unix))]
+type Rlim = u64;
+
+// The soft and hard limit to set, resolved before the task is started.
+#[derive(Clone, Copy, Default)]
+pub struct Limits {
+    open_files: Option<(Rlim, Rlim)>,
+    core: Option<(Rlim, Rlim)>,
+}
+
+#[cfg(unix)]
+mod ffi {
+    use std::os::raw::c_int;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub type Rlim = std::os::raw::c_ulong;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub type Rlim = u64;
+
+    #[repr(C)]
+    pub struct Rlimit {
+        pub cur: Rlim,
+        pub max: Rlim,
+    }
+
+    pub const RLIMIT_CORE: c_int = 4;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub const RLIMIT_NOFILE: c_int = 7;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub const RLIMIT_NOFILE: c_int = 8;
+
+    extern "C" {
+        pub fn getrlimit(resource: c_int, rlim: *mut Rlimit) -> c_int;
+        pub fn setrlimit(resource: c_int, rlim: *const Rlimit) -> c_int;
+    }
+}
+
+// The setting 'name' of 'task', or of all tasks.
+fn setting<'a>(config: &'a Config, task: Option<&str>, name: &str) -> Option<(String, &'a Value)> {
+    if let Some(task) = task {
+        let key = format!("task.{}.{}", task, name);
+        if let Some(value) = config.get_value(&key) {
+            return Some((key, value));
+        }
+    }
+    config
+        .get_value(name)
+        .map(|value| (name.to_string(), value))
+}
+
+fn invalid(key: &str, value: &Value, expected: &str) -> ! {
+    eprintln!(
+        "mk: Invalid value {} for '{}', expected {}.",
+        value, key, expected
+    );
+    process::exit(1);
+}
+
+// The soft and hard limit of 'resource'.
+#[cfg(unix)]
+fn current(resource: std::os::raw::c_int) -> Option<(Rlim, Rlim)> {
+    let mut limit = ffi::Rlimit { cur: 0, max: 0 };
+    if unsafe { ffi::getrlimit(resource, &mut limit) } != 0 {
+        return None;
+    }
+    Some((limit.cur, limit.max))
+}
+
+// The limits of 'task', the first arg of a run. Exits with an error if they are invalid or
+// can't be set.
+#[cfg(unix)]
+pub fn for_task(config: &Config, task: Option<&str>) -> Limits {
+    let mut limits = Limits::default();
+
+    if let Some((key, value)) = setting(config, task, "open_files") {
+        let (_, hard) = current(ffi::RLIMIT_NOFILE).unwrap_or((0, Rlim::MAX));
+        // Linux refuses more than 'fs.nr_open' and macOS more than OPEN_MAX, whatever the
+        // hard limit says.
+        let max = if cfg!(target_os = "macos") {
+            hard.min(10240)
+        } else {
+            std::fs::read_to_string("/proc/sys/fs/nr_open")
+                .ok()
+                .and_then(|n| n.trim().parse().ok())
+                .map_or(hard, |nr_open: Rlim| hard.min(nr_open))
+        };
+        let wanted = match value {
+            Value::Integer(n) if *n > 0 => *n as Rlim,
+            Value::String(s) if s.trim() == "max" => max,
+            Value::String(s) => match s.trim().parse() {
+                Ok(n) if n > 0 => n,
+                _ => invalid(&key, value, "a number of files or \"max\""),
+            },
+            _ => invalid(&key, value, "a number of files or \"max\""),
+        };
+        if wanted > max {
+            eprintln!(
+                "mk: Cannot set the open files limit to {} for '{}', the hard limit is {}. Use \"max\" or raise the hard limit, e.g. in /etc/security/limits.conf.",
+                wanted, key, max
+            );
+            process::exit(1);
+        }
+        limits.open_files = Some((wanted, hard));
+    }
+
+    if let Some((key, value)) = setting(config, task, "core_dumps") {
+        let enable = match value {
+            Value::Boolean(b) => *b,
+            Value::String(s) => match s.trim().to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => true,
+                "false" | "no" | "off" | "0" => false,
+                _ => invalid(&key, value, "true or false"),
+            },
+            _ => invalid(&key, value, "true or false"),
+        };
+        let (_, hard) = current(ffi::RLIMIT_CORE).unwrap_or((0, 0));
+        if enable && hard == 0 {
+            eprintln!(
+                "mk: Cannot enable core dumps for '{}', the hard limit of their size is 0.",
+                key
+            );
+            process::exit(1);
+        }
+        limits.core = Some((if enable { hard } else { 0 }, hard));
+    }
+
+    limits
+}
+
+#[cfg(not(unix))]
+pub fn for_task(config: &Config, task: Option<&str>) -> Limits {
+    for name in &["open_files", "core_dumps"] {
+        if let Some((key, _)) = setting(config, task, name) {
+            eprintln!("mk: '{}' is only supported on Unix.", key);
+            process::exit(2);
+        }
+    }
+    Limits::default()
+}
+
+// Set 'limits' in the process of 'cmd' when it starts.
+#[cfg(unix)]
+pub fn apply(cmd: &mut Command, limits: Limits) {
+    use std::io;
+    use std::os::unix::process::CommandExt;
+
+    if limits.open_files.is_none() && limits.core.is_none() {
+        return;
+    }
+
+    let set = |resource, (cur, max): (Rlim, Rlim)| {
+        let limit = ffi::Rlimit { cur, max };
+        if unsafe { ffi::setrlimit(resource, &limit) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    };
+
+    // Runs in the forked child, so it only calls setrlimit.
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(limit) = limits.open_files {
+                set(ffi::RLIMIT_NOFILE, limit)?;
+            }
+            if let Some(limit) = limits.core {
+                set(ffi::RLIMIT_CORE, limit)?;
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+pub fn apply(_cmd: &mut Command, _limits: Limits) {}
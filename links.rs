@@ -0,0 +1,50 @@
+// Symlinks, e.g. a make.py or venv linked in from shared tooling on another filesystem.
+// When such a filesystem isn't mounted, the link is left dangling and every check for the
+// file just says it doesn't exist, so mk looks for the broken link to say what's wrong.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// The first symlink on the way to 'path' whose target doesn't exist, and that target.
+fn broken_link(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let ancestors: Vec<&Path> = path.ancestors().collect();
+
+    for dir in ancestors.into_iter().rev() {
+        let meta = fs::symlink_metadata(dir).ok()?;
+        if meta.file_type().is_symlink() && fs::metadata(dir).is_err() {
+            let target = fs::read_link(dir).ok()?;
+            let target = match dir.parent() {
+                Some(parent) => parent.join(target),
+                None => target,
+            };
+            return Some((dir.to_path_buf(), target));
+        }
+    }
+
+    None
+}
+
+// Why 'path' doesn't exist, if it's because of a dangling symlink.
+pub fn describe_broken(path: &Path) -> Option<String> {
+    let (link, target) = broken_link(path)?;
+    Some(format!(
+        "{} is a symlink to {}, which doesn't exist",
+        link.display(),
+        target.display()
+    ))
+}
+
+// 'path' with all symlinks resolved, or as it is if that fails. Resolved paths keep
+// working when a link in between is changed or the checkout is moved, and on network
+// filesystems save a lookup per link. Not done on Windows, where resolved paths get a
+// '\\?\' prefix many tools can't handle.
+pub fn resolve(path: &str) -> String {
+    if cfg!(windows) {
+        return path.to_string();
+    }
+
+    match fs::canonicalize(path) {
+        Ok(resolved) => resolved.display().to_string(),
+        Err(_) => path.to_string(),
+    }
+}
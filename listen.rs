@@ -0,0 +1,253 @@
+// 'mk listen': a small HTTP server on loopback that runs tasks on request, so editor
+// plugins can run e.g. 'test-file <path>' on save without resolving the venv and spawning
+// a shell every time. Requests need the token from '.mk/listen', which is only readable
+// by the user:
+//
+//     POST /run HTTP/1.1
+//     Authorization: Bearer <token>
+//
+//     test-file
+//     src/app.py
+//
+// The body has one argument per line. The response is JSON with the exit code and the
+// task's output: '{"exit_code": 0, "stdout": "...", "stderr": "..."}'. Requests are
+// handled one at a time, so runs never overlap.
+
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::process::Output;
+use std::time::Duration;
+
+use json;
+use workspace;
+
+const USAGE: &str = "Usage: mk listen [--port <port>]";
+
+// Requests are small, anything bigger is a mistake or an attack.
+const MAX_BODY: usize = 64 * 1024;
+
+// A random token, from the randomly seeded hasher std uses for HashMaps.
+fn new_token() -> String {
+    (0..2)
+        .map(|_| format!("{:016x}", RandomState::new().build_hasher().finish()))
+        .collect()
+}
+
+struct Request {
+    method: String,
+    path: String,
+    token: Option<String>,
+    body: String,
+}
+
+fn read_request(stream: &TcpStream) -> Result<Request, String> {
+    let mut reader = BufReader::new(stream.take(MAX_BODY as u64 + 16 * 1024));
+
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(|e| e.to_string())?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let path = parts.next().unwrap_or("").to_string();
+
+    let mut token = None;
+    let mut content_length = 0;
+
+    loop {
+        line.clear();
+        reader.read_line(&mut line).map_err(|e| e.to_string())?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = match header.find(':') {
+            Some(i) => (header[..i].trim().to_lowercase(), header[i + 1..].trim()),
+            None => continue,
+        };
+        match name.as_str() {
+            "authorization" => token = value.strip_prefix("Bearer ").map(|t| t.to_string()),
+            "content-length" => content_length = value.parse().unwrap_or(0),
+            _ => (),
+        }
+    }
+
+    if content_length > MAX_BODY {
+        return Err("request too large".to_string());
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+
+    Ok(Request {
+        method,
+        path,
+        token,
+        body: String::from_utf8_lossy(&body).to_string(),
+    })
+}
+
+fn respond(mut stream: &TcpStream, status: &str, body: &str) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+}
+
+// Compare without bailing out at the first difference, so the token can't be guessed
+// byte by byte from response times.
+fn token_matches(given: Option<&str>, token: &str) -> bool {
+    match given {
+        Some(given) if given.len() == token.len() => {
+            given
+                .bytes()
+                .zip(token.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+        }
+        _ => false,
+    }
+}
+
+fn handle(stream: &TcpStream, token: &str, run: &dyn Fn(&[String]) -> io::Result<Output>) {
+    let request = match read_request(stream) {
+        Ok(request) => request,
+        Err(e) => {
+            respond(
+                stream,
+                "400 Bad Request",
+                &format!("{{\"error\": {}}}", json::quote(&e)),
+            );
+            return;
+        }
+    };
+
+    if !token_matches(request.token.as_deref(), token) {
+        respond(stream, "401 Unauthorized", "{\"error\": \"invalid token\"}");
+        return;
+    }
+
+    if request.method != "POST" || request.path != "/run" {
+        respond(stream, "404 Not Found", "{\"error\": \"use POST /run\"}");
+        return;
+    }
+
+    let args: Vec<String> = request
+        .body
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect();
+
+    eprintln!("mk: > mk {}", args.join(" "));
+
+    match run(&args) {
+        Ok(output) => {
+            let exit_code = match output.status.code() {
+                Some(code) => code.to_string(),
+                None => "null".to_string(),
+            };
+            respond(
+                stream,
+                "200 OK",
+                &format!(
+                    "{{\"exit_code\": {}, \"stdout\": {}, \"stderr\": {}}}",
+                    exit_code,
+                    json::quote(&String::from_utf8_lossy(&output.stdout)),
+                    json::quote(&String::from_utf8_lossy(&output.stderr))
+                ),
+            );
+        }
+        Err(e) => respond(
+            stream,
+            "500 Internal Server Error",
+            &format!("{{\"error\": {}}}", json::quote(&e.to_string())),
+        ),
+    }
+}
+
+#[cfg(unix)]
+fn write_private(path: &std::path::Path, content: &str) -> io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let _ = fs::remove_file(path);
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(content.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &std::path::Path, content: &str) -> io::Result<()> {
+    fs::write(path, content)
+}
+
+pub fn run(
+    args: &[String],
+    project_root: &str,
+    run: &dyn Fn(&[String]) -> io::Result<Output>,
+) -> i32 {
+    let port = match args {
+        [] => 0,
+        [option, port] if option == "--port" => match port.parse::<u16>() {
+            Ok(port) => port,
+            Err(_) => {
+                eprintln!("mk: Invalid port '{}'.", port);
+                return 2;
+            }
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("mk: Cannot listen on port {}: {}", port, e);
+            return 1;
+        }
+    };
+    let port = listener.local_addr().map(|a| a.port()).unwrap_or(port);
+
+    let token = new_token();
+    let info_path = match workspace::ensure_dir(project_root) {
+        Ok(dir) => dir.join("listen"),
+        Err(e) => {
+            eprintln!("mk: {}", e);
+            return 1;
+        }
+    };
+    if let Err(e) = write_private(&info_path, &format!("port {}\ntoken {}\n", port, token)) {
+        eprintln!("mk: Cannot write {}: {}", info_path.display(), e);
+        return 1;
+    }
+
+    eprintln!(
+        "mk: Listening on http://127.0.0.1:{}, the token is in {}.",
+        port,
+        info_path.display()
+    );
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                // Don't let a client that never finishes its request block everyone else.
+                let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+                handle(&stream, &token, run)
+            }
+            Err(e) => eprintln!("mk: Connection failed: {}", e),
+        }
+    }
+
+    0
+}
@@ -0,0 +1,155 @@
+// 'mk --list', also what a bare 'mk' does: print the project's tasks with the first line of
+// their docstring, like 'just --list'. Scripts that speak the 'listing' protocol list their
+// own tasks: mk runs them with 'MK_LIST=<version>' and they print one task per line, the
+// name and a description separated by a tab. Other scripts are imported by a small
+// bootstrap, so their '__main__' block doesn't run, and their public top-level functions
+// are listed. Tasks defined in the config come last, then aliases.
+
+use std::process::{Command, Stdio};
+
+use alias;
+use config::Config;
+use facade;
+use protocol;
+use table;
+use tasks;
+
+// Imports make.py (given as first arg) as module 'make' and prints its functions.
+const BOOTSTRAP: &str = r#"import importlib.util, inspect, sys
+path = sys.argv[1]
+sys.argv = [path]
+spec = importlib.util.spec_from_file_location("make", path)
+module = importlib.util.module_from_spec(spec)
+sys.modules["make"] = module
+spec.loader.exec_module(module)
+for name, obj in vars(module).items():
+    if name.startswith("_") or name == "main":
+        continue
+    if not inspect.isfunction(obj) or obj.__module__ != "make":
+        continue
+    doc = (inspect.getdoc(obj) or "").strip().splitlines()
+    print(name + "\t" + (doc[0] if doc else ""))
+"#;
+
+// Ask make.py for its tasks, as (name, description) pairs. 'env' is the command make.py
+// would run with, for its environment.
+pub fn script_tasks(
+    script: &protocol::Script,
+    make_py_file: &str,
+    python_bin: &str,
+    env: &Command,
+    stderr: Stdio,
+) -> Result<Vec<(String, String)>, String> {
+    let mut cmd = Command::new(python_bin);
+    for (name, value) in env.get_envs() {
+        match value {
+            Some(value) => cmd.env(name, value),
+            None => cmd.env_remove(name),
+        };
+    }
+
+    match protocol::negotiate(script, "listing") {
+        Some(version) => cmd.arg(make_py_file).env("MK_LIST", version.to_string()),
+        None => cmd.arg("-c").arg(BOOTSTRAP).arg(make_py_file),
+    };
+
+    let output = cmd
+        .stdin(Stdio::null())
+        .stderr(stderr)
+        .output()
+        .map_err(|e| format!("failed to run {}: {}", python_bin, e))?;
+
+    if !output.status.success() {
+        return Err(format!("make.py returned {}", output.status));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty())
+        .map(|line| match line.split_once('\t') {
+            Some((name, description)) => (name.trim().to_string(), description.trim().to_string()),
+            None => (line.trim().to_string(), String::new()),
+        })
+        .collect())
+}
+
+// The tasks of make.py and the config, as (name, description) pairs, and whether make.py
+// could be asked. Failures are reported here, the config tasks are returned anyway.
+pub fn collect(
+    config: &Config,
+    script: &protocol::Script,
+    make_py_file: &str,
+    python_bin: &str,
+    env: &Command,
+) -> (Vec<(String, String)>, bool) {
+    let (mut entries, ok) =
+        match script_tasks(script, make_py_file, python_bin, env, Stdio::inherit()) {
+            Ok(entries) => (entries, true),
+            Err(e) => {
+                eprintln!("mk: Cannot list the tasks of make.py: {}", e);
+                (Vec::new(), false)
+            }
+        };
+
+    // Config tasks take precedence over mapped tasks, and both over make.py tasks of the
+    // same name.
+    let config_tasks = tasks::names(config);
+    let facade_tasks: Vec<String> = facade::names(config)
+        .into_iter()
+        .filter(|name| !config_tasks.contains(name))
+        .collect();
+    entries.retain(|(name, _)| !config_tasks.contains(name) && !facade_tasks.contains(name));
+    for name in facade_tasks {
+        let words = facade::words_of(config, &name).unwrap_or_default();
+        entries.push((name, format!("(facade) {}", words.join(" "))));
+    }
+    for name in config_tasks {
+        let steps: Vec<String> = tasks::steps(config, &name)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|step| step.run)
+            .collect();
+        entries.push((name, format!("(config) {}", steps.join(", "))));
+    }
+    for name in alias::names(config) {
+        let words = alias::words_of(config, &name).unwrap_or_default();
+        entries.retain(|(task, _)| *task != name);
+        entries.push((name, format!("(alias) {}", words.join(" "))));
+    }
+
+    (entries, ok)
+}
+
+pub fn run(
+    config: &Config,
+    script: &protocol::Script,
+    make_py_file: &str,
+    python_bin: &str,
+    env: &Command,
+) -> i32 {
+    let (entries, ok) = collect(config, script, make_py_file, python_bin, env);
+    let code = if ok { 0 } else { 1 };
+
+    if entries.is_empty() {
+        if ok {
+            println!("No tasks found in make.py.");
+        }
+        return code;
+    }
+
+    print(config, &entries);
+    code
+}
+
+pub fn print(config: &Config, entries: &[(String, String)]) {
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|(name, description)| vec![name.clone(), description.clone()])
+        .collect();
+
+    println!("Tasks:");
+    for line in table::format(&rows, 4, table::max_width(config)) {
+        println!("{}", line);
+    }
+}
@@ -0,0 +1,256 @@
+// mk's messages for users, by id, so their wording can be changed, e.g. to translate them or
+// to link errors to internal runbooks. Overrides go in a messages file:
+//
+//     [messages]
+//     venv_not_found = "No venv for this project, see https://wiki.example.com/mk:\n\n{reasons}"
+//
+// mk reads 'messages_file' if set, else 'messages/<lang>.toml' next to the user config, for
+// the language in 'MK_LANG', 'LC_ALL', 'LC_MESSAGES' or 'LANG' (e.g. 'de' for
+// 'de_DE.UTF-8'). '{name}' placeholders are filled in by mk. 'mk messages' prints all
+// messages in this format, to start a file from.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use config;
+use config::Config;
+use toml;
+use toml::Value;
+
+const CATALOG: &[(&str, &str)] = &[
+    ("warning", "Warning: {msg}"),
+    ("strict_error", "Error: {msg}"),
+    ("strict_stop", "Stopping, warnings are errors in strict mode."),
+    ("make_py_unreadable", "Cannot read make.py: {reason}."),
+    (
+        "make_py_not_found",
+        "Cannot find '{script}' file in {dir} or its parent directories.",
+    ),
+    (
+        "no_alternatives",
+        "No similar files either, checked: {names}.",
+    ),
+    (
+        "only_make_py",
+        "Found {found}, but mk runs '{script}'. Use 'mk -f <file> ...' or set 'script' to run another script.",
+    ),
+    (
+        "nearby_project",
+        "Did you mean to run in {dir}? Use 'mk -C {dir} ...'.",
+    ),
+    ("run_init", "Run 'mk init' to create a 'make.py' here."),
+    (
+        "workspace_members",
+        "This is a workspace, run the tasks of a member with 'mk @<member> <task>': {members}",
+    ),
+    ("inside_venv", "{dir} is inside the venv {venv}, not a project."),
+    (
+        "venv_project",
+        "The venv belongs to {dir}. Use 'mk -C {dir} ...'.",
+    ),
+    (
+        "venv_project_unknown",
+        "Run mk in the project the venv belongs to.",
+    ),
+    ("already_exists", "{path} already exists."),
+    ("cannot_write", "Cannot write {path}: {reason}"),
+    ("step_failed", "Step '{step}' failed ({status})."),
+    (
+        "first_run",
+        "First run in this project, using {python} from {venv} (via {via}).",
+    ),
+    (
+        "venv_changed",
+        "The project files changed, using {venv} now instead of {old} (via {via}).",
+    ),
+    (
+        "no_resolver",
+        "None of the resolvers ({resolvers}) is installed and there is no active venv, '.venv' or 'venv' to use.",
+    ),
+    (
+        "venv_not_found",
+        "Cannot find the venv for this project:\n\n{reasons}",
+    ),
+    (
+        "venv_unusable",
+        "Cannot use the venv {venv}: {reason}.\n\nMount the filesystem it's on, or recreate the venv.",
+    ),
+    (
+        "venv_wrong_python",
+        "Cannot use the venv {venv}: {reason}.\n\nRecreate it with a matching Python, e.g. with 'uv sync --python <version>' or 'poetry env use <version>'.",
+    ),
+    ("unknown_setting", "Unknown setting '{key}'."),
+    (
+        "no_user_config",
+        "Cannot determine the user config location.",
+    ),
+    (
+        "config_outside_project",
+        "Not inside a project. Use '--user' to change the user config.",
+    ),
+    ("config_update_failed", "Couldn't update config: {reason}"),
+    (
+        "root_guard_abort",
+        "{msg}\n\nRun mk without sudo, or set 'root_guard = \"warn\"'.",
+    ),
+    ("cannot_chdir", "Cannot change to directory '{dir}': {reason}"),
+    ("upgrade_mk", "Please upgrade mk."),
+    (
+        "mk_version_mismatch",
+        "{file} pins mk {pinned}, but this is mk {version}. Install it as {binary}, or set 'mk_version_check = \"warn\"'.",
+    ),
+    ("syncing", "Syncing with '{command}'."),
+    ("sync_failed", "Sync failed: {reason}"),
+    (
+        "create_venv_ask",
+        "No venv found. Create it with '{command}'? [Y/n]",
+    ),
+    ("creating_venv", "No venv found, creating it with '{command}'."),
+    (
+        "path_shims_failed",
+        "Cannot use path_shims, using the venv's bin dir: {reason}",
+    ),
+    ("path_failed", "Cannot put {dir} on PATH: {reason}"),
+    (
+        "config_task_args",
+        "'{task}' is defined in the config and takes no arguments.",
+    ),
+    ("tmp_dir_failed", "Cannot create the task's temp dir: {reason}"),
+    ("run_failed", "Failed to run make.py: {reason}"),
+    (
+        "pipe_failed",
+        "'{stage}' failed ({status}), not running: {skipped}",
+    ),
+    (
+        "infrastructure_failure",
+        "Infrastructure failure, the task did not run.",
+    ),
+    ("runbook", "See {url}"),
+];
+
+// The overrides, None until loaded.
+static OVERRIDES: Mutex<Option<Vec<(String, String)>>> = Mutex::new(None);
+
+// Load the overrides for 'config'. Messages printed before this, e.g. when there is no
+// project, use the user config.
+pub fn load(config: &Config) {
+    let overrides = read_overrides(config);
+    *OVERRIDES.lock().unwrap_or_else(|e| e.into_inner()) = Some(overrides);
+}
+
+fn file(config: &Config) -> Option<PathBuf> {
+    if let Some(path) = config.get("messages_file") {
+        return Some(PathBuf::from(path));
+    }
+
+    let locale = ["MK_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())?;
+    let lang: String = locale
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    if lang.is_empty() || lang == "C" || lang == "POSIX" {
+        return None;
+    }
+
+    let dir = config::user_config_path()?.parent()?.to_path_buf();
+    Some(dir.join("messages").join(format!("{}.toml", lang)))
+}
+
+fn read_overrides(config: &Config) -> Vec<(String, String)> {
+    let path = match file(config) {
+        Some(path) => path,
+        None => return Vec::new(),
+    };
+
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        // Only complain about a file that was asked for, not a missing locale.
+        Err(e) if config.get("messages_file").is_some() => {
+            eprintln!("mk: Cannot read {}: {}", path.display(), e);
+            return Vec::new();
+        }
+        Err(_) => return Vec::new(),
+    };
+
+    let doc = match toml::parse(&text) {
+        Ok(doc) => doc,
+        Err(e) => {
+            eprintln!("mk: Ignoring {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut overrides = Vec::new();
+    for (key, value) in doc.flatten() {
+        let id = match key.strip_prefix("messages.") {
+            Some(id) => id,
+            None => continue,
+        };
+        match (CATALOG.iter().any(|(known, _)| *known == id), value) {
+            (true, Value::String(text)) => overrides.push((id.to_string(), text)),
+            (true, _) => eprintln!(
+                "mk: Ignoring message '{}' in {}, it's not a string.",
+                id,
+                path.display()
+            ),
+            (false, _) => eprintln!(
+                "mk: Ignoring unknown message '{}' in {}.",
+                id,
+                path.display()
+            ),
+        }
+    }
+    overrides
+}
+
+// The message 'id' with its placeholders filled in from 'args'.
+pub fn get(id: &str, args: &[(&str, &str)]) -> String {
+    let loaded = OVERRIDES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .is_some();
+    if !loaded {
+        load(&Config::load(None));
+    }
+
+    let guard = OVERRIDES.lock().unwrap_or_else(|e| e.into_inner());
+    let template = guard
+        .as_ref()
+        .and_then(|overrides| overrides.iter().find(|(known, _)| known == id))
+        .map(|(_, text)| text.as_str())
+        .or_else(|| {
+            CATALOG
+                .iter()
+                .find(|(known, _)| *known == id)
+                .map(|(_, text)| *text)
+        })
+        .unwrap_or(id);
+
+    let mut text = template.to_string();
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), value);
+    }
+    text
+}
+
+// Point to the docs for failures of 'class', if the config links them with 'on_<class>_url',
+// e.g. to an internal runbook: 'missing_script', 'missing_venv', 'unusable_venv',
+// 'sync_failure', 'crash' or 'infrastructure'.
+pub fn print_runbook(config: &Config, class: &str) {
+    if let Some(url) = config.get(&format!("on_{}_url", class)) {
+        eprintln!("mk: {}", get("runbook", &[("url", url)]));
+    }
+}
+
+// 'mk messages'. All messages as a messages file.
+pub fn print() {
+    println!("[messages]");
+    for (id, _) in CATALOG {
+        println!("{} = {}", id, toml::quote(&get(id, &[])));
+    }
+}
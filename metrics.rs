@@ -0,0 +1,88 @@
+// Prometheus textfile collector output ('--metrics-file' / 'metrics_file'). The file
+// keeps the latest sample of every (project, task) pair, so one file can serve all
+// projects on a build machine. It is replaced atomically, as node-exporter may read it
+// at any time.
+
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use config;
+use hooks::Outcome;
+
+const METRICS: &[(&str, &str)] = &[
+    (
+        "mk_task_duration_seconds",
+        "Duration of the last run of the task.",
+    ),
+    (
+        "mk_task_success",
+        "Whether the last run of the task succeeded (1) or failed (0).",
+    ),
+    (
+        "mk_task_exit_code",
+        "Exit code of the last run of the task (-1 if killed by a signal).",
+    ),
+    (
+        "mk_task_last_run_timestamp_seconds",
+        "Unix time the last run of the task finished.",
+    ),
+];
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+pub fn write(path: &str, outcome: &Outcome) {
+    let labels = format!(
+        "{{project=\"{}\",task=\"{}\"}}",
+        escape_label(outcome.project_root),
+        escape_label(outcome.args.first().map(|a| a.as_str()).unwrap_or(""))
+    );
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let values = [
+        format!("{:.3}", outcome.duration.as_secs_f64()),
+        (if outcome.success { "1" } else { "0" }).to_string(),
+        outcome.exit_code.unwrap_or(-1).to_string(),
+        now.to_string(),
+    ];
+
+    // Keep the samples of other tasks, replace those of this one.
+    let existing = fs::read_to_string(path).unwrap_or_default();
+    let mut samples: Vec<String> = existing
+        .lines()
+        .filter(|l| !l.starts_with('#') && !l.trim().is_empty())
+        .filter(|l| !l.contains(&format!("{} ", labels)))
+        .map(|l| l.to_string())
+        .collect();
+
+    for ((name, _), value) in METRICS.iter().zip(values.iter()) {
+        samples.push(format!("{}{} {}", name, labels, value));
+    }
+
+    let mut out = String::new();
+    for (name, help) in METRICS {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} gauge\n",
+            name, help, name
+        ));
+        for sample in &samples {
+            if sample.starts_with(&format!("{}{{", name)) {
+                out.push_str(sample);
+                out.push('\n');
+            }
+        }
+    }
+
+    if let Err(e) = config::write_atomic(Path::new(path), &out) {
+        eprintln!("mk: Couldn't write metrics: {}", e);
+    }
+}
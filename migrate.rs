@@ -0,0 +1,594 @@
+// 'mk migrate [makefile|justfile] [<file>]': turn the simple targets of a Makefile or
+// justfile into a 'make.py', to ease moving a project to mk. Targets with a recipe become
+// functions that run the recipe's lines in the shell; targets that only depend on other
+// targets (like 'all: build test') become config tasks in 'mk.toml'. What can't be
+// translated (variables, pattern rules, functions, shebang recipes) is left for review,
+// marked with 'TODO'.
+
+use std::fs;
+use std::path::Path;
+
+use config;
+use json;
+
+struct Target {
+    name: String,
+    doc: Option<String>,
+    params: Vec<Param>,
+    deps: Vec<String>,
+    lines: Vec<String>,
+}
+
+struct Param {
+    name: String,
+    default: Option<String>,
+    variadic: bool,
+}
+
+const USAGE: &str = "Usage: mk migrate [makefile|justfile] [<file>]";
+
+const MAKEFILES: &[&str] = &["GNUmakefile", "makefile", "Makefile"];
+const JUSTFILES: &[&str] = &["justfile", "Justfile", ".justfile"];
+
+// Python keywords and the names make.py itself uses.
+const RESERVED: &[&str] = &[
+    "and",
+    "as",
+    "assert",
+    "async",
+    "await",
+    "break",
+    "class",
+    "continue",
+    "def",
+    "del",
+    "elif",
+    "else",
+    "except",
+    "finally",
+    "for",
+    "from",
+    "global",
+    "if",
+    "import",
+    "in",
+    "is",
+    "lambda",
+    "nonlocal",
+    "not",
+    "or",
+    "pass",
+    "raise",
+    "return",
+    "try",
+    "while",
+    "with",
+    "yield",
+    "sh",
+    "subprocess",
+    "sys",
+];
+
+fn function_name(target: &str) -> String {
+    let name: String = target
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let name = if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name
+    };
+    if RESERVED.contains(&name.as_str()) {
+        format!("{}_", name)
+    } else {
+        name
+    }
+}
+
+// Join lines ending with a backslash with the next one.
+fn logical_lines(text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut pending = String::new();
+
+    for line in text.lines() {
+        let line = if pending.is_empty() {
+            line
+        } else {
+            line.trim_start()
+        };
+        match line.strip_suffix('\\') {
+            Some(start) => {
+                pending.push_str(start);
+                pending.push(' ');
+            }
+            None => {
+                pending.push_str(line);
+                lines.push(std::mem::take(&mut pending));
+            }
+        }
+    }
+    if !pending.is_empty() {
+        lines.push(pending);
+    }
+
+    lines
+}
+
+fn comment_doc(line: &str) -> Option<String> {
+    let doc = line.trim_start_matches('#').trim();
+    if doc.is_empty() {
+        None
+    } else {
+        Some(doc.to_string())
+    }
+}
+
+fn parse_makefile(text: &str, skipped: &mut Vec<String>) -> Vec<Target> {
+    let mut targets: Vec<Target> = Vec::new();
+    // Indices of the targets of the rule being read, which share its recipe.
+    let mut current: Vec<usize> = Vec::new();
+    let mut doc = None;
+
+    for line in logical_lines(text) {
+        if line.starts_with('\t') {
+            if current.is_empty() {
+                continue;
+            }
+            for i in &current {
+                targets[*i].lines.push(line.trim().to_string());
+            }
+            continue;
+        }
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            doc = None;
+            continue;
+        }
+        if trimmed.starts_with('#') {
+            doc = comment_doc(trimmed);
+            continue;
+        }
+
+        current.clear();
+
+        // Rules are 'targets: deps', everything else (variables like 'A = b', 'A := b',
+        // directives like 'include') isn't migrated.
+        let colon = match trimmed.find(':') {
+            Some(colon)
+                if !trimmed[..colon].contains('=')
+                    && !trimmed[colon..].trim_start_matches(':').starts_with('=') =>
+            {
+                colon
+            }
+            _ => {
+                skipped.push(trimmed.to_string());
+                continue;
+            }
+        };
+
+        let names = &trimmed[..colon];
+        let rest = trimmed[colon + 1..].trim_start_matches(':');
+        // Target-specific variables, 'test: PYTHONWARNINGS = error'.
+        if rest.contains('=') {
+            skipped.push(trimmed.to_string());
+            continue;
+        }
+        let deps: Vec<String> = rest
+            .split('#')
+            .next()
+            .unwrap_or("")
+            .split_whitespace()
+            .filter(|dep| *dep != "|")
+            .map(|dep| dep.to_string())
+            .collect();
+
+        for name in names.split_whitespace() {
+            // Special targets like '.PHONY', pattern and computed rules.
+            if name.starts_with('.') || name.contains('%') || name.contains('$') {
+                if !name.starts_with(".PHONY") {
+                    skipped.push(trimmed.to_string());
+                }
+                continue;
+            }
+            // 'all' may be named twice, e.g. once for deps and once for the recipe.
+            let i = match targets.iter().position(|t| t.name == name) {
+                Some(i) => i,
+                None => {
+                    targets.push(Target {
+                        name: name.to_string(),
+                        doc: doc.clone(),
+                        params: Vec::new(),
+                        deps: Vec::new(),
+                        lines: Vec::new(),
+                    });
+                    targets.len() - 1
+                }
+            };
+            targets[i].deps.extend(deps.iter().cloned());
+            current.push(i);
+        }
+        doc = None;
+    }
+
+    targets
+}
+
+fn parse_param(word: &str) -> Param {
+    let word = word.trim_start_matches('$');
+    let (variadic, word) = match word.strip_prefix('*').or_else(|| word.strip_prefix('+')) {
+        Some(word) => (true, word),
+        None => (false, word),
+    };
+    match word.split_once('=') {
+        Some((name, default)) => Param {
+            name: name.to_string(),
+            default: Some(default.trim_matches(|c| c == '\'' || c == '"').to_string()),
+            variadic,
+        },
+        None => Param {
+            name: word.to_string(),
+            default: None,
+            variadic,
+        },
+    }
+}
+
+fn parse_justfile(text: &str, skipped: &mut Vec<String>) -> Vec<Target> {
+    let mut targets: Vec<Target> = Vec::new();
+    let mut in_recipe = false;
+    let mut doc = None;
+
+    for line in logical_lines(text) {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if in_recipe && !line.trim().is_empty() {
+                if let Some(target) = targets.last_mut() {
+                    target.lines.push(line.trim().to_string());
+                }
+            }
+            continue;
+        }
+
+        let trimmed = line.trim();
+
+        // Recipes may contain blank lines.
+        if trimmed.is_empty() {
+            doc = None;
+            continue;
+        }
+        in_recipe = false;
+        if trimmed.starts_with('#') {
+            doc = comment_doc(trimmed);
+            continue;
+        }
+        // Attributes like '[private]'.
+        if trimmed.starts_with('[') {
+            continue;
+        }
+
+        if let Some(alias) = trimmed.strip_prefix("alias ") {
+            if let Some((name, target)) = alias.split_once(":=") {
+                targets.push(Target {
+                    name: name.trim().to_string(),
+                    doc: None,
+                    params: Vec::new(),
+                    deps: vec![target.trim().to_string()],
+                    lines: Vec::new(),
+                });
+            }
+            continue;
+        }
+
+        let colon = match trimmed.find(':') {
+            Some(colon) if !trimmed[colon + 1..].starts_with('=') => colon,
+            _ => {
+                skipped.push(trimmed.to_string());
+                continue;
+            }
+        };
+        if trimmed.starts_with("set ") || trimmed.starts_with("export ") {
+            skipped.push(trimmed.to_string());
+            continue;
+        }
+
+        let mut words = trimmed[..colon].split_whitespace();
+        let name = words
+            .next()
+            .unwrap_or("")
+            .trim_start_matches('@')
+            .to_string();
+        let params = words.map(parse_param).collect();
+
+        // Dependencies with arguments, like '(build "release")', can't be translated.
+        let rest = &trimmed[colon + 1..];
+        if rest.contains('(') {
+            skipped.push(trimmed.to_string());
+        }
+        let deps = rest
+            .split('#')
+            .next()
+            .unwrap_or("")
+            .split_whitespace()
+            .filter(|dep| !dep.contains('(') && !dep.contains(')') && *dep != "&&")
+            .map(|dep| dep.to_string())
+            .collect();
+
+        targets.push(Target {
+            name,
+            doc: doc.take(),
+            params,
+            deps,
+            lines: Vec::new(),
+        });
+        in_recipe = true;
+    }
+
+    targets
+}
+
+// A Python expression for a recipe line, and what needs a look if it can't be translated
+// properly. 'params' can be referenced as '{{name}}' in justfiles; those become f-string
+// fields.
+fn python_command(line: &str, params: &[Param]) -> (String, Option<String>) {
+    let mut todo = None;
+
+    // '$$' is a literal '$' in Makefiles.
+    let line = line.replace("$$", "\u{0}");
+    if line.contains("$(") || line.contains("${") {
+        todo = Some("uses make variables or functions".to_string());
+    }
+    let line = line.replace('\u{0}', "$");
+
+    if !line.contains("{{") {
+        return (json::quote(&line), todo);
+    }
+
+    let mut out = String::new();
+    let mut rest = line.as_str();
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start].replace('{', "{{").replace('}', "}}"));
+        let end = match rest[start..].find("}}") {
+            Some(end) => start + end,
+            None => break,
+        };
+        let expr = rest[start + 2..end].trim();
+        match params.iter().find(|p| p.name == expr) {
+            Some(param) if param.variadic => {
+                out.push_str(&format!("{{' '.join({})}}", function_name(expr)))
+            }
+            Some(_) => out.push_str(&format!("{{{}}}", function_name(expr))),
+            None => {
+                todo = Some(format!("uses '{{{{{}}}}}'", expr));
+                out.push_str(&format!("{{{{{{{{{}}}}}}}}}", expr));
+            }
+        }
+        rest = &rest[end + 2..];
+    }
+    out.push_str(&rest.replace('{', "{{").replace('}', "}}"));
+
+    (format!("f{}", json::quote(&out)), todo)
+}
+
+fn python_params(params: &[Param]) -> String {
+    params
+        .iter()
+        .map(|p| match (&p.default, p.variadic) {
+            (_, true) => format!("*{}", function_name(&p.name)),
+            (Some(default), false) => {
+                format!("{}={}", function_name(&p.name), json::quote(default))
+            }
+            (None, false) => function_name(&p.name),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn make_py(targets: &[&Target], aliases: &[&Target], source: &str, todos: &mut usize) -> String {
+    let mut out = String::from(
+        "import subprocess\nimport sys\n\n\ndef sh(command, check=True):\n    subprocess.run(command, shell=True, check=check)\n",
+    );
+
+    for target in targets {
+        out.push_str(&format!(
+            "\n\ndef {}({}):\n",
+            function_name(&target.name),
+            python_params(&target.params)
+        ));
+        let doc = target
+            .doc
+            .clone()
+            .unwrap_or_else(|| format!("Migrated from {}.", source));
+        out.push_str(&format!(
+            "    \"\"\"{}\"\"\"\n",
+            doc.replace("\"\"\"", "'''")
+        ));
+
+        for dep in &target.deps {
+            // Dependencies are called without arguments.
+            if targets.iter().any(|t| {
+                &t.name == dep && t.params.iter().all(|p| p.default.is_some() || p.variadic)
+            }) {
+                out.push_str(&format!("    {}()\n", function_name(dep)));
+            } else if aliases.iter().any(|t| &t.name == dep) {
+                out.push_str(&format!(
+                    "    sh({})\n",
+                    json::quote(&format!("mk {}", dep))
+                ));
+            } else {
+                out.push_str(&format!(
+                    "    # TODO: depended on '{}', which isn't a simple target.\n",
+                    dep
+                ));
+                *todos += 1;
+            }
+        }
+
+        if target.lines.first().is_some_and(|l| l.starts_with("#!")) {
+            out.push_str("    # TODO: shebang recipe, translate to Python:\n");
+            for line in &target.lines {
+                out.push_str(&format!("    # {}\n", line));
+            }
+            *todos += 1;
+            continue;
+        }
+
+        for line in &target.lines {
+            // '@' only silences echoing, '-' ignores failures.
+            let (line, check) = match line.trim_start_matches('@').strip_prefix('-') {
+                Some(line) => (line.trim_start_matches('@').trim(), false),
+                None => (line.trim_start_matches('@').trim(), true),
+            };
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (command, todo) = python_command(line, &target.params);
+            if let Some(todo) = todo {
+                out.push_str(&format!("    # TODO: {}.\n", todo));
+                *todos += 1;
+            }
+            if check {
+                out.push_str(&format!("    sh({})\n", command));
+            } else {
+                out.push_str(&format!("    sh({}, check=False)\n", command));
+            }
+        }
+
+        if target.lines.is_empty() && target.deps.is_empty() {
+            out.push_str("    pass\n");
+        }
+    }
+
+    let tasks: Vec<String> = targets
+        .iter()
+        .map(|t| format!("{}: {}", json::quote(&t.name), function_name(&t.name)))
+        .collect();
+    out.push_str(&format!("\n\nTASKS = {{{}}}\n", tasks.join(", ")));
+    out.push_str(
+        "\n\nif __name__ == \"__main__\":\n    if len(sys.argv) < 2 or sys.argv[1] not in TASKS:\n        print(\"Usage: mk <task>, tasks: \" + \", \".join(TASKS), file=sys.stderr)\n        sys.exit(2)\n    TASKS[sys.argv[1]](*sys.argv[2:])\n",
+    );
+    out
+}
+
+fn find_source<'a>(dir: &Path, names: &[&'a str]) -> Option<&'a str> {
+    names.iter().find(|name| dir.join(name).is_file()).cloned()
+}
+
+pub fn run(args: &[String], cur_dir: &str) -> i32 {
+    let dir = Path::new(cur_dir);
+
+    let (kind, file) = match args {
+        [] => match (find_source(dir, JUSTFILES), find_source(dir, MAKEFILES)) {
+            (Some(file), _) => ("justfile", file.to_string()),
+            (None, Some(file)) => ("makefile", file.to_string()),
+            (None, None) => {
+                eprintln!("mk: There is no Makefile or justfile here. {}", USAGE);
+                return 1;
+            }
+        },
+        [kind] | [kind, _] if kind == "makefile" || kind == "justfile" => {
+            let names = if kind == "makefile" {
+                MAKEFILES
+            } else {
+                JUSTFILES
+            };
+            let file = match args.get(1) {
+                Some(file) => file.clone(),
+                None => match find_source(dir, names) {
+                    Some(file) => file.to_string(),
+                    None => {
+                        eprintln!("mk: There is no {} here.", kind);
+                        return 1;
+                    }
+                },
+            };
+            (kind.as_str(), file)
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let make_py_file = dir.join("make.py");
+    if make_py_file.exists() {
+        eprintln!("mk: {} already exists.", make_py_file.display());
+        return 1;
+    }
+
+    let text = match fs::read_to_string(dir.join(&file)) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("mk: Cannot read {}: {}", file, e);
+            return 1;
+        }
+    };
+
+    let mut skipped = Vec::new();
+    let targets = if kind == "makefile" {
+        parse_makefile(&text, &mut skipped)
+    } else {
+        parse_justfile(&text, &mut skipped)
+    };
+
+    // Targets that only bundle others are config tasks, everything else goes to make.py.
+    let (aliases, functions): (Vec<&Target>, Vec<&Target>) = targets
+        .iter()
+        .partition(|t| t.lines.is_empty() && !t.deps.is_empty() && t.params.is_empty());
+
+    let mut todos = 0;
+    let script = make_py(&functions, &aliases, &file, &mut todos);
+
+    if let Err(e) = fs::write(&make_py_file, script) {
+        eprintln!("mk: Cannot write {}: {}", make_py_file.display(), e);
+        return 1;
+    }
+    println!(
+        "Created {} with {} tasks from {}.",
+        make_py_file.display(),
+        functions.len(),
+        file
+    );
+
+    if !aliases.is_empty() {
+        let config_file = config::project_config_path(cur_dir);
+        for alias in &aliases {
+            let steps: Vec<String> = alias
+                .deps
+                .iter()
+                .map(|dep| json::quote(&format!("mk {}", dep)))
+                .collect();
+            let key = format!("task.{}.steps", alias.name);
+            if let Err(e) =
+                config::set_in_file(&config_file, &key, &format!("[{}]", steps.join(", ")))
+            {
+                eprintln!("mk: Cannot add '{}': {}", key, e);
+                return 1;
+            }
+        }
+        println!(
+            "Added config tasks to {}: {}",
+            config_file.display(),
+            aliases
+                .iter()
+                .map(|a| a.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    if !skipped.is_empty() {
+        println!("Not migrated, please check:");
+        for line in &skipped {
+            println!("    {}", line);
+        }
+    }
+    if todos > 0 {
+        println!(
+            "{} places in make.py need a look, search for 'TODO'.",
+            todos
+        );
+    }
+
+    0
+}
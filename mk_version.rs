@@ -0,0 +1,174 @@
+// Pinning mk per project, for the same task runner behavior on every machine. A committed
+// '.mk-version' at the project root, or above it in the repository, holds the version the
+// project is run with, e.g. '0.4.2', or '0.4' for any 0.4.x. Other versions of mk run the
+// newest matching one of the toolchain dir instead, like rustup does for 'rust-toolchain':
+//
+//     ~/.local/share/mk/toolchains/0.4.2/mk
+//
+// If there is none, mk stops with an error, or only warns with 'mk_version_check = "warn"'.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::process::Command;
+
+use config::Config;
+use messages;
+
+pub const FILE: &str = ".mk-version";
+
+// Set for the pinned mk mk runs, so a toolchain that doesn't match doesn't run mk again.
+const DELEGATED: &str = "MK_DELEGATED_FROM";
+
+const BINARY: &str = if cfg!(windows) { "mk.exe" } else { "mk" };
+
+// '0.4.2' as [0, 4, 2]. None if it isn't a version.
+fn parse(version: &str) -> Option<Vec<u64>> {
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let parts: Option<Vec<u64>> = version.split('.').map(|part| part.parse().ok()).collect();
+    parts.filter(|parts| (1..=3).contains(&parts.len()))
+}
+
+// Whether 'version' is one of those 'pinned' stands for.
+fn matches(pinned: &[u64], version: &[u64]) -> bool {
+    version.len() >= pinned.len() && version[..pinned.len()] == *pinned
+}
+
+// The nearest '.mk-version' at or above 'project_root', and the version in it: the first line
+// that isn't empty or a '#' comment.
+fn find(project_root: &Path) -> Option<(PathBuf, String)> {
+    let path = project_root
+        .ancestors()
+        .map(|dir| dir.join(FILE))
+        .find(|path| path.is_file())?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("mk: Cannot read {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    };
+    let version = content
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .unwrap_or_default()
+        .to_string();
+    Some((path, version))
+}
+
+// Where versions of mk are installed, one dir per version, see 'toolchain_dir'.
+pub fn toolchain_dir(config: &Config) -> Option<PathBuf> {
+    if let Some(dir) = config.get("toolchain_dir") {
+        return Some(PathBuf::from(dir));
+    }
+    match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("mk").join("toolchains")),
+        _ => Some(
+            env::home_dir()?
+                .join(".local")
+                .join("share")
+                .join("mk")
+                .join("toolchains"),
+        ),
+    }
+}
+
+// The newest mk in 'dir' that 'pinned' stands for.
+fn installed(dir: &Path, pinned: &[u64]) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let version = parse(&entry.file_name().to_string_lossy())?;
+            let binary = entry.path().join(BINARY);
+            if matches(pinned, &version) && binary.is_file() {
+                Some((version, binary))
+            } else {
+                None
+            }
+        })
+        .max()
+        .map(|(_, binary)| binary)
+}
+
+// Check the project's '.mk-version', and run the pinned mk with the args of this run,
+// started in 'started_in', if this isn't it. Returns if this mk may go on.
+pub fn check(config: &Config, project_root: &str, started_in: Option<&Path>) {
+    let delegated_from = env::var(DELEGATED).ok();
+    env::remove_var(DELEGATED);
+
+    let mode = config.get("mk_version_check").unwrap_or("error");
+    if mode == "off" {
+        return;
+    }
+    let (path, pinned) = match find(Path::new(project_root)) {
+        Some(found) => found,
+        None => return,
+    };
+    let version = match parse(&pinned) {
+        Some(version) => version,
+        None => {
+            eprintln!(
+                "mk: '{}' in {} is not a version, e.g. '0.4.2' or '0.4'.",
+                pinned,
+                path.display()
+            );
+            process::exit(1);
+        }
+    };
+    if parse(crate::VERSION).is_some_and(|ours| matches(&version, &ours)) {
+        crate::explain(&format!("mk {} matches {}", crate::VERSION, path.display()));
+        return;
+    }
+
+    let dir = toolchain_dir(config);
+    let binary = match (&delegated_from, &dir) {
+        (None, Some(dir)) => installed(dir, &version),
+        _ => None,
+    };
+    if let Some(binary) = binary {
+        crate::explain(&format!(
+            "{} pins mk {}, running {}",
+            path.display(),
+            pinned,
+            binary.display()
+        ));
+        let mut mk = Command::new(&binary);
+        mk.args(env::args_os().skip(1))
+            .env(DELEGATED, crate::VERSION);
+        if let Some(dir) = started_in {
+            mk.current_dir(dir);
+        }
+        match crate::exec_task(&mut mk) {
+            Ok(status) => process::exit(status.code().unwrap_or(1)),
+            Err(e) => {
+                eprintln!("mk: Cannot run {}: {}", binary.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let expected = dir.map_or(format!("<toolchain_dir>/{}/{}", pinned, BINARY), |dir| {
+        dir.join(&pinned).join(BINARY).display().to_string()
+    });
+    let mut msg = messages::get(
+        "mk_version_mismatch",
+        &[
+            ("pinned", &pinned),
+            ("file", &path.display().to_string()),
+            ("version", crate::VERSION),
+            ("binary", &expected),
+        ],
+    );
+    if let Some(from) = delegated_from {
+        msg.push_str(&format!(" (started by mk {})", from));
+    }
+    if mode == "warn" {
+        crate::warn(&msg);
+    } else {
+        eprintln!("mk: {}", msg);
+        process::exit(1);
+    }
+}
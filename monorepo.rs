@@ -0,0 +1,148 @@
+// Workspaces: repositories with several projects, each with its own make.py, venv and cache
+// entry. The config at the top of the repository lists them:
+//
+//     workspace_members = ["services/*", "libs/shared"]
+//
+// A '/*' entry stands for every subdir with a task script. Members are named after their
+// dir, so 'mk @api test' runs the task 'test' of 'services/api' from anywhere in the
+// repository, like 'mk --project services/api test'. mk then runs as if started in the
+// member, so its venv is resolved and cached on its own.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use config;
+
+pub struct Member {
+    pub name: String,
+    pub dir: PathBuf,
+}
+
+// The nearest dir at or above 'cur_dir' whose config lists workspace members, and them.
+fn find_root(cur_dir: &Path) -> Option<(PathBuf, Vec<String>)> {
+    cur_dir
+        .ancestors()
+        .find_map(|dir| config::workspace_members(dir).map(|members| (dir.to_path_buf(), members)))
+}
+
+fn has_script(dir: &Path) -> bool {
+    fs::symlink_metadata(dir.join(crate::script_name(dir))).is_ok()
+}
+
+// The members of the workspace at 'root', in the order of 'patterns'.
+pub fn members(root: &Path, patterns: &[String]) -> Vec<Member> {
+    let mut members = Vec::new();
+    for pattern in patterns {
+        let pattern = pattern.trim_end_matches('/');
+        let parent = match pattern {
+            "*" => Some(""),
+            _ => pattern.strip_suffix("/*"),
+        };
+        let dirs: Vec<PathBuf> = match parent {
+            Some(parent) => {
+                let mut dirs: Vec<PathBuf> = match fs::read_dir(root.join(parent)) {
+                    Ok(entries) => entries
+                        .flatten()
+                        .map(|entry| entry.path())
+                        .filter(|dir| dir.is_dir() && has_script(dir))
+                        .collect(),
+                    Err(_) => Vec::new(),
+                };
+                dirs.sort();
+                dirs
+            }
+            None => vec![root.join(pattern)],
+        };
+        for dir in dirs {
+            let name = match dir.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => continue,
+            };
+            if members.iter().all(|m: &Member| m.dir != dir) {
+                members.push(Member { name, dir });
+            }
+        }
+    }
+    members
+}
+
+// The dir of '--project <path>' or '@<name>'. A path is relative to the workspace root, or
+// to 'cur_dir' outside of workspaces. Exits with an error if there's no such project.
+pub fn resolve(project: &str, cur_dir: &Path) -> PathBuf {
+    let workspace = find_root(cur_dir);
+
+    let name = match project.strip_prefix('@') {
+        Some(name) => name,
+        None => {
+            let base = workspace.map_or(cur_dir.to_path_buf(), |(root, _)| root);
+            let dir = base.join(project);
+            if !dir.is_dir() {
+                eprintln!("mk: There is no project dir {}.", dir.display());
+                process::exit(2);
+            }
+            return dir;
+        }
+    };
+
+    let (root, patterns) = match workspace {
+        Some(workspace) => workspace,
+        None => {
+            eprintln!(
+                "mk: '@{}' needs a workspace: list its members in the mk.toml at the top of the repository, e.g. workspace_members = [\"services/*\"].",
+                name
+            );
+            process::exit(2);
+        }
+    };
+
+    let members = members(&root, &patterns);
+    let found: Vec<&Member> = members.iter().filter(|m| m.name == name).collect();
+    match found.as_slice() {
+        [member] => member.dir.clone(),
+        [] => {
+            let names: Vec<String> = members.iter().map(|m| format!("@{}", m.name)).collect();
+            eprintln!(
+                "mk: The workspace {} has no member '{}'. Members: {}",
+                root.display(),
+                name,
+                if names.is_empty() {
+                    "none".to_string()
+                } else {
+                    names.join(", ")
+                }
+            );
+            process::exit(2);
+        }
+        _ => {
+            let dirs: Vec<String> = found
+                .iter()
+                .map(|m| {
+                    m.dir
+                        .strip_prefix(&root)
+                        .unwrap_or(&m.dir)
+                        .display()
+                        .to_string()
+                })
+                .collect();
+            eprintln!(
+                "mk: '@{}' is ambiguous in {}: {}. Use '--project <dir>' instead.",
+                name,
+                root.display(),
+                dirs.join(", ")
+            );
+            process::exit(2);
+        }
+    }
+}
+
+// Names of the members of the workspace around 'cur_dir', for completion.
+pub fn member_names(cur_dir: &Path) -> Vec<String> {
+    match find_root(cur_dir) {
+        Some((root, patterns)) => members(&root, &patterns)
+            .into_iter()
+            .map(|m| m.name)
+            .collect(),
+        None => Vec::new(),
+    }
+}
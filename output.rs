@@ -0,0 +1,324 @@
+// Output handling for tasks. By default make.py writes to mk's stdout and stderr
+// directly. With a log file or the spinner, both streams are pumped through mk: they are
+// copied to the file as well, like 'tee', and watched for silence.
+
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pty;
+use sys;
+
+// Removes ANSI escape sequences (colors, cursor movement, terminal titles) from a byte
+// stream. Sequences may be split across reads, so the state is kept between calls.
+#[derive(Clone, Copy, PartialEq)]
+enum Ansi {
+    Text,
+    // After ESC.
+    Escape,
+    // Inside 'ESC [ ...', ended by a byte in 0x40..=0x7e.
+    Csi,
+    // Inside 'ESC ] ...', ended by BEL or 'ESC \'.
+    Osc,
+    // After ESC inside an OSC sequence.
+    OscEscape,
+}
+
+pub struct AnsiStripper {
+    state: Ansi,
+}
+
+impl AnsiStripper {
+    pub fn new() -> AnsiStripper {
+        AnsiStripper { state: Ansi::Text }
+    }
+
+    pub fn strip(&mut self, input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(input.len());
+
+        for &byte in input {
+            self.state = match (self.state, byte) {
+                (Ansi::Text, 0x1b) => Ansi::Escape,
+                (Ansi::Text, _) => {
+                    out.push(byte);
+                    Ansi::Text
+                }
+                (Ansi::Escape, b'[') => Ansi::Csi,
+                (Ansi::Escape, b']') => Ansi::Osc,
+                // Two byte sequences like 'ESC c' or 'ESC 7'.
+                (Ansi::Escape, _) => Ansi::Text,
+                (Ansi::Csi, 0x40..=0x7e) => Ansi::Text,
+                (Ansi::Csi, _) => Ansi::Csi,
+                (Ansi::Osc, 0x07) => Ansi::Text,
+                (Ansi::Osc, 0x1b) => Ansi::OscEscape,
+                (Ansi::Osc, _) => Ansi::Osc,
+                (Ansi::OscEscape, b'\\') => Ansi::Text,
+                (Ansi::OscEscape, _) => Ansi::Osc,
+            };
+        }
+
+        out
+    }
+}
+
+// Tools only emit colors when writing to a terminal, which make.py no longer does when
+// its output is pumped through mk. Ask them to keep colors if mk's own output is a
+// terminal, unless the user disabled colors. 'force_color' is the setting: 'always' asks
+// for colors even if no terminal is involved, e.g. for CI logs that render them, 'never'
+// never does.
+pub fn color_env(force_color: Option<&str>, pumped: bool) -> Vec<(String, String)> {
+    let wanted = match force_color {
+        Some("always") => true,
+        Some("never") => false,
+        _ => pumped && io::stdout().is_terminal(),
+    };
+    if !wanted || env::var_os("NO_COLOR").is_some() {
+        return Vec::new();
+    }
+
+    ["FORCE_COLOR", "CLICOLOR_FORCE", "PY_COLORS"]
+        .iter()
+        .filter(|name| env::var_os(name).is_none())
+        .map(|name| (name.to_string(), "1".to_string()))
+        .collect()
+}
+
+pub struct Options {
+    pub log_file: Option<String>,
+    // Remove ANSI escape sequences from the log file copy.
+    pub strip_ansi: bool,
+    // Show a spinner on stderr after this much silence, see 'spinner_after'.
+    pub spinner_after: Option<Duration>,
+    // What the spinner says is running.
+    pub label: String,
+    // Keep the end of stderr, e.g. to tell crashes from failures.
+    pub keep_stderr_tail: bool,
+    // Connect stdout and stderr to a pseudo-terminal, which merges them into one stream.
+    pub pty: bool,
+    // The 'force_color' setting, see color_env().
+    pub force_color: Option<String>,
+}
+
+pub struct Pumped {
+    pub status: ExitStatus,
+    // The last STDERR_TAIL bytes of stderr, if asked for.
+    pub stderr_tail: Vec<u8>,
+}
+
+const STDERR_TAIL: usize = 8192;
+
+// State shared by the pumps and the spinner. Terminal writes happen while holding it, so
+// the spinner never ends up in the middle of a line of output.
+struct Shared {
+    log: Option<File>,
+    last_output: Instant,
+    // Whether the last output ended a line, i.e. the spinner may take the line.
+    line_start: bool,
+    spinner_visible: bool,
+    stderr_tail: Vec<u8>,
+}
+
+pub const CLEAR_LINE: &str = "\r\x1b[K";
+pub const SPINNER_FRAMES: &[char] = &['-', '\\', '|', '/'];
+
+fn pump<R: Read + Send + 'static, W: Write + Send + 'static>(
+    mut from: R,
+    mut to: W,
+    shared: Arc<Mutex<Shared>>,
+    strip_ansi: bool,
+    keep_tail: bool,
+) -> thread::JoinHandle<()> {
+    let mut stripper = AnsiStripper::new();
+
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            let n = match from.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+
+            let mut shared = match shared.lock() {
+                Ok(shared) => shared,
+                Err(_) => break,
+            };
+
+            if shared.spinner_visible {
+                eprint!("{}", CLEAR_LINE);
+                shared.spinner_visible = false;
+            }
+
+            let _ = to.write_all(&buf[..n]);
+            let _ = to.flush();
+            shared.last_output = Instant::now();
+            shared.line_start = buf[n - 1] == b'\n';
+
+            if keep_tail {
+                shared.stderr_tail.extend_from_slice(&buf[..n]);
+                let excess = shared.stderr_tail.len().saturating_sub(STDERR_TAIL);
+                shared.stderr_tail.drain(..excess);
+            }
+
+            if let Some(log) = shared.log.as_mut() {
+                let chunk = if strip_ansi {
+                    stripper.strip(&buf[..n])
+                } else {
+                    buf[..n].to_vec()
+                };
+                let _ = log.write_all(&chunk);
+            }
+        }
+    })
+}
+
+fn spinner(
+    shared: Arc<Mutex<Shared>>,
+    done: Arc<AtomicBool>,
+    after: Duration,
+    label: String,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut frame = 0;
+        while !done.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(100));
+
+            let mut shared = match shared.lock() {
+                Ok(shared) => shared,
+                Err(_) => break,
+            };
+
+            let quiet = shared.last_output.elapsed();
+            if quiet < after || !shared.line_start || done.load(Ordering::Relaxed) {
+                continue;
+            }
+
+            eprint!(
+                "{}{} {} (no output for {}s)",
+                CLEAR_LINE,
+                SPINNER_FRAMES[frame % SPINNER_FRAMES.len()],
+                label,
+                quiet.as_secs()
+            );
+            let _ = io::stderr().flush();
+            shared.spinner_visible = true;
+            frame += 1;
+        }
+    })
+}
+
+fn spawn_piped(
+    cmd: &mut Command,
+    shared: &Arc<Mutex<Shared>>,
+    options: &Options,
+) -> io::Result<(Child, Vec<thread::JoinHandle<()>>)> {
+    // Python buffers output to pipes in blocks, which would make the task look quiet.
+    if env::var_os("PYTHONUNBUFFERED").is_none() {
+        cmd.env("PYTHONUNBUFFERED", "1");
+    }
+
+    let mut child = cmd
+        .envs(color_env(options.force_color.as_deref(), true))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stdout = child.stdout.take().expect("mk: Child stdout is piped.");
+    let stderr = child.stderr.take().expect("mk: Child stderr is piped.");
+
+    let pumps = vec![
+        pump(
+            stdout,
+            io::stdout(),
+            shared.clone(),
+            options.strip_ansi,
+            false,
+        ),
+        pump(
+            stderr,
+            io::stderr(),
+            shared.clone(),
+            options.strip_ansi,
+            options.keep_stderr_tail,
+        ),
+    ];
+
+    Ok((child, pumps))
+}
+
+// Run 'cmd' with its stdout and stderr going through mk, for the log file copy, the
+// spinner and the stderr tail.
+pub fn run_pumped(cmd: &mut Command, options: &Options) -> io::Result<Pumped> {
+    let log = match &options.log_file {
+        Some(path) => Some(File::create(path).map_err(|e| {
+            io::Error::new(e.kind(), format!("cannot create log file {}: {}", path, e))
+        })?),
+        None => None,
+    };
+
+    let shared = Arc::new(Mutex::new(Shared {
+        log,
+        last_output: Instant::now(),
+        line_start: true,
+        spinner_visible: false,
+        stderr_tail: Vec::new(),
+    }));
+
+    sys::prepare_forwarding(cmd);
+    let (mut child, pumps) = if options.pty {
+        let pty = pty::open()?;
+        let child = cmd.stdout(pty.slave.try_clone()?).stderr(pty.slave).spawn();
+        // Drop our copies of the slave side, so reading the master ends when the task exits.
+        cmd.stdout(Stdio::null()).stderr(Stdio::null());
+        let child = child?;
+
+        let pump = pump(
+            pty.master,
+            io::stdout(),
+            shared.clone(),
+            options.strip_ansi,
+            options.keep_stderr_tail,
+        );
+        (child, vec![pump])
+    } else {
+        spawn_piped(cmd, &shared, options)?
+    };
+
+    let done = Arc::new(AtomicBool::new(false));
+    let spinner = options
+        .spinner_after
+        .filter(|_| io::stderr().is_terminal())
+        .map(|after| spinner(shared.clone(), done.clone(), after, options.label.clone()));
+
+    let forwarding = sys::forward_signals(&child);
+    let status = child.wait();
+    drop(forwarding);
+
+    for handle in pumps {
+        let _ = handle.join();
+    }
+
+    done.store(true, Ordering::Relaxed);
+    if let Some(handle) = spinner {
+        let _ = handle.join();
+    }
+
+    let mut stderr_tail = Vec::new();
+    if let Ok(mut shared) = shared.lock() {
+        if shared.spinner_visible {
+            eprint!("{}", CLEAR_LINE);
+        }
+        stderr_tail = std::mem::take(&mut shared.stderr_tail);
+    }
+
+    Ok(Pumped {
+        status: status?,
+        stderr_tail,
+    })
+}
@@ -0,0 +1,80 @@
+// 'mk pipe <task> <task>...': run tasks one after another, feeding the stdout of each
+// into the stdin of the next, like 'mk build | mk publish'. Unlike a shell pipeline, a
+// stage only starts once the previous one succeeded, so a failing build never feeds
+// partial output to publish.
+
+use std::io;
+use std::io::prelude::*;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+pub struct Stage {
+    pub args: Vec<String>,
+    pub status: ExitStatus,
+    pub started: SystemTime,
+    pub duration: Duration,
+}
+
+// Stages are given as one arg each, e.g. 'mk pipe "build --release" publish'.
+pub fn parse(args: &[String]) -> Vec<Vec<String>> {
+    args.iter()
+        .map(|stage| stage.split_whitespace().map(|a| a.to_string()).collect())
+        .filter(|stage: &Vec<String>| !stage.is_empty())
+        .collect()
+}
+
+// Run the stages, stopping at the first that fails. Returns the stages that ran.
+pub fn run(stages: &[Vec<String>], task: &dyn Fn(&[String]) -> Command) -> io::Result<Vec<Stage>> {
+    let mut done = Vec::new();
+    let mut input: Option<Vec<u8>> = None;
+
+    for (i, args) in stages.iter().enumerate() {
+        let last = i + 1 == stages.len();
+
+        let mut cmd = task(args);
+        if input.is_some() {
+            cmd.stdin(Stdio::piped());
+        }
+        if !last {
+            cmd.stdout(Stdio::piped());
+        }
+
+        let started = SystemTime::now();
+        let clock = Instant::now();
+        let mut child = cmd.spawn()?;
+
+        // Feed the previous output from a thread, so a stage that writes before it reads
+        // can't deadlock with us.
+        let feeder = match (child.stdin.take(), input.take()) {
+            (Some(mut stdin), Some(data)) => Some(thread::spawn(move || {
+                let _ = stdin.write_all(&data);
+            })),
+            _ => None,
+        };
+
+        let mut output = Vec::new();
+        if let Some(mut stdout) = child.stdout.take() {
+            stdout.read_to_end(&mut output)?;
+        }
+
+        let status = child.wait()?;
+        if let Some(feeder) = feeder {
+            let _ = feeder.join();
+        }
+
+        done.push(Stage {
+            args: args.clone(),
+            status,
+            started,
+            duration: clock.elapsed(),
+        });
+
+        if !status.success() {
+            break;
+        }
+        input = Some(output);
+    }
+
+    Ok(done)
+}
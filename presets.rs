@@ -0,0 +1,113 @@
+// Shared base configs: 'extends = "github:myorg/mk-presets#python"' in a config file
+// merges 'python.toml' from that repository below the file's own settings, so org-wide
+// conventions (tasks, hooks, wrappers) can be maintained in one place. A local path,
+// relative to the config file, works too. 'extends' can also be a list; later entries
+// override earlier ones.
+//
+// Repositories are cloned with 'git' into the cache dir and updated at most once a day.
+// If an update fails, e.g. when offline, the copy from the last update is used until the
+// next try.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+use shared_cache;
+
+const REFRESH_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+// The file 'spec' refers to, fetching it first if it's remote. 'dir' is the dir of the
+// config file the spec is from.
+pub fn resolve(spec: &str, dir: &Path) -> Result<PathBuf, String> {
+    match spec.strip_prefix("github:") {
+        Some(rest) => {
+            let (repo, name) = match rest.find('#') {
+                Some(i) => (&rest[..i], &rest[i + 1..]),
+                None => (rest, "mk"),
+            };
+            if repo.split('/').count() != 2 || repo.split('/').any(|part| part.is_empty()) {
+                return Err(format!(
+                    "expected 'github:<owner>/<repo>#<name>', got '{}'",
+                    spec
+                ));
+            }
+            let checkout = fetch(&format!("https://github.com/{}.git", repo))?;
+            let path = checkout.join(format!("{}.toml", name));
+            if !path.is_file() {
+                return Err(format!("there is no '{}.toml' in {}", name, repo));
+            }
+            Ok(path)
+        }
+        None => {
+            let path = dir.join(spec);
+            if !path.is_file() {
+                return Err(format!("{} does not exist", path.display()));
+            }
+            Ok(path)
+        }
+    }
+}
+
+fn fetched_recently(marker: &Path) -> bool {
+    fs::metadata(marker)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age < REFRESH_AFTER)
+}
+
+fn git(args: &[&str]) -> Result<(), String> {
+    let output = Command::new("git")
+        .args(args)
+        // Fail instead of waiting for credentials nobody is going to type.
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("failed to execute 'git': {}", e))?;
+
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(())
+}
+
+// A checkout of 'url' in the cache dir.
+fn fetch(url: &str) -> Result<PathBuf, String> {
+    let presets_dir = Path::new(&crate::cache_dir()).join("presets");
+    let checkout = presets_dir.join(shared_cache::hash_key(url));
+    let marker = checkout.with_extension("fetched");
+
+    if checkout.is_dir() && (fetched_recently(&marker) || crate::read_only()) {
+        return Ok(checkout);
+    }
+    if crate::read_only() {
+        return Err(format!("{} was never fetched and mk is read-only", url));
+    }
+
+    let checkout_str = checkout.to_string_lossy().to_string();
+    let result = if checkout.is_dir() {
+        git(&["-C", &checkout_str, "pull", "--quiet", "--ff-only"])
+    } else {
+        fs::create_dir_all(&presets_dir)
+            .map_err(|e| format!("cannot create {}: {}", presets_dir.display(), e))?;
+        git(&["clone", "--quiet", "--depth", "1", url, &checkout_str])
+    };
+
+    match result {
+        Ok(()) => {
+            let _ = fs::write(&marker, url);
+            Ok(checkout)
+        }
+        Err(e) if checkout.is_dir() => {
+            // Try again tomorrow instead of on every run while offline.
+            let _ = fs::write(&marker, url);
+            eprintln!(
+                "mk: Couldn't update {}, using the copy from the last update: {}",
+                url, e
+            );
+            Ok(checkout)
+        }
+        Err(e) => Err(format!("cannot fetch {}: {}", url, e)),
+    }
+}
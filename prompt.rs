@@ -0,0 +1,63 @@
+// 'mk prompt': a short status for shell prompts, e.g. a starship or powerlevel10k custom
+// segment. It runs on every prompt, so it only reads the cache and the venv's 'pyvenv.cfg'
+// and never runs uv, poetry, git or Python. Prints:
+//
+//     myproject py3.12          the cached venv is there and fine
+//     myproject py3.12 stale    requires-python changed and the venv doesn't satisfy it
+//     myproject missing         the cached venv is gone
+//     myproject new             mk hasn't resolved a venv for the project yet
+//
+// Outside of projects it prints nothing.
+
+use std::path::Path;
+
+use cache::CacheStore;
+use config::Config;
+use requires_python;
+use slow_fs;
+use stamp;
+
+pub fn run(config: &Config, store: &CacheStore, project_root: &str) {
+    let name = Path::new(project_root)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    // Only the path key, 'cache_key = git' would need git.
+    let venv_path = match store.get(project_root) {
+        Some(venv_path) => venv_path,
+        None => {
+            println!("{} new", name);
+            return;
+        }
+    };
+
+    if !slow_fs::python_exists(config, &crate::venv_python(&venv_path)) {
+        println!("{} missing", name);
+        return;
+    }
+
+    let version = match requires_python::venv_version(&venv_path) {
+        Some(version) => version,
+        None => {
+            println!("{}", name);
+            return;
+        }
+    };
+
+    let spec = requires_python::read();
+    let changed = store.get_stamp(project_root).is_some_and(|stamp| {
+        stamp::requires_python(&stamp) != requires_python::stamp(spec.as_deref())
+    });
+    let stale = changed
+        && spec
+            .as_ref()
+            .is_some_and(|spec| requires_python::satisfies(spec, &version) == Some(false));
+
+    println!(
+        "{} py{}{}",
+        name,
+        requires_python::format_version(&version[..version.len().min(2)]),
+        if stale { " stale" } else { "" }
+    );
+}
@@ -0,0 +1,121 @@
+// Capability handshake between mk and make.py. mk tells make.py which protocols it
+// speaks through 'MK_FEATURES', e.g. 'MK_FEATURES=features@1,listing@2'. make.py
+// advertises what it speaks with a comment near the top of the file:
+//
+//     # mk: features=listing@1,events@2
+//
+// Both sides then use the lower version of every protocol they share, so new protocols
+// degrade gracefully with older scripts and older mk binaries. A script that can't work
+// with less can pin a minimum with '# mk: requires=listing@2'.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+// Protocols mk speaks and the newest version of each.
+pub const FEATURES: &[(&str, u32)] = &[
+    // The handshake itself.
+    ("features", 1),
+    // 'MK_COMPLETE', see completion.rs.
+    ("completion", 1),
+    // 'MK_LIST', see listing.rs.
+    ("listing", 1),
+];
+
+// Only the top of make.py is searched, so huge scripts don't slow down every run.
+const HEADER_LINES: usize = 50;
+
+// What make.py declared in its '# mk:' comments.
+pub struct Script {
+    pub features: Vec<(String, u32)>,
+    pub requires: Vec<(String, u32)>,
+}
+
+pub fn env_value() -> String {
+    FEATURES
+        .iter()
+        .map(|(name, version)| format!("{}@{}", name, version))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Parse a list like 'listing@1,events@2'. A feature without version is version 1.
+fn parse_list(list: &str) -> Vec<(String, u32)> {
+    list.split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .filter_map(|item| match item.split_once('@') {
+            Some((name, version)) => version
+                .trim()
+                .parse()
+                .ok()
+                .map(|v| (name.trim().to_string(), v)),
+            None => Some((item.to_string(), 1)),
+        })
+        .collect()
+}
+
+pub fn read_script(make_py_file: &str) -> Script {
+    let mut script = Script {
+        features: Vec::new(),
+        requires: Vec::new(),
+    };
+
+    let f = match File::open(make_py_file) {
+        Ok(f) => f,
+        Err(_) => return script,
+    };
+
+    for line in BufReader::new(f).lines().take(HEADER_LINES) {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        let directive = match line.trim().strip_prefix("# mk:") {
+            Some(directive) => directive.trim(),
+            None => continue,
+        };
+
+        if let Some(list) = directive.strip_prefix("features=") {
+            script.features.extend(parse_list(list));
+        } else if let Some(list) = directive.strip_prefix("requires=") {
+            script.requires.extend(parse_list(list));
+        }
+    }
+
+    script
+}
+
+fn mk_version(name: &str) -> Option<u32> {
+    FEATURES.iter().find(|(n, _)| *n == name).map(|(_, v)| *v)
+}
+
+// The version of protocol 'name' both sides speak, None if one of them doesn't.
+pub fn negotiate(script: &Script, name: &str) -> Option<u32> {
+    let ours = mk_version(name)?;
+    let theirs = script
+        .features
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| *v)?;
+    Some(ours.min(theirs))
+}
+
+// Requirements of make.py this mk can't meet, as messages.
+pub fn unmet_requirements(script: &Script) -> Vec<String> {
+    script
+        .requires
+        .iter()
+        .filter_map(|(name, version)| match mk_version(name) {
+            Some(ours) if ours >= *version => None,
+            Some(ours) => Some(format!(
+                "make.py requires protocol '{}' version {}, this mk only supports version {}",
+                name, version, ours
+            )),
+            None => Some(format!(
+                "make.py requires protocol '{}', which this mk doesn't support",
+                name
+            )),
+        })
+        .collect()
+}
@@ -0,0 +1,126 @@
+// Pseudo-terminals for '--pty'. The task's stdout and stderr are connected to the PTY, so
+// tools that check for a terminal (pytest, pip, ...) keep their colors and progress bars
+// while mk reads their output. stdin stays connected to mk's stdin.
+
+use std::fs::File;
+use std::io;
+
+pub struct Pty {
+    // mk reads the task's output from here.
+    pub master: File,
+    // Handed to the task.
+    pub slave: File,
+}
+
+#[cfg(unix)]
+mod ffi {
+    use std::os::raw::{c_char, c_int, c_ulong};
+
+    #[repr(C)]
+    pub struct Winsize {
+        pub ws_row: u16,
+        pub ws_col: u16,
+        pub ws_xpixel: u16,
+        pub ws_ypixel: u16,
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub const O_NOCTTY: c_int = 0o400;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub const O_NOCTTY: c_int = 0x20000;
+    pub const O_RDWR: c_int = 2;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub const TIOCGWINSZ: c_ulong = 0x5413;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub const TIOCSWINSZ: c_ulong = 0x5414;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub const TIOCGWINSZ: c_ulong = 0x4008_7468;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub const TIOCSWINSZ: c_ulong = 0x8008_7467;
+
+    extern "C" {
+        pub fn posix_openpt(flags: c_int) -> c_int;
+        pub fn grantpt(fd: c_int) -> c_int;
+        pub fn unlockpt(fd: c_int) -> c_int;
+        pub fn ptsname(fd: c_int) -> *mut c_char;
+        pub fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+}
+
+// Columns of the terminal mk's stdout is, None if it isn't one.
+#[cfg(unix)]
+pub fn stdout_columns() -> Option<usize> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut size = ffi::Winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    if unsafe { ffi::ioctl(io::stdout().as_raw_fd(), ffi::TIOCGWINSZ, &mut size) } != 0 {
+        return None;
+    }
+    Some(size.ws_col as usize).filter(|columns| *columns > 0)
+}
+
+#[cfg(not(unix))]
+pub fn stdout_columns() -> Option<usize> {
+    None
+}
+
+#[cfg(unix)]
+pub fn open() -> io::Result<Pty> {
+    use std::ffi::CStr;
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    let fd = unsafe { ffi::posix_openpt(ffi::O_RDWR | ffi::O_NOCTTY) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let master = unsafe { File::from_raw_fd(fd) };
+
+    if unsafe { ffi::grantpt(fd) } != 0 || unsafe { ffi::unlockpt(fd) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // mk opens PTYs one at a time, before starting threads that could, so the static
+    // buffer of 'ptsname' is safe to use.
+    let name = unsafe { ffi::ptsname(fd) };
+    if name.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    let name = unsafe { CStr::from_ptr(name) }
+        .to_string_lossy()
+        .to_string();
+
+    let slave = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(ffi::O_NOCTTY)
+        .open(&name)?;
+
+    // Give the PTY the size of mk's terminal, so progress bars fit.
+    let mut size = ffi::Winsize {
+        ws_row: 0,
+        ws_col: 0,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    if unsafe { ffi::ioctl(io::stdout().as_raw_fd(), ffi::TIOCGWINSZ, &mut size) } == 0 {
+        unsafe { ffi::ioctl(slave.as_raw_fd(), ffi::TIOCSWINSZ, &size) };
+    }
+
+    Ok(Pty { master, slave })
+}
+
+#[cfg(not(unix))]
+pub fn open() -> io::Result<Pty> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "'--pty' is only supported on Unix",
+    ))
+}
@@ -0,0 +1,242 @@
+// '--record <file>' and '--replay <file>', for "can't reproduce" bug reports. A recording
+// holds everything mk resolved for a run: the project, the venv, the exact command with the
+// environment mk gave it, the Python-related variables of the calling shell, timings and
+// the outcome:
+//
+//     mk --record run.json test
+//     mk --replay run.json
+//
+// Replaying runs the recorded command in the recorded dir with the recorded environment,
+// without resolving anything, so it runs exactly what mk ran then, or fails telling why.
+
+use std::env;
+use std::fs;
+use std::process::{Command, ExitStatus};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use failure;
+use json;
+use json::Json;
+
+const FORMAT_VERSION: i64 = 1;
+
+// Variables of the calling shell that change what Python and the tools it runs do. The
+// rest of the environment is left out, it's noise and may contain secrets.
+const INHERITED_PREFIXES: &[&str] = &[
+    "PYTHON",
+    "LANG",
+    "LC_",
+    "UV_",
+    "POETRY_",
+    "PIP_",
+    "CONDA",
+    "VIRTUAL_ENV",
+    "TERM",
+    "HOME",
+];
+
+pub struct Run<'a> {
+    pub project_root: &'a str,
+    pub invocation_dir: &'a str,
+    pub args: &'a [String],
+    pub venv: &'a str,
+    pub python: &'a str,
+    pub command: &'a Command,
+    // From mk's start to the start of the task, and the task itself.
+    pub resolution: Duration,
+    pub duration: Duration,
+    pub status: &'a ExitStatus,
+}
+
+fn string_list(items: &[String]) -> String {
+    let items: Vec<String> = items.iter().map(|item| json::quote(item)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn object(members: &[(String, Option<String>)]) -> String {
+    let members: Vec<String> = members
+        .iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Some(value) => json::quote(value),
+                None => "null".to_string(),
+            };
+            format!("\n    {}: {}", json::quote(name), value)
+        })
+        .collect();
+    format!("{{{}\n  }}", members.join(","))
+}
+
+pub fn write(path: &str, run: &Run) -> Result<(), String> {
+    let recorded_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let argv: Vec<String> = run
+        .command
+        .get_args()
+        .map(|a| a.to_string_lossy().to_string())
+        .collect();
+    let env: Vec<(String, Option<String>)> = run
+        .command
+        .get_envs()
+        .map(|(name, value)| {
+            (
+                name.to_string_lossy().to_string(),
+                value.map(|v| v.to_string_lossy().to_string()),
+            )
+        })
+        .collect();
+    let mut inherited: Vec<(String, Option<String>)> = env::vars()
+        .filter(|(name, _)| INHERITED_PREFIXES.iter().any(|p| name.starts_with(p)))
+        .filter(|(name, _)| !env.iter().any(|(set, _)| set == name))
+        .map(|(name, value)| (name, Some(value)))
+        .collect();
+    inherited.sort();
+
+    let exit_code = match run.status.code() {
+        Some(code) => code.to_string(),
+        None => "null".to_string(),
+    };
+
+    let content = format!(
+        "{{\n  \"version\": {},\n  \"mk\": {},\n  \"recorded_at\": {},\n  \"project_root\": {},\n  \"invocation_dir\": {},\n  \"args\": {},\n  \"venv\": {},\n  \"python\": {},\n  \"program\": {},\n  \"argv\": {},\n  \"env\": {},\n  \"inherited\": {},\n  \"resolution_ms\": {},\n  \"duration_ms\": {},\n  \"success\": {},\n  \"exit_code\": {}\n}}\n",
+        FORMAT_VERSION,
+        json::quote(crate::VERSION),
+        recorded_at,
+        json::quote(run.project_root),
+        json::quote(run.invocation_dir),
+        string_list(run.args),
+        json::quote(run.venv),
+        json::quote(run.python),
+        json::quote(&run.command.get_program().to_string_lossy()),
+        string_list(&argv),
+        object(&env),
+        object(&inherited),
+        run.resolution.as_millis(),
+        run.duration.as_millis(),
+        run.status.success(),
+        exit_code,
+    );
+
+    fs::write(path, content).map_err(|e| format!("cannot write {}: {}", path, e))
+}
+
+fn field<'a>(doc: &'a Json, name: &str) -> Result<&'a str, String> {
+    doc.get(name)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| format!("'{}' is missing", name))
+}
+
+fn strings(doc: &Json, name: &str) -> Result<Vec<String>, String> {
+    doc.get(name)
+        .and_then(|v| v.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.as_str())
+                .map(|s| s.to_string())
+                .collect()
+        })
+        .ok_or_else(|| format!("'{}' is missing", name))
+}
+
+// Apply the variables of object 'name' to 'cmd', null removes one.
+fn apply_env(cmd: &mut Command, doc: &Json, name: &str) -> Result<(), String> {
+    let members = doc
+        .get(name)
+        .and_then(|v| v.as_object())
+        .ok_or_else(|| format!("'{}' is missing", name))?;
+    for (var, value) in members {
+        match value {
+            Json::String(value) => cmd.env(var, value),
+            _ => cmd.env_remove(var),
+        };
+    }
+    Ok(())
+}
+
+fn command(doc: &Json) -> Result<Command, String> {
+    match doc.get("version").and_then(|v| v.as_i64()) {
+        Some(FORMAT_VERSION) => {}
+        Some(version) => {
+            return Err(format!(
+                "it has version {}, this mk reads version {}",
+                version, FORMAT_VERSION
+            ))
+        }
+        None => return Err("it's not an mk recording".to_string()),
+    }
+
+    let mut cmd = Command::new(field(doc, "program")?);
+    cmd.args(strings(doc, "argv")?)
+        .current_dir(field(doc, "project_root")?);
+    apply_env(&mut cmd, doc, "inherited")?;
+    apply_env(&mut cmd, doc, "env")?;
+
+    // The run's temp dir is long gone.
+    if let Some(tmp) = doc.get("env").and_then(|env| env.get("MK_TMP")) {
+        if let Some(tmp) = tmp.as_str() {
+            fs::create_dir_all(tmp).map_err(|e| format!("cannot create {}: {}", tmp, e))?;
+        }
+    }
+    Ok(cmd)
+}
+
+// '--replay <file>'. Returns the exit code.
+pub fn replay(path: &str) -> i32 {
+    let doc = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| json::parse(&text))
+    {
+        Ok(doc) => doc,
+        Err(e) => {
+            eprintln!("mk: Cannot read recording {}: {}", path, e);
+            return 1;
+        }
+    };
+
+    let mut cmd = match command(&doc) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            eprintln!("mk: Cannot replay {}: {}.", path, e);
+            return 1;
+        }
+    };
+
+    eprintln!(
+        "mk: Replaying 'mk {}' of mk {} in {}.",
+        strings(&doc, "args").unwrap_or_default().join(" "),
+        field(&doc, "mk").unwrap_or("?"),
+        field(&doc, "project_root").unwrap_or("?"),
+    );
+    if let Ok(python) = field(&doc, "python") {
+        if !std::path::Path::new(python).exists() {
+            eprintln!("mk: The recorded Python {} doesn't exist here.", python);
+        }
+    }
+
+    let status = match cmd.status() {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("mk: Failed to run the recorded command: {}", e);
+            return 1;
+        }
+    };
+
+    let recorded = match doc.get("success") {
+        Some(Json::Bool(true)) => Some(0),
+        _ => doc.get("exit_code").and_then(|v| v.as_i64()),
+    };
+    let code = failure::status_code(&status);
+    match recorded {
+        Some(recorded) if recorded != i64::from(code) => eprintln!(
+            "mk: Exit code {}, the recording exited with {}.",
+            code, recorded
+        ),
+        Some(_) => eprintln!("mk: Exit code {}, as recorded.", code),
+        None => {}
+    }
+    code
+}
@@ -0,0 +1,145 @@
+// The project's 'requires-python' (PEP 621, in pyproject.toml) and whether a venv's
+// interpreter satisfies it. The cache records a hash of the field with every entry, so
+// the check only runs when the field changed since the entry was written.
+
+use std::fs;
+
+use shared_cache;
+use toml::{self, Value};
+
+pub fn read() -> Option<String> {
+    let text = fs::read_to_string("pyproject.toml").ok()?;
+
+    // This runs on every 'mk <task>', and pyproject.toml files can be long, so look for the
+    // usual 'requires-python = "..."' line in '[project]' first and only parse the whole
+    // file for unusual layouts.
+    if !text.contains("requires-python") {
+        return None;
+    }
+    let mut table = "";
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            table = line;
+        } else if table == "[project]" {
+            if let Some(value) = line.strip_prefix("requires-python") {
+                if let Some(value) = value.trim_start().strip_prefix('=') {
+                    if let Ok(Value::String(spec)) = toml::parse_value(value.trim()) {
+                        return Some(spec);
+                    }
+                }
+            }
+        }
+    }
+
+    let doc = toml::parse(&text).ok()?;
+
+    doc.flatten()
+        .into_iter()
+        .find(|(name, _)| name == "project.requires-python")
+        .and_then(|(_, value)| match value {
+            Value::String(spec) => Some(spec),
+            _ => None,
+        })
+}
+
+// What the cache stores, '-' if the project has no requires-python.
+pub fn stamp(spec: Option<&str>) -> String {
+    match spec {
+        Some(spec) => shared_cache::hash_key(spec.trim()),
+        None => "-".to_string(),
+    }
+}
+
+// The Python version of a venv, from its 'pyvenv.cfg'.
+pub fn venv_version(venv_path: &str) -> Option<Vec<u64>> {
+    let cfg = fs::read_to_string(format!("{}/pyvenv.cfg", venv_path)).ok()?;
+
+    for name in &["version_info", "version"] {
+        for line in cfg.lines() {
+            if let Some((key, value)) = line.split_once('=') {
+                if key.trim() == *name {
+                    return parse_version(value.trim());
+                }
+            }
+        }
+    }
+
+    None
+}
+
+fn parse_version(text: &str) -> Option<Vec<u64>> {
+    let parts: Vec<u64> = text
+        .split('.')
+        .map_while(|part| part.parse().ok())
+        .collect();
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts)
+    }
+}
+
+fn compare(a: &[u64], b: &[u64]) -> std::cmp::Ordering {
+    let len = a.len().max(b.len());
+    let get = |v: &[u64], i: usize| v.get(i).cloned().unwrap_or(0);
+    (0..len)
+        .map(|i| get(a, i).cmp(&get(b, i)))
+        .find(|o| o.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
+
+// Whether 'version' satisfies the PEP 440 specifier 'spec', e.g. '>=3.9,<4'. None if the
+// specifier can't be understood, e.g. because it uses pre-releases.
+pub fn satisfies(spec: &str, version: &[u64]) -> Option<bool> {
+    use std::cmp::Ordering::*;
+
+    for clause in spec.split(',').map(|c| c.trim()).filter(|c| !c.is_empty()) {
+        let op_len = clause
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(clause.len());
+        let op = clause[..op_len].trim();
+        let target = clause[op_len..].trim();
+
+        let (target, wildcard) = match target.strip_suffix(".*") {
+            Some(target) => (target, true),
+            None => (target, false),
+        };
+        let target = parse_version(target)?;
+
+        let prefix_match = version.len() >= target.len() && version[..target.len()] == target[..];
+        let ordering = compare(version, &target);
+
+        let ok = match op {
+            "==" | "===" if wildcard => prefix_match,
+            "!=" if wildcard => !prefix_match,
+            "==" | "===" => ordering == Equal,
+            "!=" => ordering != Equal,
+            ">=" => ordering != Less,
+            "<=" => ordering != Greater,
+            ">" => ordering == Greater,
+            "<" => ordering == Less,
+            // '~=3.9.1' means '>=3.9.1, ==3.9.*'.
+            "~=" if target.len() >= 2 => {
+                let head = &target[..target.len() - 1];
+                ordering != Less && version.len() >= head.len() && version[..head.len()] == head[..]
+            }
+            _ => return None,
+        };
+
+        if !ok {
+            return Some(false);
+        }
+    }
+
+    Some(true)
+}
+
+pub fn format_version(version: &[u64]) -> String {
+    version
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
@@ -0,0 +1,543 @@
+// Finding a project's venv by asking the tools that manage it. Resolvers are tried in the
+// order of the 'resolvers' setting until one returns a venv:
+//
+//     resolvers = ["pdm", "uv", "poetry"]
+//
+// By default pdm and pipenv come first in projects with their lockfile, followed by uv and
+// poetry. hatch and conda are only asked when listed, conda needs 'conda_env' as well, the
+// name or path of the environment. Resolvers whose tool isn't installed are skipped.
+//
+// Builds that embed mk can add their own resolvers by implementing VenvResolver and adding
+// them to chain(). Builds with '--cfg mk_no_conda' leave the conda resolver out.
+
+use std::env;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use config::Config;
+use json;
+use sync;
+
+pub trait VenvResolver {
+    // The tool, as in the 'resolvers' setting and on PATH.
+    fn name(&self) -> &'static str;
+
+    // The venv the tool uses for the project in the current dir, or why there is none.
+    fn resolve(&self, config: &Config) -> Result<String, String>;
+}
+
+enum Discovery {
+    Done(Output),
+    TimedOut(u64),
+    Failed(io::Error),
+}
+
+// Errors of uv and poetry that go away by themselves: network hiccups, package indexes
+// under load, a keyring that isn't unlocked yet.
+const TRANSIENT_ERRORS: &[&str] = &[
+    "timed out",
+    "timeout",
+    "connection reset",
+    "connection refused",
+    "connection aborted",
+    "network is unreachable",
+    "temporary failure in name resolution",
+    "could not resolve host",
+    "failed to resolve",
+    "failed to fetch",
+    "too many requests",
+    "service unavailable",
+    "bad gateway",
+    "keyring",
+    "secretservice",
+    "dbus",
+];
+
+// Whether a failed command's stderr looks like a transient problem, see TRANSIENT_ERRORS.
+fn is_transient(stderr: &[u8]) -> bool {
+    let stderr = String::from_utf8_lossy(stderr).to_lowercase();
+    TRANSIENT_ERRORS.iter().any(|e| stderr.contains(e))
+}
+
+// The last non-empty line of a command's stderr, usually the actual error.
+fn last_error_line(stderr: &[u8]) -> String {
+    String::from_utf8_lossy(stderr)
+        .lines()
+        .rev()
+        .map(|line| line.trim())
+        .find(|line| !line.is_empty())
+        .unwrap_or("")
+        .to_string()
+}
+
+// Run a backend discovery command like 'poetry env info --path'. These can hang for a long
+// time (e.g. on network-mounted homes), so they are killed after 'discovery_timeout' seconds.
+// Timeouts and transient failures are retried up to 'discovery_retries' times, waiting
+// 'discovery_backoff' seconds before the first retry and twice as long before each next one.
+fn run_discovery(config: &Config, cmd: &mut Command) -> Discovery {
+    let timeout = config.get_u64("discovery_timeout", 20);
+    let retries = config.get_u64("discovery_retries", 0);
+    let mut backoff = Duration::from_secs(config.get_u64("discovery_backoff", 1));
+
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    for attempt in 0..=retries {
+        if attempt > 0 {
+            crate::explain(&format!(
+                "retrying in {}s ({}/{})",
+                backoff.as_secs(),
+                attempt,
+                retries
+            ));
+            thread::sleep(backoff);
+            backoff *= 2;
+        }
+
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) => return Discovery::Failed(e),
+        };
+
+        // Drain the pipes while waiting, so a chatty command can't block on a full pipe.
+        let mut stdout = child.stdout.take().unwrap();
+        let mut stderr = child.stderr.take().unwrap();
+        let stdout_reader = thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = stdout.read_to_end(&mut buf);
+            buf
+        });
+        let stderr_reader = thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = stderr.read_to_end(&mut buf);
+            buf
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(timeout);
+
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break Some(status),
+                Ok(None) if Instant::now() >= deadline => break None,
+                Ok(None) => thread::sleep(Duration::from_millis(10)),
+                Err(e) => return Discovery::Failed(e),
+            }
+        };
+
+        match status {
+            Some(status) => {
+                let output = Output {
+                    status,
+                    stdout: stdout_reader.join().unwrap_or_default(),
+                    stderr: stderr_reader.join().unwrap_or_default(),
+                };
+                if !status.success() && is_transient(&output.stderr) && attempt < retries {
+                    crate::explain(&format!(
+                        "failed with a temporary error: {}",
+                        last_error_line(&output.stderr)
+                    ));
+                    continue;
+                }
+                return Discovery::Done(output);
+            }
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                crate::explain(&format!("timed out after {}s", timeout));
+            }
+        }
+    }
+
+    Discovery::TimedOut(timeout)
+}
+
+// Whether 'program' can be found on PATH, without running it.
+pub fn on_path(program: &str) -> bool {
+    let names = if cfg!(windows) {
+        vec![format!("{}.exe", program), format!("{}.cmd", program)]
+    } else {
+        vec![program.to_string()]
+    };
+
+    env::var_os("PATH").is_some_and(|path| {
+        env::split_paths(&path).any(|dir| names.iter().any(|name| dir.join(name).is_file()))
+    })
+}
+
+// A venv found by looking around, for when none of the resolvers is installed, as in
+// slim container images: the active venv, or '.venv' or 'venv' in the project.
+pub fn probe_venv() -> Option<String> {
+    let mut candidates: Vec<String> = env::var("VIRTUAL_ENV").into_iter().collect();
+    candidates.push(".venv".to_string());
+    candidates.push("venv".to_string());
+
+    let cur_dir = env::current_dir().ok()?;
+    for candidate in candidates {
+        let path = cur_dir.join(&candidate);
+        if Path::new(&crate::venv_python(&path.display().to_string())).exists() {
+            crate::explain(&format!("found venv {}", path.display()));
+            return Some(path.display().to_string());
+        }
+    }
+
+    None
+}
+
+// The venv uv and poetry would pick anyway, if it can be told without running them, which
+// takes hundreds of milliseconds: 'UV_PROJECT_ENVIRONMENT' if set, else an active venv
+// inside the project, else the project's '.venv'. An active venv outside the project
+// belongs to something else and isn't used, as uv wouldn't either. Returns the venv and
+// how it was found.
+pub fn fast_path_venv() -> Option<(String, &'static str)> {
+    let cur_dir = env::current_dir().ok()?;
+    let mut candidates = Vec::new();
+
+    match env::var_os("UV_PROJECT_ENVIRONMENT").filter(|dir| !dir.is_empty()) {
+        Some(dir) => candidates.push((cur_dir.join(dir), "UV_PROJECT_ENVIRONMENT")),
+        None => {
+            if let Some(dir) = env::var_os("VIRTUAL_ENV").filter(|dir| !dir.is_empty()) {
+                let dir = PathBuf::from(dir);
+                if dir.starts_with(&cur_dir) {
+                    candidates.push((dir, "VIRTUAL_ENV"));
+                } else {
+                    crate::explain(&format!(
+                        "not using VIRTUAL_ENV {}, it's outside the project",
+                        dir.display()
+                    ));
+                }
+            }
+            candidates.push((cur_dir.join(".venv"), "the project's .venv"));
+        }
+    }
+
+    for (dir, via) in candidates {
+        let path = dir.display().to_string();
+        if Path::new(&crate::venv_python(&path)).exists() {
+            crate::explain(&format!("found venv {} via {}", path, via));
+            return Some((path, via));
+        }
+        crate::explain(&format!("no venv at {} ({})", path, via));
+    }
+
+    None
+}
+
+// The text of a discovery command's output, without a byte order mark. Tools print UTF-16
+// on some Windows setups.
+fn decode(bytes: &[u8]) -> Vec<u8> {
+    if let Some(utf16) = bytes.strip_prefix(&[0xff, 0xfe]) {
+        let units = utf16
+            .chunks(2)
+            .map(|pair| u16::from_le_bytes([pair[0], *pair.get(1).unwrap_or(&0)]));
+        return char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect::<String>()
+            .into_bytes();
+    }
+    bytes
+        .strip_prefix(&[0xef, 0xbb, 0xbf])
+        .unwrap_or(bytes)
+        .to_vec()
+}
+
+// Whether 'line', which isn't UTF-8, names an existing path. mk can't use such a path.
+#[cfg(unix)]
+fn exists_not_utf8(line: &[u8]) -> bool {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    Path::new(OsStr::from_bytes(line)).exists()
+}
+
+#[cfg(not(unix))]
+fn exists_not_utf8(_line: &[u8]) -> bool {
+    false
+}
+
+// The path from a discovery command's output: the last line that is an existing 'what'
+// according to 'is_path'. Plugins print warnings and other noise to stdout as well, so the
+// other lines are ignored, and output without such a line is an error instead of a path
+// to cache. The raw output is shown with '--verbose'.
+fn discover(
+    config: &Config,
+    cmd: &mut Command,
+    what: &str,
+    is_path: fn(&Path) -> bool,
+) -> Result<String, String> {
+    let description = sync::describe(cmd);
+
+    let result = match run_discovery(config, cmd) {
+        Discovery::Done(out) => out,
+        Discovery::TimedOut(timeout) => {
+            return Err(format!(
+                "'{}' did not respond within {}s, raise 'discovery_timeout' if your setup is just slow",
+                description, timeout
+            ));
+        }
+        Discovery::Failed(e) => return Err(format!("could not run '{}': {}", description, e)),
+    };
+
+    if !result.status.success() {
+        if is_transient(&result.stderr) {
+            return Err(format!(
+                "'{}' failed with what looks like a temporary problem (network, keyring), try again or raise 'discovery_retries': {}",
+                description,
+                last_error_line(&result.stderr)
+            ));
+        }
+        return Err(format!(
+            "'{}' returned {}, this usually means there is no venv",
+            description, result.status
+        ));
+    }
+
+    let stdout = decode(&result.stdout);
+    let lines: Vec<&[u8]> = stdout
+        .split(|b| *b == b'\n')
+        .map(|line| line.trim_ascii())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    for (i, line) in lines.iter().enumerate().rev() {
+        match std::str::from_utf8(line) {
+            Ok(path) if is_path(Path::new(path)) => {
+                if lines.len() > 1 {
+                    crate::explain(&format!(
+                        "'{}' printed {} lines, using line {}",
+                        description,
+                        lines.len(),
+                        i + 1
+                    ));
+                }
+                return Ok(path.to_string());
+            }
+            Ok(_) => {}
+            Err(_) if exists_not_utf8(line) => {
+                return Err(format!(
+                    "'{}' printed a {} path that isn't valid UTF-8, which mk can't use: {}",
+                    description,
+                    what,
+                    String::from_utf8_lossy(line)
+                ));
+            }
+            Err(_) => {}
+        }
+    }
+
+    crate::explain(&format!("output of '{}':", description));
+    for line in String::from_utf8_lossy(&result.stdout)
+        .split('\n')
+        .filter(|line| !line.is_empty())
+    {
+        crate::explain(&format!("  {}", line.escape_debug()));
+    }
+    match lines.last() {
+        Some(line) => Err(format!(
+            "'{}' printed no existing {} path, the last line was '{}' (see --verbose for all of it)",
+            description,
+            what,
+            String::from_utf8_lossy(line)
+        )),
+        None => Err(format!("'{}' printed no {} path", description, what)),
+    }
+}
+
+pub struct Uv {
+    pub lock_flags: sync::LockFlags,
+}
+
+impl VenvResolver for Uv {
+    fn name(&self) -> &'static str {
+        "uv"
+    }
+
+    fn resolve(&self, config: &Config) -> Result<String, String> {
+        discover(
+            config,
+            Command::new("uv")
+                .arg("run")
+                .args(self.lock_flags.uv_run_args())
+                .arg("python")
+                .arg("-c")
+                .arg("import os; print(os.environ['VIRTUAL_ENV'])"),
+            "venv",
+            Path::is_dir,
+        )
+    }
+}
+
+pub struct Poetry;
+
+impl VenvResolver for Poetry {
+    fn name(&self) -> &'static str {
+        "poetry"
+    }
+
+    fn resolve(&self, config: &Config) -> Result<String, String> {
+        discover(
+            config,
+            Command::new("poetry").arg("env").arg("info").arg("--path"),
+            "venv",
+            Path::is_dir,
+        )
+    }
+}
+
+pub struct Pipenv;
+
+impl VenvResolver for Pipenv {
+    fn name(&self) -> &'static str {
+        "pipenv"
+    }
+
+    fn resolve(&self, config: &Config) -> Result<String, String> {
+        discover(
+            config,
+            Command::new("pipenv").arg("--venv"),
+            "venv",
+            Path::is_dir,
+        )
+    }
+}
+
+pub struct Pdm;
+
+impl VenvResolver for Pdm {
+    fn name(&self) -> &'static str {
+        "pdm"
+    }
+
+    // pdm prints the interpreter, the venv is two levels up: '<venv>/bin/python'.
+    fn resolve(&self, config: &Config) -> Result<String, String> {
+        let python = discover(
+            config,
+            Command::new("pdm").arg("info").arg("--python"),
+            "interpreter",
+            Path::is_file,
+        )?;
+        Path::new(&python)
+            .parent()
+            .and_then(|bin| bin.parent())
+            .map(|venv| venv.display().to_string())
+            .ok_or_else(|| {
+                format!(
+                    "'pdm info --python' printed no venv interpreter: {}",
+                    python
+                )
+            })
+    }
+}
+
+pub struct Hatch;
+
+impl VenvResolver for Hatch {
+    fn name(&self) -> &'static str {
+        "hatch"
+    }
+
+    fn resolve(&self, config: &Config) -> Result<String, String> {
+        discover(
+            config,
+            Command::new("hatch").arg("env").arg("find"),
+            "venv",
+            Path::is_dir,
+        )
+    }
+}
+
+pub struct Conda;
+
+impl VenvResolver for Conda {
+    fn name(&self) -> &'static str {
+        "conda"
+    }
+
+    // Environments are listed by path; 'conda_env' is matched against their dir name, or
+    // the whole path. 'base' is the first one.
+    fn resolve(&self, config: &Config) -> Result<String, String> {
+        let wanted = match config.get("conda_env") {
+            Some(name) if !name.is_empty() => name,
+            _ => return Err("set 'conda_env' to the environment to use".to_string()),
+        };
+
+        let mut cmd = Command::new("conda");
+        cmd.arg("info").arg("--json");
+        let description = sync::describe(&cmd);
+        let output = match run_discovery(config, &mut cmd) {
+            Discovery::Done(out) if out.status.success() => out,
+            Discovery::Done(out) => {
+                return Err(format!("'{}' returned {}", description, out.status))
+            }
+            Discovery::TimedOut(timeout) => {
+                return Err(format!(
+                    "'{}' did not respond within {}s",
+                    description, timeout
+                ))
+            }
+            Discovery::Failed(e) => return Err(format!("could not run '{}': {}", description, e)),
+        };
+
+        let envs = json::string_array(&String::from_utf8_lossy(&output.stdout), "envs")
+            .ok_or_else(|| format!("'{}' printed no environments", description))?;
+
+        let found = if wanted == "base" {
+            envs.first().cloned()
+        } else {
+            envs.iter()
+                .find(|env| {
+                    env.as_str() == wanted
+                        || Path::new(env).file_name().map(|n| n.to_string_lossy())
+                            == Some(wanted.into())
+                })
+                .cloned()
+        };
+
+        found.ok_or_else(|| format!("there is no conda environment '{}'", wanted))
+    }
+}
+
+// The resolvers to try, in order, see the top of this file.
+pub fn chain(config: &Config, lock_flags: &sync::LockFlags) -> Vec<Box<dyn VenvResolver>> {
+    let names = config.get_list("resolvers").unwrap_or_else(|| {
+        let mut names = Vec::new();
+        if Path::new("pdm.lock").exists() {
+            names.push("pdm".to_string());
+        }
+        if Path::new("Pipfile.lock").exists() || Path::new("Pipfile").exists() {
+            names.push("pipenv".to_string());
+        }
+        names.push("uv".to_string());
+        names.push("poetry".to_string());
+        names
+    });
+
+    let mut chain: Vec<Box<dyn VenvResolver>> = Vec::new();
+    for name in names {
+        chain.push(match name.as_str() {
+            "uv" => Box::new(Uv {
+                lock_flags: *lock_flags,
+            }),
+            "poetry" => Box::new(Poetry),
+            "pipenv" => Box::new(Pipenv),
+            "pdm" => Box::new(Pdm),
+            "hatch" => Box::new(Hatch),
+            "conda" if cfg!(not(mk_no_conda)) => Box::new(Conda),
+            "conda" => {
+                crate::warn("This mk was built without the conda resolver, skipping it.");
+                continue;
+            }
+            other => {
+                crate::warn(&format!(
+                    "Unknown resolver '{}' in 'resolvers', expected uv, poetry, pipenv, pdm, hatch or conda.",
+                    other
+                ));
+                continue;
+            }
+        });
+    }
+    chain
+}
@@ -0,0 +1,241 @@
+// 'mk config schema': a JSON Schema of mk.toml and the '[tool.mk]' table of pyproject.toml,
+// for editors to validate and complete the config with.
+//
+// The settings and their types come from SETTINGS in config.rs, their descriptions from
+// the comments there, compiled in, so the schema has every setting there is.
+
+use config;
+use config::Kind;
+use json;
+
+const CONFIG_SOURCE: &str = include_str!("config.rs");
+
+// The settings of SETTINGS with the comment above them. Settings listed together share
+// their comment.
+fn settings() -> Vec<(String, String)> {
+    let start = CONFIG_SOURCE
+        .find("pub const SETTINGS")
+        .expect("mk: config.rs has SETTINGS.");
+    let mut settings = Vec::new();
+    let mut comment: Vec<&str> = Vec::new();
+    let mut in_group = false;
+
+    for line in CONFIG_SOURCE[start..].lines().skip(1) {
+        let line = line.trim();
+        if line == "];" {
+            break;
+        }
+        if let Some(text) = line.strip_prefix("//") {
+            if in_group {
+                comment.clear();
+                in_group = false;
+            }
+            comment.push(text.trim());
+        } else if let Some(name) = setting_name(line) {
+            in_group = true;
+            settings.push((name.to_string(), without_source_refs(&comment.join(" "))));
+        }
+    }
+
+    settings
+}
+
+// The name of the setting on this line of SETTINGS, '("name", Kind::...)', or '"name",' on
+// a line of its own where the entry is wrapped.
+fn setting_name(line: &str) -> Option<&str> {
+    let rest = line
+        .strip_prefix("(\"")
+        .or_else(|| line.strip_prefix('"'))?;
+    rest.split('"').next()
+}
+
+// Remove references to mk's source, like ', see resolvers.rs', which mean nothing in an
+// editor.
+fn without_source_refs(text: &str) -> String {
+    let mut text = text.to_string();
+    while let Some(start) = text.find(", see ") {
+        match text[start..].find(".rs") {
+            Some(end) => text.replace_range(start..start + end + 3, ""),
+            None => break,
+        }
+    }
+    text
+}
+
+fn type_of(name: &str) -> String {
+    match config::SETTINGS
+        .iter()
+        .find(|(setting, _)| *setting == name)
+        .map(|(_, kind)| kind)
+    {
+        Some(Kind::Bool) => "\"type\": \"boolean\"".to_string(),
+        Some(Kind::Integer) => "\"type\": \"integer\", \"minimum\": 0".to_string(),
+        Some(Kind::List) => {
+            "\"type\": [\"array\", \"string\"], \"items\": { \"type\": \"string\" }".to_string()
+        }
+        Some(Kind::Choice(choices)) => {
+            let choices: Vec<String> = choices.iter().map(|c| json::quote(c)).collect();
+            format!("\"enum\": [{}]", choices.join(", "))
+        }
+        Some(Kind::IntegerOr(word)) => format!(
+            "\"oneOf\": [{{ \"type\": \"integer\", \"minimum\": 1 }}, {{ \"const\": {} }}]",
+            json::quote(word)
+        ),
+        Some(Kind::String) | None => "\"type\": \"string\"".to_string(),
+    }
+}
+
+// The schema, as pretty-printed JSON.
+pub fn generate() -> String {
+    let settings = settings();
+
+    let mut properties: Vec<String> = settings
+        .iter()
+        .map(|(name, description)| {
+            format!(
+                "    {}: {{ \"description\": {}, {} }}",
+                json::quote(name),
+                json::quote(description),
+                type_of(name)
+            )
+        })
+        .collect();
+
+    properties.push(
+        "    \"extends\": {
+      \"description\": \"Base configs this one builds on: 'github:<org>/<repo>[#<name>]' or paths relative to this file.\",
+      \"type\": [\"string\", \"array\"],
+      \"items\": { \"type\": \"string\" }
+    }"
+        .to_string(),
+    );
+    properties.push(
+        "    \"env\": {
+      \"description\": \"Environment variables for tasks.\",
+      \"type\": \"object\",
+      \"additionalProperties\": { \"type\": [\"string\", \"number\", \"boolean\"] }
+    }"
+        .to_string(),
+    );
+    properties.push(
+        "    \"alias\": {
+      \"description\": \"Short names for tasks and their arguments, e.g. t = \\\"test --fast\\\".\",
+      \"type\": \"object\",
+      \"additionalProperties\": {
+        \"type\": [\"string\", \"array\"],
+        \"items\": { \"type\": \"string\" }
+      }
+    }"
+        .to_string(),
+    );
+    properties.push(
+        "    \"facade\": {
+      \"description\": \"Tasks run by another build system, e.g. Bazel or Pants, instead of make.py.\",
+      \"type\": \"object\",
+      \"additionalProperties\": false,
+      \"properties\": {
+        \"tool\": {
+          \"description\": \"The command of the build system, e.g. 'bazel' or './pants'.\",
+          \"type\": [\"string\", \"array\"],
+          \"items\": { \"type\": \"string\" }
+        },
+        \"task\": {
+          \"description\": \"The arguments of the tool per task, e.g. test = \\\"test //...\\\".\",
+          \"type\": \"object\",
+          \"additionalProperties\": {
+            \"type\": [\"string\", \"array\"],
+            \"items\": { \"type\": \"string\" }
+          }
+        }
+      }
+    }"
+        .to_string(),
+    );
+    properties.push(
+        "    \"task\": {
+      \"description\": \"Tasks defined in the config, run like those of make.py, and settings of make.py tasks.\",
+      \"type\": \"object\",
+      \"additionalProperties\": {
+        \"type\": \"object\",
+        \"additionalProperties\": false,
+        \"properties\": {
+          \"steps\": {
+            \"description\": \"Commands run one after another, stopping at the first that fails. A step can be a table with a 'when' condition.\",
+            \"type\": \"array\",
+            \"items\": {
+              \"oneOf\": [
+                { \"type\": \"string\" },
+                {
+                  \"type\": \"object\",
+                  \"required\": [\"run\"],
+                  \"additionalProperties\": false,
+                  \"properties\": {
+                    \"run\": { \"type\": \"string\" },
+                    \"when\": { \"type\": \"string\" }
+                  }
+                }
+              ]
+            }
+          },
+          \"matrix\": {
+            \"description\": \"Values the steps are run with, once for every combination, e.g. { py = [\\\"3.10\\\", \\\"3.12\\\"] }.\",
+            \"type\": \"object\",
+            \"additionalProperties\": { \"type\": [\"array\", \"string\", \"number\", \"boolean\"] }
+          },
+          \"parallel\": {
+            \"description\": \"Whether the matrix combinations, or else the steps, run in parallel (default: false).\",
+            \"type\": \"boolean\"
+          },
+          \"complete\": {
+            \"description\": \"Words the shell completes after the task.\",
+            \"type\": [\"array\", \"string\"],
+            \"items\": { \"type\": \"string\" }
+          },
+          \"open_files\": {
+            \"description\": \"The task's soft limit of open files, or 'max' for the hard limit.\",
+            \"oneOf\": [{ \"type\": \"integer\", \"minimum\": 1 }, { \"const\": \"max\" }]
+          },
+          \"core_dumps\": {
+            \"description\": \"Whether the task may dump core.\",
+            \"type\": \"boolean\"
+          }
+        }
+      }
+    }"
+        .to_string(),
+    );
+
+    format!(
+        "{{
+  \"$schema\": \"http://json-schema.org/draft-07/schema#\",
+  \"title\": \"mk config\",
+  \"description\": \"Settings of mk {}, in mk.toml or the [tool.mk] table of pyproject.toml.\",
+  \"type\": \"object\",
+  \"additionalProperties\": false,
+  \"properties\": {{
+{}
+  }}
+}}
+",
+        crate::VERSION,
+        properties.join(",\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The descriptions are scraped from config.rs, which breaks silently if its layout
+    // changes.
+    #[test]
+    fn every_setting_has_a_description() {
+        let settings = settings();
+        let names: Vec<&str> = settings.iter().map(|(name, _)| name.as_str()).collect();
+        let expected: Vec<&str> = config::SETTINGS.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, expected);
+        for (name, description) in &settings {
+            assert!(!description.is_empty(), "'{}' has no description", name);
+        }
+    }
+}
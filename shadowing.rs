@@ -0,0 +1,344 @@
+// Local modules that shadow what make.py imports. Python puts the dir of make.py first on
+// sys.path, so a 'json.py' or 'typing.py' next to it, or a 'tasks.py' named like an
+// installed package, is imported instead of the real module, and make.py fails with a
+// traceback that doesn't point at the cause. mk warns about them before running make.py.
+//
+// This runs on every task, so it reads make.py's imports with a simple line scan and only
+// lists the dirs on sys.path mk knows about: that of make.py and those of PYTHONPATH.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// sys.stdlib_module_names, without the private ones.
+const STDLIB: &[&str] = &[
+    "abc",
+    "aifc",
+    "antigravity",
+    "argparse",
+    "array",
+    "ast",
+    "asynchat",
+    "asyncio",
+    "asyncore",
+    "atexit",
+    "audioop",
+    "base64",
+    "bdb",
+    "binascii",
+    "bisect",
+    "builtins",
+    "bz2",
+    "cProfile",
+    "calendar",
+    "cgi",
+    "cgitb",
+    "chunk",
+    "cmath",
+    "cmd",
+    "code",
+    "codecs",
+    "codeop",
+    "collections",
+    "colorsys",
+    "compileall",
+    "concurrent",
+    "configparser",
+    "contextlib",
+    "contextvars",
+    "copy",
+    "copyreg",
+    "crypt",
+    "csv",
+    "ctypes",
+    "curses",
+    "dataclasses",
+    "datetime",
+    "dbm",
+    "decimal",
+    "difflib",
+    "dis",
+    "distutils",
+    "doctest",
+    "email",
+    "encodings",
+    "ensurepip",
+    "enum",
+    "errno",
+    "faulthandler",
+    "fcntl",
+    "filecmp",
+    "fileinput",
+    "fnmatch",
+    "fractions",
+    "ftplib",
+    "functools",
+    "gc",
+    "genericpath",
+    "getopt",
+    "getpass",
+    "gettext",
+    "glob",
+    "graphlib",
+    "grp",
+    "gzip",
+    "hashlib",
+    "heapq",
+    "hmac",
+    "html",
+    "http",
+    "idlelib",
+    "imaplib",
+    "imghdr",
+    "imp",
+    "importlib",
+    "inspect",
+    "io",
+    "ipaddress",
+    "itertools",
+    "json",
+    "keyword",
+    "lib2to3",
+    "linecache",
+    "locale",
+    "logging",
+    "lzma",
+    "mailbox",
+    "mailcap",
+    "marshal",
+    "math",
+    "mimetypes",
+    "mmap",
+    "modulefinder",
+    "msilib",
+    "msvcrt",
+    "multiprocessing",
+    "netrc",
+    "nis",
+    "nntplib",
+    "nt",
+    "ntpath",
+    "nturl2path",
+    "numbers",
+    "opcode",
+    "operator",
+    "optparse",
+    "os",
+    "ossaudiodev",
+    "pathlib",
+    "pdb",
+    "pickle",
+    "pickletools",
+    "pipes",
+    "pkgutil",
+    "platform",
+    "plistlib",
+    "poplib",
+    "posix",
+    "posixpath",
+    "pprint",
+    "profile",
+    "pstats",
+    "pty",
+    "pwd",
+    "py_compile",
+    "pyclbr",
+    "pydoc",
+    "pydoc_data",
+    "pyexpat",
+    "queue",
+    "quopri",
+    "random",
+    "re",
+    "readline",
+    "reprlib",
+    "resource",
+    "rlcompleter",
+    "runpy",
+    "sched",
+    "secrets",
+    "select",
+    "selectors",
+    "shelve",
+    "shlex",
+    "shutil",
+    "signal",
+    "site",
+    "smtpd",
+    "smtplib",
+    "sndhdr",
+    "socket",
+    "socketserver",
+    "spwd",
+    "sqlite3",
+    "sre_compile",
+    "sre_constants",
+    "sre_parse",
+    "ssl",
+    "stat",
+    "statistics",
+    "string",
+    "stringprep",
+    "struct",
+    "subprocess",
+    "sunau",
+    "symtable",
+    "sys",
+    "sysconfig",
+    "syslog",
+    "tabnanny",
+    "tarfile",
+    "telnetlib",
+    "tempfile",
+    "termios",
+    "textwrap",
+    "this",
+    "threading",
+    "time",
+    "timeit",
+    "tkinter",
+    "token",
+    "tokenize",
+    "tomllib",
+    "trace",
+    "traceback",
+    "tracemalloc",
+    "tty",
+    "turtle",
+    "turtledemo",
+    "types",
+    "typing",
+    "unicodedata",
+    "unittest",
+    "urllib",
+    "uu",
+    "uuid",
+    "venv",
+    "warnings",
+    "wave",
+    "weakref",
+    "webbrowser",
+    "winreg",
+    "winsound",
+    "wsgiref",
+    "xdrlib",
+    "xml",
+    "xmlrpc",
+    "zipapp",
+    "zipfile",
+    "zipimport",
+    "zlib",
+    "zoneinfo",
+];
+
+// The top-level modules make.py imports, in order. Relative imports are left out.
+fn imports(source: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut add = |module: &str| {
+        let name = module.split('.').next().unwrap_or("").trim();
+        if !name.is_empty() && !name.starts_with('.') && !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    };
+
+    for line in source.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("import ") {
+            for module in rest.split('#').next().unwrap_or("").split(',') {
+                add(module.split_whitespace().next().unwrap_or(""));
+            }
+        } else if let Some(rest) = line.strip_prefix("from ") {
+            if rest.contains(" import ") {
+                add(rest.split_whitespace().next().unwrap_or(""));
+            }
+        }
+    }
+
+    names
+}
+
+// The file or package dir of module 'name' in 'dir'.
+fn module_in(dir: &Path, name: &str) -> Option<PathBuf> {
+    let file = dir.join(format!("{}.py", name));
+    if file.is_file() {
+        return Some(file);
+    }
+    let package = dir.join(name);
+    if package.join("__init__.py").is_file() {
+        return Some(package);
+    }
+    None
+}
+
+// The site-packages dirs of the venv.
+fn site_packages(venv_path: &str) -> Vec<PathBuf> {
+    let windows = Path::new(venv_path).join("Lib").join("site-packages");
+    if windows.is_dir() {
+        return vec![windows];
+    }
+    fs::read_dir(Path::new(venv_path).join("lib"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.file_name().to_string_lossy().starts_with("python"))
+                .map(|e| e.path().join("site-packages"))
+                .filter(|dir| dir.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Warn about local modules that shadow a module make.py imports from the standard library,
+// or a module file that shadows a package installed in the venv.
+pub fn check(make_py_file: &str, venv_path: &str) {
+    let source = match fs::read_to_string(make_py_file) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+    let script_dir = Path::new(make_py_file)
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .to_path_buf();
+    let mut dirs = vec![script_dir];
+    if let Some(path) = env::var_os("PYTHONPATH") {
+        dirs.extend(env::split_paths(&path).filter(|dir| !dir.as_os_str().is_empty()));
+    }
+
+    let mut site = None;
+    for name in imports(&source) {
+        let local = match dirs.iter().find_map(|dir| module_in(dir, &name)) {
+            Some(local) => local,
+            None => continue,
+        };
+        let local_name = local
+            .strip_prefix(&dirs[0])
+            .unwrap_or(&local)
+            .display()
+            .to_string();
+
+        if STDLIB.contains(&name.as_str()) {
+            crate::warn(&format!(
+                "'{}' shadows the standard library module '{}' that {} imports, so Python imports the local one instead. Rename it.",
+                local_name,
+                name,
+                Path::new(make_py_file).file_name().unwrap_or_default().to_string_lossy()
+            ));
+            continue;
+        }
+
+        // A package of the project may well be installed in its venv too.
+        if local.is_dir() {
+            continue;
+        }
+        let site = site.get_or_insert_with(|| site_packages(venv_path));
+        if let Some(installed) = site.iter().find_map(|dir| module_in(dir, &name)) {
+            crate::warn(&format!(
+                "'{}' shadows the installed package '{}' ({}) that {} imports, so Python imports the local one instead. Rename it.",
+                local_name,
+                name,
+                installed.display(),
+                Path::new(make_py_file).file_name().unwrap_or_default().to_string_lossy()
+            ));
+        }
+    }
+}
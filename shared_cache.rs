@@ -0,0 +1,112 @@
+// Optional system-wide cache (e.g. '/var/cache/mk') shared by all users of a machine, so
+// ephemeral CI users on a build farm benefit from environments resolved before.
+//
+// Every entry is a file of its own, named after a hash of its key, containing
+// '<key> <venv>'. Like in the user cache, newer mk versions may append fields, which older
+// ones ignore. An entry is only trusted if neither the entry nor the venv's 'bin' dir
+// can be modified by anyone but their owner, and both have the same owner: a user can
+// only vouch for a venv they own. Hits are copied to the user cache by the caller.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// FNV-1a, so entry file names stay the same across mk versions and platforms.
+pub fn hash_key(key: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in key.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn entry_path(cache_dir: &str, key: &str) -> PathBuf {
+    Path::new(cache_dir).join(hash_key(key))
+}
+
+#[cfg(unix)]
+fn owner_if_protected(path: &Path) -> Result<u32, String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let meta = fs::metadata(path).map_err(|e| format!("cannot stat {}: {}", path.display(), e))?;
+
+    if meta.mode() & 0o022 != 0 {
+        return Err(format!("{} is writable by group or others", path.display()));
+    }
+
+    Ok(meta.uid())
+}
+
+#[cfg(unix)]
+fn check_entry(entry: &Path, bin_dir: &str) -> Result<(), String> {
+    let entry_owner = owner_if_protected(entry)?;
+    let bin_dir_owner = owner_if_protected(Path::new(bin_dir))?;
+
+    if entry_owner != bin_dir_owner {
+        return Err(format!(
+            "{} and {} are owned by different users",
+            entry.display(),
+            bin_dir
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_entry(_entry: &Path, _bin_dir: &str) -> Result<(), String> {
+    Err("the shared cache is only supported on Unix".to_string())
+}
+
+// Look up 'key'. Returns the venv path if the entry exists and passes the ownership checks.
+pub fn read_entry(cache_dir: &str, key: &str) -> Result<Option<String>, String> {
+    let path = entry_path(cache_dir, key);
+
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => return Ok(None),
+    };
+
+    // Guard against hash collisions: the entry must be for exactly this key. The venv path is
+    // the rest of the line, it may contain spaces.
+    let venv_path = match content
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix(key))
+        .and_then(|rest| rest.strip_prefix(' '))
+    {
+        Some(venv_path) if !venv_path.is_empty() => venv_path.to_string(),
+        _ => return Ok(None),
+    };
+
+    let python_bin = crate::venv_python(&venv_path);
+
+    if !Path::new(&python_bin).exists() {
+        return Ok(None);
+    }
+
+    check_entry(&path, &crate::venv_bin_dir(&venv_path))?;
+
+    Ok(Some(venv_path))
+}
+
+// Store an entry. Failing is fine (e.g. the dir is read-only for us, or another user
+// owns the entry in a sticky dir), the shared cache is only an optimization.
+pub fn write_entry(cache_dir: &str, key: &str, venv_path: &str) {
+    let path = entry_path(cache_dir, key);
+    let tmp_path = Path::new(cache_dir).join(format!(".{}.{}", hash_key(key), std::process::id()));
+
+    if fs::write(&tmp_path, format!("{} {}\n", key, venv_path)).is_err() {
+        return;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = fs::set_permissions(&tmp_path, fs::Permissions::from_mode(0o644));
+    }
+
+    if fs::rename(&tmp_path, &path).is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+}
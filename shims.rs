@@ -0,0 +1,91 @@
+// 'path_shims': instead of the whole venv bin dir, put a dir with shims for only the
+// listed executables on the task's PATH, so venv scripts can't shadow system tools by
+// accident. A shim is a tiny script exec'ing the venv's executable; a symlink wouldn't
+// do, as Python finds its venv relative to the path it was started by.
+//
+// Shim dirs are content-addressed, i.e. named after a hash of what they contain, so they
+// are created once and shared by all projects with the same venv and list. The hash
+// includes SHIM_FORMAT, so mk versions writing different shims don't share dirs.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use shared_cache;
+
+// Always shimmed, so 'python' in tasks keeps meaning the venv's interpreter.
+const ALWAYS: &[&str] = &["python", "python3"];
+
+// Changed whenever shim_script() changes.
+const SHIM_FORMAT: &str = "shims 1";
+
+fn shim_script(target: &str) -> String {
+    format!(
+        "#!/bin/sh\nexec '{}' \"$@\"\n",
+        target.replace('\'', "'\\''")
+    )
+}
+
+// The shim dir for 'names' from 'venv_bin_dir', created if needed.
+#[cfg(unix)]
+pub fn shim_dir(cache_dir: &str, venv_bin_dir: &str, names: &[String]) -> Result<String, String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut names: Vec<&str> = ALWAYS
+        .iter()
+        .cloned()
+        .chain(names.iter().map(|n| n.as_str()))
+        .collect();
+    names.sort();
+    names.dedup();
+
+    let shims: Vec<(&str, String)> = names
+        .into_iter()
+        .filter(|name| !name.contains('/'))
+        .map(|name| (name, format!("{}/{}", venv_bin_dir, name)))
+        .filter(|(_, target)| Path::new(target).exists())
+        .collect();
+
+    let manifest: String = shims
+        .iter()
+        .map(|(name, target)| format!("{} {}\n", name, target))
+        .collect();
+    let manifest = format!("{}\n{}", SHIM_FORMAT, manifest);
+    let dir = PathBuf::from(cache_dir)
+        .join("shims")
+        .join(shared_cache::hash_key(&manifest));
+
+    if dir.is_dir() {
+        return Ok(dir.display().to_string());
+    }
+    if crate::read_only() {
+        return Err(format!("{} doesn't exist yet", dir.display()));
+    }
+
+    // Build it next to its final place and move it there in one step, so concurrent runs
+    // never see a partial dir.
+    let tmp = dir.with_extension(format!("tmp{}", std::process::id()));
+    let error = |e: std::io::Error| format!("cannot create {}: {}", tmp.display(), e);
+    fs::create_dir_all(&tmp).map_err(error)?;
+
+    for (name, target) in &shims {
+        let path = tmp.join(name);
+        fs::write(&path, shim_script(target)).map_err(error)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).map_err(error)?;
+    }
+
+    if fs::rename(&tmp, &dir).is_err() {
+        // Another run was faster.
+        let _ = fs::remove_dir_all(&tmp);
+    }
+
+    Ok(dir.display().to_string())
+}
+
+#[cfg(not(unix))]
+pub fn shim_dir(
+    _cache_dir: &str,
+    _venv_bin_dir: &str,
+    _names: &[String],
+) -> Result<String, String> {
+    Err("shims are only supported on Unix".to_string())
+}
@@ -0,0 +1,92 @@
+// Existence checks for venvs on network filesystems (NFS, SMB), where a single stat can
+// take seconds. 'stat_timeout' bounds how long a check may take; a check that takes
+// longer counts as passed. With 'slow_fs = true' a venv that was checked successfully
+// isn't checked again for 'slow_fs_revalidate' seconds. Successful checks are recorded
+// as marker files in the local cache dir, whose mtime is the time of the check.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use config::Config;
+use shared_cache;
+
+fn marker(python_bin: &str) -> PathBuf {
+    Path::new(&crate::cache_dir())
+        .join("validated")
+        .join(shared_cache::hash_key(python_bin))
+}
+
+fn recently_validated(python_bin: &str, max_age: u64) -> bool {
+    let modified = match fs::metadata(marker(python_bin)).and_then(|m| m.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return false,
+    };
+
+    match SystemTime::now().duration_since(modified) {
+        Ok(age) => age.as_secs() < max_age,
+        Err(_) => false,
+    }
+}
+
+fn record_validated(python_bin: &str) {
+    if crate::read_only() {
+        return;
+    }
+    let path = marker(python_bin);
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(&path, python_bin);
+}
+
+// Forget the last check, e.g. because running the interpreter failed.
+pub fn invalidate(python_bin: &str) {
+    if crate::read_only() {
+        return;
+    }
+    let _ = fs::remove_file(marker(python_bin));
+}
+
+// Check in a thread, so a hanging stat can be abandoned. None if it timed out.
+fn exists_within(path: &str, timeout: Duration) -> Option<bool> {
+    let (tx, rx) = mpsc::channel();
+    let path = path.to_string();
+
+    thread::spawn(move || {
+        let _ = tx.send(Path::new(&path).exists());
+    });
+
+    rx.recv_timeout(timeout).ok()
+}
+
+pub fn python_exists(config: &Config, python_bin: &str) -> bool {
+    let slow_fs = config.get_bool("slow_fs", false);
+
+    if slow_fs && recently_validated(python_bin, config.get_u64("slow_fs_revalidate", 3600)) {
+        crate::explain(&format!("slow_fs: {} was checked recently", python_bin));
+        return true;
+    }
+
+    let exists = match config.get_u64("stat_timeout", 0) {
+        0 => Path::new(python_bin).exists(),
+        ms => match exists_within(python_bin, Duration::from_millis(ms)) {
+            Some(exists) => exists,
+            None => {
+                eprintln!(
+                    "mk: Checking {} took longer than {}ms, assuming it exists. Consider 'slow_fs = true'.",
+                    python_bin, ms
+                );
+                return true;
+            }
+        },
+    };
+
+    if exists && slow_fs {
+        record_validated(python_bin);
+    }
+
+    exists
+}
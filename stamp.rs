@@ -0,0 +1,52 @@
+// The stamp the cache stores with every entry, to tell when the entry may be out of date:
+// '<requires-python>+<files>'. The first part is a hash of requires-python, see
+// requires_python.rs. The second one is a hash of the size and mtime of the files that
+// decide which venv a project gets, so a venv that 'uv sync' recreated elsewhere, or a
+// switch from poetry to uv, is noticed and the venv resolved again instead of reusing a
+// stale path. Entries of older mk versions have no second part.
+
+use std::fs;
+use std::time::UNIX_EPOCH;
+
+use requires_python;
+use shared_cache;
+
+const PROJECT_FILES: &[&str] = &["pyproject.toml", "uv.lock", "poetry.lock"];
+
+// The hash of the project files, in the current dir. A warm run calls this, so it only
+// looks at the files' metadata.
+fn files() -> String {
+    let mut text = String::new();
+    for name in PROJECT_FILES {
+        let meta = fs::metadata(name).ok();
+        let mtime = meta
+            .as_ref()
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+        match meta {
+            Some(meta) => text.push_str(&format!("{}:{}:{};", name, meta.len(), mtime)),
+            None => text.push_str(&format!("{}:-;", name)),
+        }
+    }
+    shared_cache::hash_key(&text)
+}
+
+// The stamp for the project as it is now, with requires-python 'spec'.
+pub fn current(spec: Option<&str>) -> String {
+    format!("{}+{}", requires_python::stamp(spec), files())
+}
+
+// The requires-python part of 'stamp'.
+pub fn requires_python(stamp: &str) -> &str {
+    stamp.split('+').next().unwrap_or(stamp)
+}
+
+// Whether the project files changed since 'stored' was written. Not for stamps of older mk
+// versions, which don't know.
+pub fn files_changed(stored: &str, current: &str) -> bool {
+    match (stored.split_once('+'), current.split_once('+')) {
+        (Some((_, stored)), Some((_, current))) => stored != current,
+        _ => false,
+    }
+}
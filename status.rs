@@ -0,0 +1,97 @@
+// 'mk status [--markdown]': a summary of how to run the project's tasks, i.e. the tools it
+// needs and its tasks, generated from make.py and the config. With '--markdown' it's a
+// snippet for READMEs, e.g. regenerated in CI, so the docs never go out of date:
+//
+//     mk status --markdown > docs/tasks.md
+
+use std::path::Path;
+
+use config::Config;
+use listing;
+use requires_python;
+use tasks;
+
+const USAGE: &str = "Usage: mk status [--markdown]";
+
+// What's needed to run the tasks: Python, the package manager and the tools config tasks
+// run in the shell, in this order.
+fn requirements(config: &Config, project_root: &str) -> Vec<String> {
+    let mut tools = vec![match requires_python::read() {
+        Some(spec) => format!("Python {}", spec),
+        None => "Python".to_string(),
+    }];
+
+    let root = Path::new(project_root);
+    if root.join("uv.lock").exists() {
+        tools.push("uv".to_string());
+    } else if root.join("poetry.lock").exists() {
+        tools.push("poetry".to_string());
+    }
+
+    tools.push("mk".to_string());
+
+    // Tools from the venv are installed with the project.
+    let shims = config.get_list("path_shims").unwrap_or_default();
+
+    for name in tasks::names(config) {
+        for step in tasks::steps(config, &name).unwrap_or_default() {
+            let program = match step.run.split_whitespace().next() {
+                Some(program) => program.to_string(),
+                None => continue,
+            };
+            if !shims.contains(&program) && !program.contains('=') && !tools.contains(&program) {
+                tools.push(program);
+            }
+        }
+    }
+
+    tools
+}
+
+// Table cells can't contain '|' or line breaks.
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+pub fn run(
+    args: &[String],
+    config: &Config,
+    project_root: &str,
+    entries: &[(String, String)],
+) -> i32 {
+    let markdown = match args {
+        [] => false,
+        [flag] if flag == "--markdown" => true,
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+
+    let requirements = requirements(config, project_root);
+
+    if !markdown {
+        println!("Requires: {}", requirements.join(", "));
+        listing::print(config, entries);
+        return 0;
+    }
+
+    println!("## Tasks");
+    println!();
+    println!(
+        "Requires {}. Run tasks with `mk <task>`, list them with `mk --list`.",
+        requirements
+            .iter()
+            .map(|tool| format!("`{}`", tool))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    println!();
+    println!("| Task | Description |");
+    println!("| --- | --- |");
+    for (name, description) in entries {
+        println!("| `mk {}` | {} |", cell(name), cell(description));
+    }
+
+    0
+}
@@ -0,0 +1,229 @@
+// '--sync': install the project's dependencies into its venv (creating it if needed)
+// before running the task. Dependency groups and extras are passed on, so the venv
+// contains the tools tasks need, e.g. 'mk --sync --group dev lint'.
+//
+// '--frozen', '--locked' and '--no-sync' are passed on whenever mk runs uv itself, for
+// syncing and for finding the venv, so CI can be sure nothing is resolved or installed
+// behind its back. Poetry never updates 'poetry.lock' on 'install' and finds the venv
+// without installing anything, so it needs none of them.
+
+use std::io;
+use std::io::prelude::*;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process::Command;
+
+use config::Config;
+use messages;
+
+#[derive(Clone, Copy, Default)]
+pub struct LockFlags {
+    // Use the lockfile as it is, without checking it against 'pyproject.toml'.
+    pub frozen: bool,
+    // Fail if the lockfile doesn't match 'pyproject.toml' instead of updating it.
+    pub locked: bool,
+    // Don't sync the venv before running something in it.
+    pub no_sync: bool,
+}
+
+impl LockFlags {
+    // Args for 'uv sync'.
+    pub fn uv_sync_args(&self) -> Vec<&'static str> {
+        let mut args = Vec::new();
+        if self.frozen {
+            args.push("--frozen");
+        }
+        if self.locked {
+            args.push("--locked");
+        }
+        args
+    }
+
+    // Args for 'uv run', which syncs the venv unless told otherwise.
+    pub fn uv_run_args(&self) -> Vec<&'static str> {
+        let mut args = self.uv_sync_args();
+        if self.no_sync {
+            args.push("--no-sync");
+        }
+        args
+    }
+}
+
+// The sync command for the project's backend. Poetry projects are recognized by their
+// lockfile, everything else is synced with uv.
+pub fn command(
+    config: &Config,
+    project_root: &str,
+    groups: &[String],
+    extras: &[String],
+    lock_flags: &LockFlags,
+) -> Command {
+    let mut groups: Vec<String> = groups.to_vec();
+    groups.extend(config.get_list("sync_groups").unwrap_or_default());
+    let mut extras: Vec<String> = extras.to_vec();
+    extras.extend(config.get_list("sync_extras").unwrap_or_default());
+
+    let root = Path::new(project_root);
+    let poetry = root.join("poetry.lock").exists() && !root.join("uv.lock").exists();
+
+    let mut cmd;
+    if poetry {
+        cmd = Command::new("poetry");
+        cmd.arg("install");
+        for group in &groups {
+            cmd.arg("--with").arg(group);
+        }
+        for extra in &extras {
+            cmd.arg("--extras").arg(extra);
+        }
+    } else {
+        cmd = Command::new("uv");
+        cmd.arg("sync").args(lock_flags.uv_sync_args());
+        for group in &groups {
+            cmd.arg("--group").arg(group);
+        }
+        for extra in &extras {
+            cmd.arg("--extra").arg(extra);
+        }
+    }
+
+    cmd.current_dir(project_root);
+    cmd
+}
+
+// 'create_venv': what to do when no venv is found, 'never' (default), 'ask' on a terminal
+// or 'always', e.g. for fresh clones in CI. The venv is created with the sync command, as
+// with '--sync'. Returns whether it was, exits if that failed.
+pub fn create_venv(config: &Config, project_root: &str, lock_flags: &LockFlags) -> bool {
+    let ask = match config.get("create_venv") {
+        None | Some("never") => return false,
+        Some("ask") => true,
+        Some("always") => false,
+        Some(other) => {
+            crate::warn(&format!(
+                "Invalid value '{}' for 'create_venv', using never.",
+                other
+            ));
+            return false;
+        }
+    };
+    // Without a pyproject.toml there is nothing to sync, and '--no-sync' rules it out.
+    if lock_flags.no_sync || !Path::new(project_root).join("pyproject.toml").is_file() {
+        return false;
+    }
+
+    let cmd = command(config, project_root, &[], &[], lock_flags);
+    if ask {
+        if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+            return false;
+        }
+        eprint!(
+            "mk: {} ",
+            messages::get("create_venv_ask", &[("command", &describe(&cmd))])
+        );
+        let _ = io::stderr().flush();
+        let mut answer = String::new();
+        if io::stdin().lock().read_line(&mut answer).is_err() {
+            return false;
+        }
+        if !matches!(answer.trim().to_lowercase().as_str(), "" | "y" | "yes") {
+            return false;
+        }
+    } else {
+        eprintln!(
+            "mk: {}",
+            messages::get("creating_venv", &[("command", &describe(&cmd))])
+        );
+    }
+
+    if let Err(e) = run(cmd) {
+        eprintln!("mk: {}", messages::get("sync_failed", &[("reason", &e)]));
+        messages::print_runbook(config, "sync_failure");
+        std::process::exit(1);
+    }
+    true
+}
+
+// 'mk add-task-dep [--group <group>] <package>...'. Adds packages the tasks need to the
+// project's dev group (or the one given), with 'uv add' or 'poetry add', which also install
+// them into the venv.
+pub fn add_task_dep(args: &[String], project_root: &str, lock_flags: &LockFlags) -> i32 {
+    let usage = "Usage: mk add-task-dep [--group <group>] <package>...";
+
+    let mut group = "dev".to_string();
+    let mut packages = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--group" {
+            match args.next() {
+                Some(value) => group = value.clone(),
+                None => {
+                    eprintln!("{}", usage);
+                    return 2;
+                }
+            }
+        } else if let Some(value) = arg.strip_prefix("--group=") {
+            group = value.to_string();
+        } else if arg.starts_with('-') {
+            eprintln!("mk: Unknown option '{}'.\n{}", arg, usage);
+            return 2;
+        } else {
+            packages.push(arg.clone());
+        }
+    }
+    if packages.is_empty() {
+        eprintln!("{}", usage);
+        return 2;
+    }
+
+    if crate::read_only() {
+        eprintln!("mk: Not changing pyproject.toml in read-only mode.");
+        return 1;
+    }
+
+    let root = Path::new(project_root);
+    let poetry = root.join("poetry.lock").exists() && !root.join("uv.lock").exists();
+
+    let mut cmd;
+    if poetry {
+        cmd = Command::new("poetry");
+        cmd.arg("add").arg("--group").arg(&group);
+    } else {
+        cmd = Command::new("uv");
+        cmd.arg("add").args(lock_flags.uv_run_args());
+        if group == "dev" {
+            cmd.arg("--dev");
+        } else {
+            cmd.arg("--group").arg(&group);
+        }
+    }
+    cmd.args(&packages).current_dir(project_root);
+
+    eprintln!("mk: Adding with '{}'.", describe(&cmd));
+    match run(cmd) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("mk: {}", e);
+            1
+        }
+    }
+}
+
+// For messages.
+pub fn describe(cmd: &Command) -> String {
+    let mut words = vec![cmd.get_program().to_string_lossy().to_string()];
+    words.extend(cmd.get_args().map(|a| a.to_string_lossy().to_string()));
+    words.join(" ")
+}
+
+pub fn run(mut cmd: Command) -> Result<(), String> {
+    let status = cmd
+        .status()
+        .map_err(|e| format!("failed to execute '{}': {}", describe(&cmd), e))?;
+
+    if !status.success() {
+        return Err(format!("'{}' returned {}", describe(&cmd), status));
+    }
+
+    Ok(())
+}
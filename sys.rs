@@ -0,0 +1,316 @@
+// Thin wrappers around the few OS APIs std doesn't expose. mk has no dependencies, so
+// the C functions are declared here directly.
+
+use std::io;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+mod ffi {
+    extern "C" {
+        pub fn atexit(function: extern "C" fn()) -> i32;
+        pub fn geteuid() -> u32;
+        pub fn getpgid(pid: i32) -> i32;
+        pub fn getpgrp() -> i32;
+        pub fn kill(pid: i32, sig: i32) -> i32;
+        pub fn signal(signum: i32, handler: usize) -> usize;
+        pub fn tcgetpgrp(fd: i32) -> i32;
+    }
+
+    pub const SIG_IGN: usize = 1;
+    pub const SIG_ERR: usize = usize::MAX;
+    pub const SIGHUP: i32 = 1;
+    pub const SIGINT: i32 = 2;
+    pub const SIGKILL: i32 = 9;
+    pub const SIGTERM: i32 = 15;
+}
+
+// Effective user id of mk, None on platforms without user ids.
+#[cfg(unix)]
+pub fn euid() -> Option<u32> {
+    Some(unsafe { ffi::geteuid() })
+}
+
+#[cfg(not(unix))]
+pub fn euid() -> Option<u32> {
+    None
+}
+
+// Owner of a file, None if unknown.
+#[cfg(unix)]
+pub fn owner(path: &str) -> Option<u32> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(path).ok().map(|m| m.uid())
+}
+
+#[cfg(not(unix))]
+pub fn owner(_path: &str) -> Option<u32> {
+    None
+}
+
+// Run 'function' when mk exits, also through process::exit(), but not when it replaced
+// itself with the task.
+#[cfg(unix)]
+pub fn at_exit(function: extern "C" fn()) {
+    unsafe { ffi::atexit(function) };
+}
+
+#[cfg(not(unix))]
+pub fn at_exit(_function: extern "C" fn()) {}
+
+// Create dir 'path' that only mk's user can access. Fails if it exists, so a dir someone
+// else created first in a shared place like the temp dir is never taken over.
+#[cfg(unix)]
+pub fn create_private_dir(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    std::fs::DirBuilder::new().mode(0o700).create(path)
+}
+
+#[cfg(not(unix))]
+pub fn create_private_dir(path: &Path) -> io::Result<()> {
+    std::fs::create_dir(path)
+}
+
+// Whether 'path' is a dir (not a link) of mk's user that nobody else can write to.
+#[cfg(unix)]
+pub fn is_private_dir(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::symlink_metadata(path)
+        .is_ok_and(|m| m.is_dir() && Some(m.uid()) == euid() && m.mode() & 0o022 == 0)
+}
+
+#[cfg(not(unix))]
+pub fn is_private_dir(path: &Path) -> bool {
+    path.is_dir()
+}
+
+// Whether process 'pid' exists, None where that can't be told.
+#[cfg(unix)]
+pub fn alive(pid: u32) -> Option<bool> {
+    // A zombie, which no parent has waited for yet, has exited.
+    if let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+        let state = stat
+            .rsplit(") ")
+            .next()
+            .and_then(|rest| rest.chars().next());
+        return Some(state != Some('Z'));
+    }
+    if unsafe { ffi::kill(pid as i32, 0) } == 0 {
+        return Some(true);
+    }
+    // EPERM: it exists, but belongs to another user.
+    Some(std::io::Error::last_os_error().raw_os_error() == Some(1))
+}
+
+#[cfg(not(unix))]
+pub fn alive(_pid: u32) -> Option<bool> {
+    None
+}
+
+// When process 'pid' started, in seconds since the epoch, from '/proc'. None where that
+// can't be told.
+#[cfg(target_os = "linux")]
+pub fn started_at(pid: u32) -> Option<u64> {
+    extern "C" {
+        fn sysconf(name: i32) -> i64;
+    }
+    const SC_CLK_TCK: i32 = 2;
+
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name in parens may contain spaces. 'starttime' is field 22, in clock ticks
+    // since boot.
+    let ticks: u64 = stat.rsplit_once(") ")?.1.split(' ').nth(19)?.parse().ok()?;
+    let boot: u64 = std::fs::read_to_string("/proc/stat")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("btime "))?
+        .trim()
+        .parse()
+        .ok()?;
+    let per_second = match unsafe { sysconf(SC_CLK_TCK) } {
+        n if n > 0 => n as u64,
+        _ => return None,
+    };
+    Some(boot + ticks / per_second)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn started_at(_pid: u32) -> Option<u64> {
+    None
+}
+
+// Whether 'pid' is still the process that was running at 'since' (seconds since the
+// epoch), and not one that got its pid after it exited, which started later. None where
+// that can't be told.
+pub fn running_since(pid: u32, since: u64) -> Option<bool> {
+    if !alive(pid)? {
+        return Some(false);
+    }
+    match started_at(pid) {
+        Some(start) if since > 0 && start > since + 1 => Some(false),
+        _ => Some(true),
+    }
+}
+
+// A command that runs 'script' with the platform's shell.
+pub fn shell(script: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(script);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script);
+        cmd
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_signum: i32) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+// Note Ctrl+C and SIGTERM in interrupted() instead of dying, for modes that supervise
+// long-running children and must tear them down first.
+#[cfg(unix)]
+pub fn catch_interrupts() {
+    let handler = on_interrupt as extern "C" fn(i32) as usize;
+    unsafe {
+        ffi::signal(ffi::SIGINT, handler);
+        ffi::signal(ffi::SIGTERM, handler);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn catch_interrupts() {}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+// Start the command in a process group of its own, so terminate() also reaches the
+// processes it starts, e.g. the workers of a dev server.
+#[cfg(unix)]
+pub fn new_group(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    command.process_group(0);
+}
+
+#[cfg(not(unix))]
+pub fn new_group(_command: &mut Command) {}
+
+// Ask a child started with new_group() to stop (SIGTERM) and kill its group if it's still
+// running after 'grace'.
+pub fn terminate(child: &mut Child, grace: Duration) {
+    if let Ok(Some(_)) = child.try_wait() {
+        return;
+    }
+
+    #[cfg(unix)]
+    unsafe {
+        ffi::kill(-(child.id() as i32), ffi::SIGTERM);
+    }
+
+    let deadline = Instant::now() + grace;
+    while Instant::now() < deadline {
+        if let Ok(Some(_)) = child.try_wait() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    #[cfg(unix)]
+    unsafe {
+        ffi::kill(-(child.id() as i32), ffi::SIGKILL);
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+// The task mk waits for while forwarding signals to it, see forward_signals(): its pid if
+// it's in mk's process group, minus its pid if it has a group of its own, 0 if none.
+static FORWARD_TO: AtomicI32 = AtomicI32::new(0);
+
+#[cfg(unix)]
+extern "C" fn on_forwarded_signal(signum: i32) {
+    let target = FORWARD_TO.load(Ordering::SeqCst);
+    // In mk's group, Ctrl+C and the hangup of the terminal reach the task directly.
+    if target == 0 || (target > 0 && signum != ffi::SIGTERM) {
+        return;
+    }
+    unsafe {
+        ffi::kill(target, signum);
+    }
+}
+
+// Start the task of 'cmd' so forward_signals() reaches it. In the foreground of a terminal
+// it stays in mk's process group, which keeps job control (Ctrl+Z) and terminal input
+// working. Elsewhere, e.g. in CI, it gets a group of its own, so forwarded signals reach
+// the processes it starts as well.
+#[cfg(unix)]
+pub fn prepare_forwarding(cmd: &mut Command) {
+    if unsafe { ffi::tcgetpgrp(0) != ffi::getpgrp() } {
+        new_group(cmd);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn prepare_forwarding(_cmd: &mut Command) {}
+
+// Forwards SIGINT, SIGTERM and SIGHUP to the task while it's alive, so mk doesn't die and
+// leave it behind, e.g. when a CI runner cancels a job. mk then exits when the task did,
+// with its exit code. Signals mk ignores, e.g. the hangup under 'nohup', stay ignored.
+// Dropping it restores the handlers it replaced.
+pub struct Forwarding(Vec<(i32, usize)>);
+
+#[cfg(unix)]
+pub fn forward_signals(child: &Child) -> Forwarding {
+    let pid = child.id() as i32;
+    let own_group = unsafe { ffi::getpgid(pid) } == pid;
+    FORWARD_TO.store(if own_group { -pid } else { pid }, Ordering::SeqCst);
+
+    let handler = on_forwarded_signal as extern "C" fn(i32) as usize;
+    let mut replaced = Vec::new();
+    unsafe {
+        for signum in &[ffi::SIGINT, ffi::SIGTERM, ffi::SIGHUP] {
+            match ffi::signal(*signum, handler) {
+                ffi::SIG_ERR => {}
+                ffi::SIG_IGN => {
+                    ffi::signal(*signum, ffi::SIG_IGN);
+                }
+                previous => replaced.push((*signum, previous)),
+            }
+        }
+    }
+    Forwarding(replaced)
+}
+
+#[cfg(not(unix))]
+pub fn forward_signals(_child: &Child) -> Forwarding {
+    Forwarding(Vec::new())
+}
+
+impl Drop for Forwarding {
+    fn drop(&mut self) {
+        FORWARD_TO.store(0, Ordering::SeqCst);
+        for (_signum, _previous) in &self.0 {
+            #[cfg(unix)]
+            unsafe {
+                ffi::signal(*_signum, *_previous);
+            }
+        }
+    }
+}
+
+// Like Command::status(), with signals forwarded to the task, see forward_signals().
+pub fn run_forwarding(cmd: &mut Command) -> io::Result<ExitStatus> {
+    prepare_forwarding(cmd);
+    let mut child = cmd.spawn()?;
+    let _forwarding = forward_signals(&child);
+    child.wait()
+}
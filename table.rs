@@ -0,0 +1,125 @@
+// Tables in mk's own output, e.g. 'mk --list' and 'mk cache list'. Columns are aligned and
+// the last one, usually a description or path, is wrapped to fit the terminal, so tables
+// stay readable in narrow panes. Output that isn't a terminal, e.g. CI logs and pipes, is
+// never wrapped. The 'output_width' setting fixes the width, '--wide' turns wrapping off.
+
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use config::Config;
+use pty;
+
+// Set by '--wide'.
+static WIDE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_wide() {
+    WIDE.store(true, Ordering::Relaxed);
+}
+
+// Narrower than this, wrapping would make the last column unreadable, so it's not done.
+const MIN_LAST_COLUMN: usize = 20;
+
+// The width to fit tables into, None for no limit.
+pub fn max_width(config: &Config) -> Option<usize> {
+    if WIDE.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    match config.get_u64("output_width", 0) {
+        0 => {}
+        width => return Some(width as usize),
+    }
+
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .filter(|columns| *columns > 0)
+        .or_else(pty::stdout_columns)
+}
+
+// Split 'text' into lines of at most 'width' chars, at spaces where possible.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split(' ') {
+        let mut word = word.to_string();
+        loop {
+            let used = line.chars().count();
+            let needed = word.chars().count() + if line.is_empty() { 0 } else { 1 };
+            if used + needed <= width {
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(&word);
+                break;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+                continue;
+            }
+            // A word longer than a line, e.g. a path, is broken up.
+            let head: String = word.chars().take(width).collect();
+            word = word.chars().skip(width).collect();
+            lines.push(head);
+        }
+    }
+
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// The lines of a table with 'rows' of the same number of cells, each indented by 'indent'.
+pub fn format(rows: &[Vec<String>], indent: usize, max_width: Option<usize>) -> Vec<String> {
+    let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    if columns == 0 {
+        return Vec::new();
+    }
+
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    // Where the last column starts.
+    let offset = indent + widths[..columns - 1].iter().map(|w| w + 2).sum::<usize>();
+    let last_width = match max_width {
+        Some(max) if max >= offset + MIN_LAST_COLUMN => Some(max - offset),
+        _ => None,
+    };
+
+    let mut lines = Vec::new();
+    for row in rows {
+        let mut line = " ".repeat(indent);
+        for (i, width) in widths[..columns - 1].iter().enumerate() {
+            let cell = row.get(i).map(|c| c.as_str()).unwrap_or("");
+            line.push_str(&format!("{:width$}  ", cell, width = width));
+        }
+
+        let last = row.get(columns - 1).map(|c| c.as_str()).unwrap_or("");
+        let parts = match last_width {
+            Some(width) => wrap(last, width),
+            None => vec![last.to_string()],
+        };
+        for (i, part) in parts.iter().enumerate() {
+            if i == 0 {
+                line.push_str(part);
+                lines.push(line.trim_end().to_string());
+            } else {
+                lines.push(format!("{}{}", " ".repeat(offset), part));
+            }
+        }
+    }
+    lines
+}
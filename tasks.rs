@@ -0,0 +1,360 @@
+// Tasks defined in the config instead of make.py, for glue that doesn't need Python:
+//
+//     [task.release]
+//     steps = [
+//         "mk test",
+//         "mk build",
+//         { run = "twine upload dist/*", when = "env.CI == '1'" },
+//     ]
+//
+// Steps run one after another in the project root, until one fails. 'mk <task>' steps run
+// the make.py task directly, in the venv that was already resolved, or the tool of a task
+// mapped in '[facade]'. Other 'mk ...' steps (options, config tasks) run this mk binary.
+// Everything else runs in the shell with the same environment make.py gets, so tools from
+// the venv are found.
+//
+// With 'matrix = { py = ["3.10", "3.12"], db = ["sqlite", "postgres"] }' the steps run once
+// per combination, with the values in 'MK_MATRIX_PY' and 'MK_MATRIX_DB'.
+
+use std::ffi::OsString;
+use std::process::{Command, ExitStatus};
+use std::time::Duration;
+
+use alias;
+use config::Config;
+use expr;
+use facade;
+use limits;
+use limits::Limits;
+use sync;
+use sys;
+use table;
+use toml::Value;
+
+pub struct Step {
+    pub run: String,
+    // Condition, see expr.rs. The step is skipped if it's false.
+    pub when: Option<String>,
+}
+
+// The steps of config task 'name', None if there is no such task.
+pub fn steps(config: &Config, name: &str) -> Option<Vec<Step>> {
+    let steps = match config.get_value(&format!("task.{}.steps", name))? {
+        Value::Array(items) => items,
+        _ => {
+            eprintln!("mk: 'task.{}.steps' must be a list of commands.", name);
+            std::process::exit(1);
+        }
+    };
+
+    Some(
+        steps
+            .iter()
+            .filter_map(|step| match step {
+                Value::String(run) => Some(Step {
+                    run: run.clone(),
+                    when: None,
+                }),
+                Value::Table(entries) => {
+                    let field = |name: &str| {
+                        entries
+                            .iter()
+                            .find(|(k, _)| k == name)
+                            .and_then(|(_, v)| v.as_str())
+                            .map(|v| v.to_string())
+                    };
+                    match field("run") {
+                        Some(run) => Some(Step {
+                            run,
+                            when: field("when"),
+                        }),
+                        None => {
+                            crate::warn(&format!(
+                                "Ignoring step without 'run' in 'task.{}.steps'.",
+                                name
+                            ));
+                            None
+                        }
+                    }
+                }
+                _ => {
+                    eprintln!(
+                        "mk: Ignoring invalid step in 'task.{}.steps': {}",
+                        name, step
+                    );
+                    None
+                }
+            })
+            .collect(),
+    )
+}
+
+// Names of the tasks defined in the config, sorted.
+pub fn names(config: &Config) -> Vec<String> {
+    config
+        .values()
+        .into_iter()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("task.")?
+                .strip_suffix(".steps")
+                .map(|name| name.to_string())
+        })
+        .collect()
+}
+
+// The combinations of 'task.<name>.matrix', e.g. 'matrix = { py = ["3.10", "3.12"] }',
+// as (key, value) lists in the order of the config. None if the task has no matrix.
+pub fn matrix(config: &Config, name: &str) -> Option<Vec<Vec<(String, String)>>> {
+    let entries = match config.get_value(&format!("task.{}.matrix", name))? {
+        Value::Table(entries) => entries,
+        _ => {
+            eprintln!("mk: 'task.{}.matrix' must be a table of lists.", name);
+            std::process::exit(1);
+        }
+    };
+
+    let mut combinations: Vec<Vec<(String, String)>> = vec![Vec::new()];
+
+    for (key, values) in entries {
+        let values: Vec<String> = match values {
+            Value::Array(items) => items
+                .iter()
+                .map(|v| match v {
+                    Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                })
+                .collect(),
+            v => vec![v.to_string()],
+        };
+
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.push((key.clone(), value.clone()));
+                    combination
+                })
+            })
+            .collect();
+    }
+
+    Some(combinations)
+}
+
+// The environment variable a matrix value is passed in, e.g. 'MK_MATRIX_PY' for 'py'.
+pub fn matrix_var(key: &str) -> String {
+    format!(
+        "MK_MATRIX_{}",
+        key.to_uppercase()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+    )
+}
+
+pub fn describe_combination(combination: &[(String, String)]) -> String {
+    combination
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// 'mk <task>' steps for a 'python' matrix entry: make.py runs with that Python in a
+// throwaway environment, so the project's venv is left alone.
+pub fn uv_python_command(
+    version: &str,
+    script: &str,
+    task_args: &[String],
+    lock_flags: &sync::LockFlags,
+) -> Command {
+    let mut cmd = Command::new("uv");
+    cmd.arg("run")
+        .args(lock_flags.uv_run_args())
+        .args(["--isolated", "--python", version, "python", script])
+        .args(task_args);
+    cmd
+}
+
+pub struct MatrixResult {
+    pub combination: Vec<(String, String)>,
+    pub status: ExitStatus,
+    pub duration: Duration,
+}
+
+pub fn print_grid(config: &Config, results: &[MatrixResult]) {
+    let keys: Vec<&str> = match results.first() {
+        Some(result) => result.combination.iter().map(|(k, _)| k.as_str()).collect(),
+        None => return,
+    };
+
+    let mut rows: Vec<Vec<String>> = vec![keys
+        .iter()
+        .map(|k| k.to_string())
+        .chain(vec!["result".to_string(), "time".to_string()])
+        .collect()];
+    for result in results {
+        let mut row: Vec<String> = result.combination.iter().map(|(_, v)| v.clone()).collect();
+        row.push(if result.status.success() {
+            "ok".to_string()
+        } else {
+            format!("FAILED ({})", result.status)
+        });
+        row.push(format!("{:.1}s", result.duration.as_secs_f64()));
+        rows.push(row);
+    }
+
+    eprintln!();
+    for line in table::format(&rows, 2, table::max_width(config)) {
+        eprintln!("{}", line);
+    }
+}
+
+// Split a command line into words, with '...' and "..." quoting and backslash escapes.
+pub fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, ' ') | (None, '\t') => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => word.push(c),
+            (_, '\\') => {
+                if let Some(next) = chars.next() {
+                    word.push(next);
+                }
+                in_word = true;
+            }
+            (_, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+// The command of a step, run in 'dir' with 'env' and the task's 'limits'. Steps that run
+// make.py tasks get the limits of those tasks from 'make_py'.
+pub fn command(
+    config: &Config,
+    step: &Step,
+    make_py: &dyn Fn(&[String]) -> Command,
+    env: &[(OsString, Option<OsString>)],
+    limits: Limits,
+    dir: &str,
+) -> Command {
+    let mut words = split_words(&step.run);
+    // Aliases work as on the command line.
+    if words.first().is_some_and(|program| program == "mk") {
+        let args = alias::expand(config, &words[1..]);
+        words.truncate(1);
+        words.extend(args);
+    }
+
+    let mut cmd = match words.split_first() {
+        Some((program, args))
+            if program == "mk"
+                && args.first().is_some_and(|task| {
+                    !task.starts_with('-')
+                        && config.get_value(&format!("task.{}.steps", task)).is_none()
+                }) =>
+        {
+            match facade::command(config, &args[0], &args[1..], dir) {
+                Some(cmd) => cmd,
+                None => make_py(args),
+            }
+        }
+        Some((program, args)) if program == "mk" => {
+            let mut cmd = crate::mk_command();
+            cmd.args(args);
+            limits::apply(&mut cmd, limits);
+            cmd
+        }
+        _ => {
+            let mut cmd = sys::shell(&step.run);
+            limits::apply(&mut cmd, limits);
+            cmd
+        }
+    };
+
+    // Variables without a value are removed, see 'unset_env'.
+    for (name, value) in env {
+        match value {
+            Some(value) => cmd.env(name, value),
+            None => cmd.env_remove(name),
+        };
+    }
+    cmd.current_dir(dir);
+    cmd
+}
+
+// Whether 'step' runs, i.e. its condition is true if it has one.
+pub fn should_run(step: &Step) -> bool {
+    let when = match &step.when {
+        Some(when) => when,
+        None => return true,
+    };
+
+    match expr::eval(when) {
+        Ok(true) => true,
+        Ok(false) => {
+            eprintln!("mk: - {} (skipped, 'when = {}' is false)", step.run, when);
+            false
+        }
+        Err(e) => {
+            eprintln!("mk: Invalid condition '{}': {}.", when, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Whether the combinations of task 'name' run in parallel, see dashboard.rs.
+pub fn parallel(config: &Config, name: &str) -> bool {
+    config.get_bool(&format!("task.{}.parallel", name), false)
+}
+
+// Run the steps. Returns the failed step and its status, if one failed.
+pub fn run(
+    config: &Config,
+    steps: &[Step],
+    make_py: &dyn Fn(&[String]) -> Command,
+    env: &[(OsString, Option<OsString>)],
+    limits: Limits,
+    dir: &str,
+) -> Result<(), (String, ExitStatus)> {
+    for step in steps.iter().filter(|step| should_run(step)) {
+        eprintln!("mk: > {}", step.run);
+
+        let status =
+            match sys::run_forwarding(&mut command(config, step, make_py, env, limits, dir)) {
+                Ok(status) => status,
+                Err(e) => {
+                    eprintln!("mk: Failed to run '{}': {}", step.run, e);
+                    std::process::exit(1);
+                }
+            };
+
+        if !status.success() {
+            return Err((step.run.clone(), status));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,211 @@
+// Opt-in OpenTelemetry export. With 'otlp_endpoint' set, every invocation is sent as a
+// trace to an OTLP/HTTP collector (JSON encoding): one 'mk' span for the whole
+// invocation and one child span for the task, with duration, exit code, project and
+// task name.
+//
+// Only plain 'http://' endpoints are supported, as mk has no TLS stack. Point it at a
+// local collector or agent that forwards to your backend. Builds with '--cfg
+// mk_no_telemetry' leave it out.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::prelude::*;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use config::Config;
+use hooks::Outcome;
+use json;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn random_u64(salt: u64) -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(salt);
+    hasher.write_u32(process::id());
+    hasher.write_u128(unix_nanos(SystemTime::now()));
+    hasher.finish()
+}
+
+fn unix_nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0)
+}
+
+fn attribute(key: &str, value: &str) -> String {
+    format!(
+        "{{\"key\":{},\"value\":{{\"stringValue\":{}}}}}",
+        json::quote(key),
+        json::quote(value)
+    )
+}
+
+fn int_attribute(key: &str, value: i64) -> String {
+    format!(
+        "{{\"key\":{},\"value\":{{\"intValue\":\"{}\"}}}}",
+        json::quote(key),
+        value
+    )
+}
+
+struct Span {
+    span_id: String,
+    parent_span_id: String,
+    name: String,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<String>,
+    ok: bool,
+}
+
+fn span_json(trace_id: &str, span: &Span) -> String {
+    format!(
+        "{{\"traceId\":\"{}\",\"spanId\":\"{}\",\"parentSpanId\":\"{}\",\"name\":{},\"kind\":1,\"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\"attributes\":[{}],\"status\":{{\"code\":{}}}}}",
+        trace_id,
+        span.span_id,
+        span.parent_span_id,
+        json::quote(&span.name),
+        unix_nanos(span.start),
+        unix_nanos(span.end),
+        span.attributes.join(","),
+        if span.ok { 1 } else { 2 }
+    )
+}
+
+// Split 'http://host:port/path' into host:port and path.
+fn parse_endpoint(endpoint: &str) -> Result<(String, String), String> {
+    let rest = match endpoint.strip_prefix("http://") {
+        Some(rest) => rest,
+        None if endpoint.starts_with("https://") => {
+            return Err("https endpoints are not supported, use a local collector".to_string())
+        }
+        None => return Err("expected an 'http://' URL".to_string()),
+    };
+
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], rest[i..].trim_end_matches('/')),
+        None => (rest, ""),
+    };
+
+    let authority = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let path = if path.ends_with("/v1/traces") {
+        path.to_string()
+    } else {
+        format!("{}/v1/traces", path)
+    };
+
+    Ok((authority, path))
+}
+
+fn post(endpoint: &str, body: &str) -> Result<(), String> {
+    let (authority, path) = parse_endpoint(endpoint)?;
+
+    let addr = authority
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("cannot resolve {}", authority))?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(TIMEOUT)).ok();
+    stream.set_write_timeout(Some(TIMEOUT)).ok();
+
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        authority,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| e.to_string())?;
+
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    let status_line = response.lines().next().unwrap_or("");
+
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        Some(_) => Err(format!("collector answered '{}'", status_line)),
+        None => Err("no response from collector".to_string()),
+    }
+}
+
+pub fn export(
+    config: &Config,
+    outcome: &Outcome,
+    mk_started: SystemTime,
+    task_started: SystemTime,
+) {
+    let endpoint = match config.get("otlp_endpoint") {
+        Some(endpoint) if !endpoint.is_empty() => endpoint,
+        _ => return,
+    };
+
+    if cfg!(mk_no_telemetry) {
+        crate::warn("This mk was built without telemetry, ignoring 'otlp_endpoint'.");
+        return;
+    }
+
+    let trace_id = format!("{:016x}{:016x}", random_u64(1), random_u64(2));
+    let root_id = format!("{:016x}", random_u64(3));
+    let task_name = outcome.args.first().map(|a| a.as_str()).unwrap_or("");
+
+    let mut attributes = vec![
+        attribute("mk.project", outcome.project_root),
+        attribute("mk.task", task_name),
+        attribute("mk.args", &outcome.args.join(" ")),
+        attribute(
+            "mk.result",
+            if outcome.success {
+                "success"
+            } else {
+                "failure"
+            },
+        ),
+    ];
+    if let Some(code) = outcome.exit_code {
+        attributes.push(int_attribute("process.exit_code", i64::from(code)));
+    }
+
+    let root = Span {
+        span_id: root_id.clone(),
+        parent_span_id: "".to_string(),
+        name: "mk".to_string(),
+        start: mk_started,
+        end: SystemTime::now(),
+        attributes: attributes.clone(),
+        ok: outcome.success,
+    };
+
+    let task = Span {
+        span_id: format!("{:016x}", random_u64(4)),
+        parent_span_id: root_id,
+        name: format!("task {}", task_name).trim().to_string(),
+        start: task_started,
+        end: task_started + outcome.duration,
+        attributes,
+        ok: outcome.success,
+    };
+
+    let body = format!(
+        "{{\"resourceSpans\":[{{\"resource\":{{\"attributes\":[{},{}]}},\"scopeSpans\":[{{\"scope\":{{\"name\":\"mk\",\"version\":\"{}\"}},\"spans\":[{},{}]}}]}}]}}",
+        attribute("service.name", "mk"),
+        attribute("service.version", crate::VERSION),
+        crate::VERSION,
+        span_json(&trace_id, &root),
+        span_json(&trace_id, &task)
+    );
+
+    if let Err(e) = post(endpoint, &body) {
+        eprintln!("mk: Couldn't export trace to {}: {}", endpoint, e);
+    }
+}
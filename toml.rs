@@ -0,0 +1,650 @@
+// A small TOML parser. mk must stay dependency free, so this covers what config files
+// and pyproject.toml use in practice: tables, arrays of tables, dotted and quoted keys,
+// all string flavours, integers, floats, booleans, date-times (kept as text), arrays
+// and inline tables.
+//
+// Besides the values, the parser records where in the text each key/value pair is, so
+// 'mk config set' can edit files without destroying comments and formatting.
+
+use std::collections::HashSet;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Datetime(String),
+    Array(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+// Serializes as TOML.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{}", quote(s)),
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{:?}", n),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Datetime(d) => write!(f, "{}", d),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(|v| v.to_string()).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Table(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(k, v)| format!("{} = {}", quote_key(k), v))
+                    .collect();
+                write!(f, "{{ {} }}", entries.join(", "))
+            }
+        }
+    }
+}
+
+pub fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                out.push_str(&format!("\\u{:04X}", c as u32))
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn is_bare_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+pub fn quote_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(is_bare_key_char) {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+// A key/value pair as written in the document. 'key' is the full dotted path including
+// the enclosing table, e.g. 'task.test.steps'.
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    // Byte offsets: start of the line, and the span of the value.
+    pub line_start: usize,
+    pub value_start: usize,
+    pub value_end: usize,
+}
+
+pub struct Header {
+    pub name: String,
+    // Byte offsets of the start of the header line and just past it.
+    pub line_start: usize,
+    pub line_end: usize,
+}
+
+pub struct Document {
+    pub entries: Vec<Entry>,
+    pub headers: Vec<Header>,
+}
+
+impl Document {
+    // All values, with inline tables flattened into dotted keys as well.
+    pub fn flatten(&self) -> Vec<(String, Value)> {
+        let mut out = Vec::new();
+        for entry in &self.entries {
+            flatten_into(&entry.key, &entry.value, &mut out);
+        }
+        out
+    }
+}
+
+fn flatten_into(key: &str, value: &Value, out: &mut Vec<(String, Value)>) {
+    if let Value::Table(entries) = value {
+        for (k, v) in entries {
+            flatten_into(&format!("{}.{}", key, k), v, out);
+        }
+    }
+    out.push((key.to_string(), value.clone()));
+}
+
+pub fn parse(text: &str) -> Result<Document, String> {
+    let mut parser = Parser {
+        src: text,
+        pos: 0,
+        table: Vec::new(),
+        array_tables: Vec::new(),
+        seen: HashSet::new(),
+        doc: Document {
+            entries: Vec::new(),
+            headers: Vec::new(),
+        },
+    };
+
+    parser.document().map_err(|e| {
+        let line = text[..parser.pos.min(text.len())].matches('\n').count() + 1;
+        format!("line {}: {}", line, e)
+    })?;
+
+    Ok(parser.doc)
+}
+
+// Parse a single value, e.g. one given on the command line.
+pub fn parse_value(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        src: text,
+        pos: 0,
+        table: Vec::new(),
+        array_tables: Vec::new(),
+        seen: HashSet::new(),
+        doc: Document {
+            entries: Vec::new(),
+            headers: Vec::new(),
+        },
+    };
+
+    parser.skip_ws();
+    let value = parser.value()?;
+    parser.skip_ws();
+
+    if parser.pos != text.len() {
+        return Err("unexpected trailing characters".to_string());
+    }
+
+    Ok(value)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    // Path of the current [table].
+    table: Vec<String>,
+    // How often each [[array.table]] was opened, to number its elements.
+    array_tables: Vec<(String, usize)>,
+    // Keys defined so far, to reject duplicates.
+    seen: HashSet<Vec<String>>,
+    doc: Document,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.src[self.pos..].starts_with(s)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.peek() {
+            Some(found) if found == c => {
+                self.pos += c.len_utf8();
+                Ok(())
+            }
+            Some(found) => Err(format!("expected '{}', found '{}'", c, found)),
+            None => Err(format!("expected '{}', found end of file", c)),
+        }
+    }
+
+    // Spaces and tabs.
+    fn skip_ws(&mut self) {
+        while let Some(' ') | Some('\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while let Some(c) = self.peek() {
+                if c == '\n' {
+                    break;
+                }
+                self.pos += c.len_utf8();
+            }
+        }
+    }
+
+    // Whitespace, newlines and comments, as allowed inside arrays.
+    fn skip_ws_comments_newlines(&mut self) {
+        loop {
+            self.skip_ws();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') => self.pos += 1,
+                Some('\r') if self.starts_with("\r\n") => self.pos += 2,
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_ws();
+        self.skip_comment();
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some('\r') if self.starts_with("\r\n") => {
+                self.pos += 2;
+                Ok(())
+            }
+            Some(c) => Err(format!("unexpected '{}' after value", c)),
+        }
+    }
+
+    fn document(&mut self) -> Result<(), String> {
+        loop {
+            self.skip_ws_comments_newlines();
+
+            match self.peek() {
+                None => return Ok(()),
+                Some('[') => self.header()?,
+                Some(_) => {
+                    let line_start = self.pos;
+                    let mut key = self.table.clone();
+                    key.extend(self.key()?);
+                    self.skip_ws();
+                    self.expect('=')?;
+                    self.skip_ws();
+                    let value_start = self.pos;
+                    let value = self.value()?;
+                    let value_end = self.pos;
+                    self.end_of_line()?;
+
+                    if !self.seen.insert(key.clone()) {
+                        return Err(format!("duplicate key '{}'", key.join(".")));
+                    }
+                    let key = key.join(".");
+
+                    self.doc.entries.push(Entry {
+                        key,
+                        value,
+                        line_start,
+                        value_start,
+                        value_end,
+                    });
+                }
+            }
+        }
+    }
+
+    fn header(&mut self) -> Result<(), String> {
+        let line_start = self.pos;
+        let is_array = self.starts_with("[[");
+        self.pos += if is_array { 2 } else { 1 };
+        self.skip_ws();
+        let parts = self.key()?;
+        self.skip_ws();
+        if is_array {
+            self.expect(']')?;
+        }
+        self.expect(']')?;
+        self.end_of_line()?;
+
+        // Elements of [[name]] become 'name.0', 'name.1', ... Headers below an array of
+        // tables refer to its latest element.
+        let mut name = Vec::new();
+        for (i, part) in parts.iter().enumerate() {
+            name.push(part.clone());
+            if is_array && i + 1 == parts.len() {
+                break;
+            }
+            let joined = name.join(".");
+            if let Some((_, index)) = self.array_tables.iter().find(|(n, _)| *n == joined) {
+                name.push(index.to_string());
+            }
+        }
+
+        if is_array {
+            let joined = name.join(".");
+            let index = match self.array_tables.iter_mut().find(|(n, _)| *n == joined) {
+                Some(entry) => {
+                    entry.1 += 1;
+                    entry.1
+                }
+                None => {
+                    self.array_tables.push((joined, 0));
+                    0
+                }
+            };
+            name.push(index.to_string());
+        }
+
+        self.table = name;
+        self.doc.headers.push(Header {
+            name: self.table.join("."),
+            line_start,
+            line_end: self.pos,
+        });
+
+        Ok(())
+    }
+
+    // A possibly dotted key.
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_ws();
+            let part = match self.peek() {
+                Some('"') => self.basic_string()?,
+                Some('\'') => self.literal_string()?,
+                Some(c) if is_bare_key_char(c) => {
+                    let start = self.pos;
+                    while let Some(c) = self.peek() {
+                        if !is_bare_key_char(c) {
+                            break;
+                        }
+                        self.pos += 1;
+                    }
+                    self.src[start..self.pos].to_string()
+                }
+                Some(c) => return Err(format!("invalid character '{}' in key", c)),
+                None => return Err("expected a key".to_string()),
+            };
+            parts.push(part);
+            self.skip_ws();
+            if self.peek() == Some('.') {
+                self.pos += 1;
+            } else {
+                return Ok(parts);
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') if self.starts_with("\"\"\"") => {
+                self.multiline_basic_string().map(Value::String)
+            }
+            Some('"') => self.basic_string().map(Value::String),
+            Some('\'') if self.starts_with("'''") => {
+                self.multiline_literal_string().map(Value::String)
+            }
+            Some('\'') => self.literal_string().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some('t') if self.starts_with("true") => {
+                self.pos += 4;
+                Ok(Value::Boolean(true))
+            }
+            Some('f') if self.starts_with("false") => {
+                self.pos += 5;
+                Ok(Value::Boolean(false))
+            }
+            Some(_) => self.number_or_datetime(),
+            None => Err("expected a value".to_string()),
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let c = self.bump().ok_or("unterminated escape sequence")?;
+        let unicode = |p: &mut Parser, len: usize| -> Result<char, String> {
+            let end = p.pos + len;
+            let hex = p.src.get(p.pos..end).ok_or("invalid unicode escape")?;
+            let code = u32::from_str_radix(hex, 16).map_err(|_| "invalid unicode escape")?;
+            p.pos = end;
+            std::char::from_u32(code).ok_or_else(|| "invalid unicode escape".to_string())
+        };
+        match c {
+            'b' => Ok('\u{8}'),
+            't' => Ok('\t'),
+            'n' => Ok('\n'),
+            'f' => Ok('\u{c}'),
+            'r' => Ok('\r'),
+            'e' => Ok('\u{1b}'),
+            '"' => Ok('"'),
+            '\\' => Ok('\\'),
+            'x' => unicode(self, 2),
+            'u' => unicode(self, 4),
+            'U' => unicode(self, 8),
+            c => Err(format!("invalid escape sequence '\\{}'", c)),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(out),
+                Some('\\') => out.push(self.escape()?),
+                Some('\n') | None => return Err("unterminated string".to_string()),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.expect('\'')?;
+        let start = self.pos;
+        loop {
+            match self.bump() {
+                Some('\'') => return Ok(self.src[start..self.pos - 1].to_string()),
+                Some('\n') | None => return Err("unterminated string".to_string()),
+                Some(_) => {}
+            }
+        }
+    }
+
+    fn multiline_basic_string(&mut self) -> Result<String, String> {
+        self.pos += 3;
+        self.skip_first_newline();
+        let mut out = String::new();
+        loop {
+            if self.starts_with("\"\"\"") {
+                self.pos += 3;
+                // Up to two extra quotes may close the string.
+                while self.peek() == Some('"') && !self.starts_with("\"\"\"") {
+                    out.push('"');
+                    self.pos += 1;
+                }
+                return Ok(out);
+            }
+            match self.bump() {
+                Some('\\') => {
+                    // A backslash at the end of a line trims the following whitespace.
+                    let rest = &self.src[self.pos..];
+                    let trimmed = rest.trim_start_matches([' ', '\t']);
+                    if trimmed.starts_with('\n') || trimmed.starts_with("\r\n") {
+                        let ws = rest.len() - rest.trim_start().len();
+                        self.pos += ws;
+                    } else {
+                        out.push(self.escape()?);
+                    }
+                }
+                Some(c) => out.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn multiline_literal_string(&mut self) -> Result<String, String> {
+        self.pos += 3;
+        self.skip_first_newline();
+        let start = self.pos;
+        match self.src[self.pos..].find("'''") {
+            Some(offset) => {
+                let mut end = self.pos + offset;
+                self.pos = end + 3;
+                while self.peek() == Some('\'') && !self.starts_with("'''") {
+                    self.pos += 1;
+                    end += 1;
+                }
+                Ok(self.src[start..end].to_string())
+            }
+            None => Err("unterminated string".to_string()),
+        }
+    }
+
+    fn skip_first_newline(&mut self) {
+        if self.starts_with("\n") {
+            self.pos += 1;
+        } else if self.starts_with("\r\n") {
+            self.pos += 2;
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_ws_comments_newlines();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_ws_comments_newlines();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return Err("expected ',' or ']' in array".to_string()),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut entries: Vec<(String, Value)> = Vec::new();
+        loop {
+            self.skip_ws_comments_newlines();
+            if self.peek() == Some('}') {
+                self.pos += 1;
+                return Ok(Value::Table(entries));
+            }
+            let key = self.key()?;
+            self.skip_ws();
+            self.expect('=')?;
+            self.skip_ws();
+            let value = self.value()?;
+            insert_dotted(&mut entries, &key, value)?;
+            self.skip_ws_comments_newlines();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {}
+                _ => return Err("expected ',' or '}' in inline table".to_string()),
+            }
+        }
+    }
+
+    fn number_or_datetime(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            // Date-times may contain a single space between date and time.
+            let is_datetime_space = c == ' '
+                && self.src[start..self.pos].len() == 10
+                && self.src[start..self.pos].matches('-').count() == 2
+                && self.src[self.pos + 1..]
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.is_ascii_digit());
+            if c.is_ascii_alphanumeric() || "+-_.:".contains(c) || is_datetime_space {
+                self.pos += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+        let token = &self.src[start..self.pos];
+
+        if token.is_empty() {
+            return Err(format!(
+                "invalid value starting with '{}'",
+                self.peek().unwrap_or(' ')
+            ));
+        }
+
+        // Date-times, e.g. '1979-05-27' or '07:32'.
+        let bytes = token.as_bytes();
+        if bytes.len() >= 5
+            && ((bytes[4] == b'-' && bytes[..4].iter().all(u8::is_ascii_digit))
+                || (bytes[2] == b':' && bytes[..2].iter().all(u8::is_ascii_digit)))
+        {
+            return Ok(Value::Datetime(token.to_string()));
+        }
+
+        let clean = token.replace('_', "");
+
+        match clean.as_str() {
+            "inf" | "+inf" => return Ok(Value::Float(f64::INFINITY)),
+            "-inf" => return Ok(Value::Float(f64::NEG_INFINITY)),
+            "nan" | "+nan" | "-nan" => return Ok(Value::Float(f64::NAN)),
+            _ => {}
+        }
+
+        let radix = |prefix: &str, radix: u32| -> Option<Result<Value, String>> {
+            clean.strip_prefix(prefix).map(|digits| {
+                i64::from_str_radix(digits, radix)
+                    .map(Value::Integer)
+                    .map_err(|_| format!("invalid number '{}'", token))
+            })
+        };
+        if let Some(result) = radix("0x", 16)
+            .or_else(|| radix("0o", 8))
+            .or_else(|| radix("0b", 2))
+        {
+            return result;
+        }
+
+        if let Ok(n) = clean.parse::<i64>() {
+            return Ok(Value::Integer(n));
+        }
+        if clean.contains(['.', 'e', 'E']) {
+            if let Ok(n) = clean.parse::<f64>() {
+                return Ok(Value::Float(n));
+            }
+        }
+
+        Err(format!("invalid value '{}'", token))
+    }
+}
+
+fn insert_dotted(
+    entries: &mut Vec<(String, Value)>,
+    key: &[String],
+    value: Value,
+) -> Result<(), String> {
+    let (first, rest) = key.split_first().ok_or("empty key")?;
+
+    if rest.is_empty() {
+        if entries.iter().any(|(k, _)| k == first) {
+            return Err(format!("duplicate key '{}'", first));
+        }
+        entries.push((first.clone(), value));
+        return Ok(());
+    }
+
+    if !entries.iter().any(|(k, _)| k == first) {
+        entries.push((first.clone(), Value::Table(Vec::new())));
+    }
+    match entries.iter_mut().find(|(k, _)| k == first) {
+        Some((_, Value::Table(inner))) => insert_dotted(inner, rest, value),
+        _ => Err(format!("'{}' is not a table", first)),
+    }
+}
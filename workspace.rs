@@ -0,0 +1,105 @@
+// The '.mk' dir in the project root, where mk keeps per-project files like the scratch
+// dirs handed to tasks. It ignores itself in git, so it never shows up as untracked.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sys;
+
+pub fn dir(project_root: &str) -> PathBuf {
+    PathBuf::from(project_root).join(".mk")
+}
+
+pub fn ensure_dir(project_root: &str) -> Result<PathBuf, String> {
+    let dir = dir(project_root);
+
+    fs::create_dir_all(&dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+
+    let gitignore = dir.join(".gitignore");
+    if !gitignore.exists() {
+        let _ = fs::write(&gitignore, "*\n");
+    }
+
+    Ok(dir)
+}
+
+// The scratch dir of this run in the system's temp dir, removed when mk exits.
+static TEMP_RUN_TMP: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+// The scratch dir of this run, '.mk/tmp/<run-id>'. It's left behind for inspection until a
+// later run prunes it, see prune_run_tmp(), or 'mk clean' removes it. In read-only mode
+// it's in the system's temp dir instead and removed when mk exits, see
+// remove_temp_run_tmp().
+pub fn run_tmp(project_root: &str) -> PathBuf {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let run_id = format!("{}-{}", now, std::process::id());
+
+    if crate::read_only() {
+        env::temp_dir().join(format!("mk-{}", run_id))
+    } else {
+        dir(project_root).join("tmp").join(run_id)
+    }
+}
+
+// Create the scratch dir of this run. Its name can be guessed, so one that exists already
+// may be someone else's and is an error.
+pub fn create_run_tmp(project_root: &str) -> Result<String, String> {
+    let tmp = run_tmp(project_root);
+    let error = |e: std::io::Error| format!("cannot create {}: {}", tmp.display(), e);
+    if !crate::read_only() {
+        ensure_dir(project_root)?;
+        fs::create_dir_all(dir(project_root).join("tmp")).map_err(error)?;
+    }
+    sys::create_private_dir(&tmp).map_err(error)?;
+    if crate::read_only() {
+        *TEMP_RUN_TMP.lock().unwrap_or_else(|e| e.into_inner()) = Some(tmp.clone());
+        sys::at_exit(remove_temp_run_tmp);
+    }
+
+    Ok(tmp.display().to_string())
+}
+
+extern "C" fn remove_temp_run_tmp() {
+    if let Some(tmp) = TEMP_RUN_TMP
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+    {
+        let _ = fs::remove_dir_all(tmp);
+    }
+}
+
+// Remove the scratch dirs of runs that are over, whose mk is gone (exec'ed into the task and
+// the task exited, too), except the newest one, so the last run can still be inspected.
+pub fn prune_run_tmp(project_root: &str) {
+    if crate::read_only() {
+        return;
+    }
+    let entries = match fs::read_dir(dir(project_root).join("tmp")) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let mut finished: Vec<(u64, PathBuf)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let (secs, pid) = name.split_once('-')?;
+            let (secs, pid) = (secs.parse().ok()?, pid.parse().ok()?);
+            match sys::running_since(pid, secs) {
+                Some(false) => Some((secs, entry.path())),
+                _ => None,
+            }
+        })
+        .collect();
+    finished.sort();
+    finished.pop();
+    for (_, path) in finished {
+        let _ = fs::remove_dir_all(path);
+    }
+}